@@ -0,0 +1,401 @@
+//! Audit trail of state mutations with pluggable sinks.
+//!
+//! [`AuditedStateStore`] wraps a store and emits one [`AuditRecord`] per `set_json` (whole
+//! document or path update), `del`, `del_prefix`, `del_prefix_recursive` and purge. Records
+//! carry the acting scope, the FQN, SHA-256 hashes of the document before and after the
+//! change, and a timestamp. Values themselves are never written to the audit trail.
+
+use crate::error::{from_serde, with_context};
+use crate::key::{StatePath, fqn, fqn_prefix, scope_members};
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::sha256_hex;
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+/// Mutation recorded by an [`AuditRecord`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    /// Whole-document `set_json`.
+    Set,
+    /// `set_json` with a JSON Pointer path.
+    SetPath,
+    /// Single-key `del`.
+    Del,
+    /// Bulk `del_prefix`.
+    DelPrefix,
+    /// Bulk `del_prefix_recursive`, covering sub-prefixes too.
+    DelPrefixRecursive,
+    /// `purge_tenant`, `purge_team` or `purge_user`.
+    Purge,
+}
+
+/// One audited mutation.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// When the mutation completed.
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    /// Kind of mutation.
+    pub operation: AuditOperation,
+    /// Environment of the acting tenant.
+    pub env: String,
+    /// Acting tenant.
+    pub tenant: String,
+    /// Acting team, when the call was team-scoped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// Acting user, when the call was user-scoped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// FQN of the document, the namespaced prefix for [`AuditOperation::DelPrefix`] and
+    /// [`AuditOperation::DelPrefixRecursive`], or the [`PurgeReport::scope`] for
+    /// [`AuditOperation::Purge`].
+    pub fqn: String,
+    /// JSON Pointer of a [`AuditOperation::SetPath`] update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Hash of the document before the mutation; `None` when it did not exist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_hash: Option<String>,
+    /// Hash of the document after the mutation; `None` when it no longer exists.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_hash: Option<String>,
+    /// Number of entries removed by [`AuditOperation::DelPrefix`] or [`AuditOperation::Purge`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<u64>,
+}
+
+/// Hex-encoded SHA-256 of a document's JSON serialization (object keys are sorted).
+pub fn value_hash(value: &Value) -> GResult<String> {
+    let bytes = serde_json::to_vec(value).map_err(from_serde)?;
+    Ok(sha256_hex(&bytes))
+}
+
+/// Destination for audit records.
+pub trait AuditSink: Send + Sync + 'static {
+    /// Persists or forwards `record`.
+    fn record(&self, record: &AuditRecord) -> GResult<()>;
+}
+
+/// Emits each record as an `info` event on the `greentic_state::audit` target.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, record: &AuditRecord) -> GResult<()> {
+        info!(
+            target: "greentic_state::audit",
+            operation = ?record.operation,
+            env = %record.env,
+            tenant = %record.tenant,
+            team = record.team.as_deref(),
+            user = record.user.as_deref(),
+            fqn = %record.fqn,
+            path = record.path.as_deref(),
+            old_hash = record.old_hash.as_deref(),
+            new_hash = record.new_hash.as_deref(),
+            deleted = record.deleted,
+            "state mutation"
+        );
+        Ok(())
+    }
+}
+
+/// Appends each record as one JSON line to a file, flushing after every write.
+pub struct JsonLinesAuditSink {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesAuditSink {
+    /// Opens `path` for appending, creating it when missing.
+    pub fn open(path: impl AsRef<Path>) -> GResult<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| with_context(err, format!("open audit log {}", path.display())))?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&self, record: &AuditRecord) -> GResult<()> {
+        let mut line = serde_json::to_vec(record).map_err(from_serde)?;
+        line.push(b'\n');
+        let mut writer = self.writer.lock();
+        writer
+            .write_all(&line)
+            .and_then(|()| writer.flush())
+            .map_err(|err| with_context(err, "write audit log"))
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_sink::RedisStreamAuditSink;
+
+#[cfg(feature = "redis")]
+mod redis_sink {
+    use super::{AuditRecord, AuditSink};
+    use crate::error::{from_redis, from_serde};
+    use greentic_types::GResult;
+    use parking_lot::Mutex;
+
+    /// Appends each record to a Redis stream (`XADD`) as a single `record` field holding JSON.
+    pub struct RedisStreamAuditSink {
+        client: redis::Client,
+        stream: String,
+        max_len: Option<usize>,
+        connection: Mutex<Option<redis::Connection>>,
+    }
+
+    impl RedisStreamAuditSink {
+        /// Writes to `stream`, keeping every entry.
+        pub fn new(client: redis::Client, stream: impl Into<String>) -> Self {
+            Self {
+                client,
+                stream: stream.into(),
+                max_len: None,
+                connection: Mutex::new(None),
+            }
+        }
+
+        /// Trims the stream to roughly `max_len` entries (`MAXLEN ~`) on every append.
+        pub fn with_max_len(mut self, max_len: usize) -> Self {
+            self.max_len = Some(max_len);
+            self
+        }
+    }
+
+    impl AuditSink for RedisStreamAuditSink {
+        fn record(&self, record: &AuditRecord) -> GResult<()> {
+            let payload = serde_json::to_string(record).map_err(from_serde)?;
+            let mut command = redis::cmd("XADD");
+            command.arg(&self.stream);
+            if let Some(max_len) = self.max_len {
+                command.arg("MAXLEN").arg("~").arg(max_len);
+            }
+            command.arg("*").arg("record").arg(payload);
+
+            let mut slot = self.connection.lock();
+            let connection = match slot.as_mut() {
+                Some(connection) => connection,
+                None => slot.insert(
+                    self.client
+                        .get_connection()
+                        .map_err(|err| from_redis(err, "connect redis"))?,
+                ),
+            };
+            let result = command.query::<String>(connection);
+            if result.is_err() {
+                // Reconnect on the next record rather than reusing a possibly broken connection.
+                *slot = None;
+            }
+            result
+                .map(drop)
+                .map_err(|err| from_redis(err, "append audit record"))
+        }
+    }
+}
+
+/// [`StateStore`] wrapper that reports every mutation to an [`AuditSink`].
+///
+/// The previous document is read before each mutation so its hash can be recorded, and path
+/// updates re-read the document afterwards. Records are emitted only for successful mutations,
+/// after they are applied; a failing sink is logged and does not fail the write.
+pub struct AuditedStateStore<S> {
+    inner: S,
+    sink: Arc<dyn AuditSink>,
+}
+
+impl<S: StateStore> AuditedStateStore<S> {
+    /// Wraps `inner`, sending records to `sink`.
+    pub fn new(inner: S, sink: Arc<dyn AuditSink>) -> Self {
+        Self { inner, sink }
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn current_hash(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+    ) -> GResult<Option<String>> {
+        self.inner
+            .get_json(tenant, prefix, key, None)?
+            .as_ref()
+            .map(value_hash)
+            .transpose()
+    }
+
+    fn emit(&self, tenant: &TenantCtx, operation: AuditOperation, fqn: String) -> AuditRecord {
+        let (team, user) = scope_members(tenant);
+        AuditRecord {
+            timestamp: OffsetDateTime::now_utc(),
+            operation,
+            env: tenant.env.as_str().to_owned(),
+            tenant: tenant.tenant_id.as_str().to_owned(),
+            team: team.map(str::to_owned),
+            user: user.map(str::to_owned),
+            fqn,
+            path: None,
+            old_hash: None,
+            new_hash: None,
+            deleted: None,
+        }
+    }
+
+    fn publish_purge(&self, tenant: &TenantCtx, report: &PurgeReport) {
+        let mut record = self.emit(tenant, AuditOperation::Purge, report.scope.clone());
+        record.timestamp = report.completed_at;
+        record.deleted = Some(report.deleted);
+        self.publish(record);
+    }
+
+    fn publish(&self, record: AuditRecord) {
+        if let Err(err) = self.sink.record(&record) {
+            warn!(error = %err, fqn = %record.fqn, "failed to record state audit entry");
+        }
+    }
+}
+
+impl<S: StateStore> StateStore for AuditedStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        self.inner.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let old_hash = self.current_hash(tenant, prefix, key)?;
+        self.inner
+            .set_json(tenant, prefix, key, path, value, ttl_secs)?;
+
+        let (operation, new_hash) = match path {
+            Some(path) if !path.segments.is_empty() => (
+                AuditOperation::SetPath,
+                self.current_hash(tenant, prefix, key)?,
+            ),
+            _ => (AuditOperation::Set, Some(value_hash(value)?)),
+        };
+        let mut record = self.emit(tenant, operation, fqn(tenant, prefix, key).0);
+        if operation == AuditOperation::SetPath {
+            record.path = path.map(StatePath::to_pointer);
+        }
+        record.old_hash = old_hash;
+        record.new_hash = new_hash;
+        self.publish(record);
+        Ok(())
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let written = self
+            .inner
+            .set_if_absent(tenant, prefix, key, value, ttl_secs)?;
+        if written {
+            let mut record = self.emit(tenant, AuditOperation::Set, fqn(tenant, prefix, key).0);
+            record.new_hash = Some(value_hash(value)?);
+            self.publish(record);
+        }
+        Ok(written)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let old_hash = self.current_hash(tenant, prefix, key)?;
+        let deleted = self.inner.del(tenant, prefix, key)?;
+        if deleted {
+            let mut record = self.emit(tenant, AuditOperation::Del, fqn(tenant, prefix, key).0);
+            record.old_hash = old_hash;
+            self.publish(record);
+        }
+        Ok(deleted)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let deleted = self.inner.del_prefix(tenant, prefix)?;
+        let mut record = self.emit(
+            tenant,
+            AuditOperation::DelPrefix,
+            fqn_prefix(tenant, prefix),
+        );
+        record.deleted = Some(deleted);
+        self.publish(record);
+        Ok(deleted)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let deleted = self.inner.del_prefix_recursive(tenant, prefix)?;
+        let mut record = self.emit(
+            tenant,
+            AuditOperation::DelPrefixRecursive,
+            fqn_prefix(tenant, prefix),
+        );
+        record.deleted = Some(deleted);
+        self.publish(record);
+        Ok(deleted)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.inner.prefix_stats(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.inner.list_keys(tenant, prefix)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.inner.ttl(tenant, prefix, key)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let report = self.inner.purge_tenant(tenant)?;
+        self.publish_purge(tenant, &report);
+        Ok(report)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let report = self.inner.purge_team(tenant, team)?;
+        self.publish_purge(tenant, &report);
+        Ok(report)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let report = self.inner.purge_user(tenant, user)?;
+        self.publish_purge(tenant, &report);
+        Ok(report)
+    }
+}
@@ -0,0 +1,94 @@
+//! File-backed store: an in-memory store loaded from, and saved back to, an NDJSON snapshot.
+
+use greentic_state::error::with_context;
+use greentic_state::inmemory::InMemoryStateStore;
+use greentic_state::snapshot::{self, ConflictPolicy};
+use greentic_state::{StateStore, TenantCtx};
+use greentic_types::GResult;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+type ScopeId = (String, String, Option<String>, Option<String>);
+
+pub struct FileStore {
+    path: PathBuf,
+    store: InMemoryStateStore,
+    scopes: BTreeMap<ScopeId, TenantCtx>,
+}
+
+impl FileStore {
+    /// Loads `path`, treating a missing file as an empty store.
+    ///
+    /// Snapshot TTLs are relative to when the file was written, so they are shortened by the
+    /// file's age and entries that expired in the meantime are dropped.
+    pub fn open(path: &Path) -> GResult<Self> {
+        let mut file_store = Self {
+            path: path.to_path_buf(),
+            store: InMemoryStateStore::new(),
+            scopes: BTreeMap::new(),
+        };
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(file_store),
+            Err(err) => return Err(with_context(err, format!("open {}", path.display()))),
+        };
+        let age = file
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age.as_secs())
+            .unwrap_or(0);
+
+        let mut records = Vec::new();
+        for record in snapshot::read_ndjson(BufReader::new(file)) {
+            let mut record = record?;
+            if let Some(ttl) = record.ttl_secs {
+                match ttl.checked_sub(age).filter(|left| *left > 0) {
+                    Some(left) => record.ttl_secs = Some(left),
+                    None => continue,
+                }
+            }
+            file_store.track(&record.tenant_ctx()?);
+            records.push(Ok(record));
+        }
+        snapshot::import(&file_store.store, records, ConflictPolicy::Overwrite)?;
+        Ok(file_store)
+    }
+
+    /// Registers a tenant scope so its entries are included by [`FileStore::save`].
+    pub fn track(&mut self, tenant: &TenantCtx) {
+        let team = tenant.team_id.as_ref().or(tenant.team.as_ref());
+        let user = tenant.user_id.as_ref().or(tenant.user.as_ref());
+        let id = (
+            tenant.env.as_str().to_owned(),
+            tenant.tenant_id.as_str().to_owned(),
+            team.map(|team| team.as_str().to_owned()),
+            user.map(|user| user.as_str().to_owned()),
+        );
+        self.scopes.entry(id).or_insert_with(|| tenant.clone());
+    }
+
+    pub fn store(&self) -> &dyn StateStore {
+        &self.store
+    }
+
+    /// Rewrites the file with every tracked scope, replacing it atomically.
+    pub fn save(&self) -> GResult<()> {
+        let staging = self.path.with_extension("tmp");
+        let file = File::create(&staging)
+            .map_err(|err| with_context(err, format!("create {}", staging.display())))?;
+        let mut writer = BufWriter::new(file);
+        for tenant in self.scopes.values() {
+            snapshot::write_ndjson(&mut writer, snapshot::export(&self.store, tenant, None)?)?;
+        }
+        writer
+            .flush()
+            .map_err(|err| with_context(err, format!("write {}", staging.display())))?;
+        fs::rename(&staging, &self.path)
+            .map_err(|err| with_context(err, format!("replace {}", self.path.display())))
+    }
+}
@@ -0,0 +1,523 @@
+mod file_store;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use file_store::FileStore;
+use greentic_state::error::{invalid_input, with_context};
+use greentic_state::migrate::{self, MigrationOptions};
+use greentic_state::redis_store::RedisStateStore;
+use greentic_state::snapshot::{self, ConflictPolicy};
+use greentic_state::{StateKey, StatePath, StateStore, StateTtl, TenantCtx};
+use greentic_types::{EnvId, GResult, TeamId, TenantId, UserId};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Command-line tooling for Greentic state stores.
+#[derive(Parser)]
+#[command(name = "greentic-state", version, about)]
+struct Cli {
+    /// Print compact JSON instead of pretty-printed output.
+    #[arg(long, global = true)]
+    raw: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a document, or the value at a JSON Pointer path.
+    Get {
+        #[command(flatten)]
+        target: Target,
+        prefix: String,
+        key: String,
+        /// JSON Pointer inside the document.
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Write a JSON value (use `-` to read it from stdin).
+    Set {
+        #[command(flatten)]
+        target: Target,
+        prefix: String,
+        key: String,
+        value: String,
+        /// JSON Pointer inside the document.
+        #[arg(long)]
+        path: Option<String>,
+        /// TTL in seconds; `0` clears it, omitting it keeps the current one.
+        #[arg(long)]
+        ttl: Option<u32>,
+    },
+    /// Delete one key.
+    Del {
+        #[command(flatten)]
+        target: Target,
+        prefix: String,
+        key: String,
+    },
+    /// Delete every key under a prefix.
+    DelPrefix {
+        #[command(flatten)]
+        target: Target,
+        prefix: String,
+    },
+    /// List keys, optionally under one prefix.
+    Ls {
+        #[command(flatten)]
+        target: Target,
+        prefix: Option<String>,
+    },
+    /// Show the remaining TTL of a key.
+    Ttl {
+        #[command(flatten)]
+        target: Target,
+        prefix: String,
+        key: String,
+    },
+    /// Delete everything a tenant, team (`--team`) or user (`--user`) stored, across all
+    /// prefixes, and print a deletion report.
+    Purge {
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Write a tenant's entries as NDJSON.
+    Export {
+        #[command(flatten)]
+        target: Target,
+        /// Only export entries under this prefix.
+        #[arg(long)]
+        prefix: Option<String>,
+        /// Output file; defaults to stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Load NDJSON records produced by `export`.
+    Import {
+        /// Redis URL or snapshot file to import into.
+        #[arg(long)]
+        store: String,
+        /// Input file; defaults to stdin.
+        #[arg(long, short)]
+        input: Option<PathBuf>,
+        /// What to do with keys that already exist.
+        #[arg(long, value_enum, default_value_t = OnConflict::Overwrite)]
+        on_conflict: OnConflict,
+    },
+    /// Copy a tenant's state from one backend to another.
+    Migrate(MigrateArgs),
+    /// Serve a Redis-backed store over HTTP.
+    #[cfg(feature = "server")]
+    Serve {
+        /// Redis URL of the store to expose.
+        #[arg(long)]
+        store: String,
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+}
+
+/// Store location plus the tenant scope commands operate on.
+#[derive(Args)]
+struct Target {
+    /// Redis URL (`redis://`, `rediss://`) or path to an NDJSON snapshot file.
+    #[arg(long)]
+    store: String,
+    #[command(flatten)]
+    tenant: TenantArgs,
+}
+
+#[derive(Args)]
+struct TenantArgs {
+    /// Environment id.
+    #[arg(long)]
+    env: String,
+    /// Tenant id.
+    #[arg(long)]
+    tenant: String,
+    /// Optional team id narrowing the scope.
+    #[arg(long)]
+    team: Option<String>,
+    /// Optional user id narrowing the scope.
+    #[arg(long)]
+    user: Option<String>,
+}
+
+impl TenantArgs {
+    fn to_ctx(&self) -> GResult<TenantCtx> {
+        let env = EnvId::try_from(self.env.as_str())
+            .map_err(|err| invalid_input(format!("--env: {err}")))?;
+        let tenant = TenantId::try_from(self.tenant.as_str())
+            .map_err(|err| invalid_input(format!("--tenant: {err}")))?;
+        let team = self
+            .team
+            .as_deref()
+            .map(TeamId::try_from)
+            .transpose()
+            .map_err(|err| invalid_input(format!("--team: {err}")))?;
+        let user = self
+            .user
+            .as_deref()
+            .map(UserId::try_from)
+            .transpose()
+            .map_err(|err| invalid_input(format!("--user: {err}")))?;
+        Ok(TenantCtx::new(env, tenant).with_team(team).with_user(user))
+    }
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    /// Source Redis URL or snapshot file.
+    #[arg(long)]
+    from: String,
+    /// Target Redis URL or snapshot file.
+    #[arg(long)]
+    to: String,
+    #[command(flatten)]
+    tenant: TenantArgs,
+    /// Only copy entries under this prefix.
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Report what would be copied without writing.
+    #[arg(long)]
+    dry_run: bool,
+    /// Checkpoint file used to resume an interrupted run.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Skip comparing source and target values after copying.
+    #[arg(long)]
+    no_verify: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OnConflict {
+    Overwrite,
+    Skip,
+    Fail,
+}
+
+impl From<OnConflict> for ConflictPolicy {
+    fn from(value: OnConflict) -> Self {
+        match value {
+            OnConflict::Overwrite => ConflictPolicy::Overwrite,
+            OnConflict::Skip => ConflictPolicy::Skip,
+            OnConflict::Fail => ConflictPolicy::Fail,
+        }
+    }
+}
+
+/// An opened backend; file stores are written back after mutating commands.
+enum Backend {
+    Redis(Box<RedisStateStore>),
+    File(FileStore),
+}
+
+impl Backend {
+    fn open(location: &str) -> GResult<Self> {
+        let scheme = location.split_once("://").map(|(scheme, _)| scheme);
+        match scheme {
+            Some("redis" | "rediss" | "redis+unix" | "unix") => {
+                Ok(Self::Redis(Box::new(RedisStateStore::from_url(location)?)))
+            }
+            Some(other) => Err(invalid_input(format!(
+                "unsupported store scheme `{other}`; use a Redis URL or a file path"
+            ))),
+            None => Ok(Self::File(FileStore::open(Path::new(location))?)),
+        }
+    }
+
+    fn open_for(location: &str, tenant: &TenantCtx) -> GResult<Self> {
+        let mut backend = Self::open(location)?;
+        if let Self::File(file) = &mut backend {
+            file.track(tenant);
+        }
+        Ok(backend)
+    }
+
+    fn store(&self) -> &dyn StateStore {
+        match self {
+            Self::Redis(store) => store.as_ref(),
+            Self::File(file) => file.store(),
+        }
+    }
+
+    fn persist(&self) -> GResult<()> {
+        match self {
+            Self::Redis(_) => Ok(()),
+            Self::File(file) => file.save(),
+        }
+    }
+}
+
+struct Output {
+    raw: bool,
+}
+
+impl Output {
+    fn print(&self, value: &impl Serialize) -> GResult<()> {
+        let rendered = if self.raw {
+            serde_json::to_string(value)
+        } else {
+            serde_json::to_string_pretty(value)
+        }
+        .map_err(|err| with_context(err, "render output"))?;
+        println!("{rendered}");
+        Ok(())
+    }
+}
+
+fn parse_path(path: Option<String>) -> Option<StatePath> {
+    path.map(|pointer| StatePath::from_pointer(&pointer))
+}
+
+fn parse_value(raw: &str) -> GResult<Value> {
+    let text = if raw == "-" {
+        let mut buffer = String::new();
+        io::stdin()
+            .read_to_string(&mut buffer)
+            .map_err(|err| with_context(err, "read value from stdin"))?;
+        buffer
+    } else {
+        raw.to_owned()
+    };
+    serde_json::from_str(&text).map_err(|err| invalid_input(format!("value is not JSON: {err}")))
+}
+
+fn not_found(prefix: &str, key: &str) -> ExitCode {
+    eprintln!("not found: {prefix} {key}");
+    ExitCode::FAILURE
+}
+
+fn run(command: Command, out: &Output) -> GResult<ExitCode> {
+    match command {
+        Command::Get {
+            target,
+            prefix,
+            key,
+            path,
+        } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open(&target.store)?;
+            let path = parse_path(path);
+            let value = backend.store().get_json(
+                &tenant,
+                &prefix,
+                &StateKey::new(key.as_str()),
+                path.as_ref(),
+            )?;
+            match value {
+                Some(value) => out.print(&value)?,
+                None => return Ok(not_found(&prefix, &key)),
+            }
+        }
+        Command::Set {
+            target,
+            prefix,
+            key,
+            value,
+            path,
+            ttl,
+        } => {
+            let tenant = target.tenant.to_ctx()?;
+            let value = parse_value(&value)?;
+            let backend = Backend::open_for(&target.store, &tenant)?;
+            let path = parse_path(path);
+            backend.store().set_json(
+                &tenant,
+                &prefix,
+                &StateKey::new(key.as_str()),
+                path.as_ref(),
+                &value,
+                ttl,
+            )?;
+            backend.persist()?;
+        }
+        Command::Del {
+            target,
+            prefix,
+            key,
+        } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open_for(&target.store, &tenant)?;
+            let deleted = backend
+                .store()
+                .del(&tenant, &prefix, &StateKey::new(key.as_str()))?;
+            backend.persist()?;
+            out.print(&json!({ "deleted": deleted }))?;
+        }
+        Command::DelPrefix { target, prefix } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open_for(&target.store, &tenant)?;
+            let deleted = backend.store().del_prefix(&tenant, &prefix)?;
+            backend.persist()?;
+            out.print(&json!({ "deleted": deleted }))?;
+        }
+        Command::Purge { target } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open(&target.store)?;
+            let store = backend.store();
+            let report = match (&tenant.team_id, &tenant.user_id) {
+                (None, None) => store.purge_tenant(&tenant)?,
+                (Some(team), None) => store.purge_team(&tenant, team)?,
+                (None, Some(user)) => store.purge_user(&tenant, user)?,
+                (Some(_), Some(_)) => {
+                    return Err(invalid_input(
+                        "purge takes either --team or --user, not both",
+                    ));
+                }
+            };
+            backend.persist()?;
+            out.print(&report)?;
+        }
+        Command::Ls { target, prefix } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open(&target.store)?;
+            let keys: Vec<Value> = backend
+                .store()
+                .list_keys(&tenant, prefix.as_deref())?
+                .into_iter()
+                .map(|entry| json!({ "prefix": entry.prefix, "key": entry.key.as_str() }))
+                .collect();
+            out.print(&keys)?;
+        }
+        Command::Ttl {
+            target,
+            prefix,
+            key,
+        } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open(&target.store)?;
+            let ttl = backend
+                .store()
+                .ttl(&tenant, &prefix, &StateKey::new(key.as_str()))?;
+            let ttl_ms = match ttl {
+                None => return Ok(not_found(&prefix, &key)),
+                Some(StateTtl::Persistent) => None,
+                Some(StateTtl::ExpiresIn(remaining)) => Some(remaining.as_millis()),
+            };
+            out.print(&json!({ "ttl_ms": ttl_ms }))?;
+        }
+        Command::Export {
+            target,
+            prefix,
+            output,
+        } => {
+            let tenant = target.tenant.to_ctx()?;
+            let backend = Backend::open(&target.store)?;
+            let records = snapshot::export(backend.store(), &tenant, prefix.as_deref())?;
+            let written = match output {
+                Some(path) => {
+                    let file = File::create(&path)
+                        .map_err(|err| with_context(err, format!("create {}", path.display())))?;
+                    let mut writer = BufWriter::new(file);
+                    let written = snapshot::write_ndjson(&mut writer, records)?;
+                    writer
+                        .flush()
+                        .map_err(|err| with_context(err, format!("write {}", path.display())))?;
+                    written
+                }
+                None => snapshot::write_ndjson(io::stdout().lock(), records)?,
+            };
+            eprintln!("exported {written} entries");
+        }
+        Command::Import {
+            store,
+            input,
+            on_conflict,
+        } => {
+            let records: Vec<_> = match input {
+                Some(path) => {
+                    let file = File::open(&path)
+                        .map_err(|err| with_context(err, format!("open {}", path.display())))?;
+                    snapshot::read_ndjson(BufReader::new(file)).collect::<GResult<_>>()?
+                }
+                None => snapshot::read_ndjson(io::stdin().lock()).collect::<GResult<_>>()?,
+            };
+            let mut backend = Backend::open(&store)?;
+            if let Backend::File(file) = &mut backend {
+                for record in &records {
+                    file.track(&record.tenant_ctx()?);
+                }
+            }
+            let report = snapshot::import(
+                backend.store(),
+                records.into_iter().map(Ok),
+                on_conflict.into(),
+            )?;
+            backend.persist()?;
+            out.print(&report)?;
+        }
+        Command::Migrate(args) => return run_migrate(args, out),
+        #[cfg(feature = "server")]
+        Command::Serve { store, listen } => run_serve(&store, &listen)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_migrate(args: MigrateArgs, out: &Output) -> GResult<ExitCode> {
+    let tenant = args.tenant.to_ctx()?;
+    let source = Backend::open(&args.from)?;
+    let target = Backend::open_for(&args.to, &tenant)?;
+    let options = MigrationOptions {
+        prefix: args.prefix,
+        dry_run: args.dry_run,
+        verify: !args.no_verify,
+        checkpoint: args.checkpoint,
+        source: Some(source_label(&args.from)),
+        ..MigrationOptions::default()
+    };
+    let report = migrate::migrate(source.store(), target.store(), &tenant, &options)?;
+    if !options.dry_run {
+        target.persist()?;
+    }
+    out.print(&report)?;
+    Ok(if report.is_consistent() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// `location` without credentials or query parameters, to tell migration sources apart.
+fn source_label(location: &str) -> String {
+    let Some((scheme, rest)) = location.split_once("://") else {
+        return location.to_owned();
+    };
+    let rest = rest.split('?').next().unwrap_or_default();
+    let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    format!("{scheme}://{host}{path}")
+}
+
+#[cfg(feature = "server")]
+fn run_serve(location: &str, listen: &str) -> GResult<()> {
+    let Backend::Redis(store) = Backend::open(location)? else {
+        return Err(invalid_input("`serve` requires a Redis URL"));
+    };
+    let runtime =
+        tokio::runtime::Runtime::new().map_err(|err| with_context(err, "start runtime"))?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(listen)
+            .await
+            .map_err(|err| with_context(err, format!("bind {listen}")))?;
+        eprintln!("serving state on http://{listen}");
+        greentic_state::http::serve(listener, std::sync::Arc::new(*store))
+            .await
+            .map_err(|err| with_context(err, "serve"))
+    })
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let out = Output { raw: cli.raw };
+    run(cli.command, &out).unwrap_or_else(|err| {
+        eprintln!("error: {err}");
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,615 @@
+//! Tiered store that keeps hot documents in a local [`InMemoryStateStore`] in front of a
+//! remote [`StateStore`] such as Redis.
+//!
+//! Besides [`CachedStateStore::stats`], cache activity is reported through the [`metrics`]
+//! facade, next to the [`instrument`](crate::instrument) metrics:
+//!
+//! | Metric | Kind | Labels |
+//! |---|---|---|
+//! | [`CACHE_READS_TOTAL`] | counter | `result` (`hit` or `miss`) |
+//! | [`CACHE_INVALIDATIONS_TOTAL`] | counter | `kind` (`key`, `prefix` or `all`) |
+
+use crate::clock::Clock;
+use crate::error::from_serde;
+use crate::inmemory::InMemoryStateStore;
+use crate::key::{FqnKey, StatePath, fqn, fqn_prefix, fqn_subprefix_base};
+use crate::limits::StateLimits;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::get_at_path;
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::warn;
+
+/// Counter of cached reads split into local hits and remote misses.
+pub const CACHE_READS_TOTAL: &str = "greentic_state_cache_reads_total";
+/// Counter of remote invalidations applied to a local cache.
+pub const CACHE_INVALIDATIONS_TOTAL: &str = "greentic_state_cache_invalidations_total";
+
+/// Settings for [`CachedStateStore`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// Upper bound, in seconds, on how long a document stays cached locally; `0` disables the
+    /// local copy, so every read goes to the remote store.
+    pub entry_ttl_secs: u32,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { entry_ttl_secs: 30 }
+    }
+}
+
+/// Change notification exchanged between cache instances.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Invalidation {
+    /// A single document changed.
+    Key {
+        /// FQN of the changed document.
+        fqn: String,
+    },
+    /// Every document under the namespaced prefix (see [`fqn_prefix`]) changed.
+    Prefix {
+        /// Namespaced prefix of the removed documents.
+        prefix: String,
+    },
+    /// Drop everything, e.g. after missing notifications while disconnected.
+    All,
+}
+
+/// Envelope published by [`CachedStateStore`] so a node can skip its own notifications.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InvalidationMessage {
+    /// Identifier of the cache instance that performed the write.
+    pub origin: String,
+    /// What changed.
+    #[serde(flatten)]
+    pub invalidation: Invalidation,
+}
+
+impl InvalidationMessage {
+    /// Encodes the message as JSON for transport.
+    pub fn encode(&self) -> GResult<String> {
+        serde_json::to_string(self).map_err(from_serde)
+    }
+
+    /// Decodes a message produced by [`InvalidationMessage::encode`].
+    pub fn decode(payload: &str) -> GResult<Self> {
+        serde_json::from_str(payload).map_err(from_serde)
+    }
+}
+
+/// Broadcasts local writes to other cache instances.
+pub trait CacheInvalidator: Send + Sync + 'static {
+    /// Publishes `message` to every subscribed cache.
+    fn publish(&self, message: &InvalidationMessage) -> GResult<()>;
+}
+
+/// Counters describing cache effectiveness.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads served from the local cache.
+    pub hits: u64,
+    /// Reads that had to go to the remote store.
+    pub misses: u64,
+    /// Remote invalidations applied to the local cache.
+    pub invalidations: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// Handle used by invalidation listeners to evict entries from a [`CachedStateStore`].
+#[derive(Clone)]
+pub struct CacheInvalidationTarget {
+    origin: Arc<str>,
+    local: InMemoryStateStore,
+    counters: Arc<Counters>,
+}
+
+impl CacheInvalidationTarget {
+    /// Applies a message received from the transport; messages from this cache are ignored.
+    pub fn apply_message(&self, message: &InvalidationMessage) {
+        if message.origin.as_str() != &*self.origin {
+            self.apply(&message.invalidation);
+        }
+    }
+
+    /// Evicts the entries described by `invalidation`.
+    pub fn apply(&self, invalidation: &Invalidation) {
+        let kind = match invalidation {
+            Invalidation::Key { fqn } => {
+                self.local.remove_fqn(fqn);
+                "key"
+            }
+            Invalidation::Prefix { prefix } => {
+                self.local.remove_fqn_prefix(prefix);
+                "prefix"
+            }
+            Invalidation::All => {
+                self.local.clear();
+                "all"
+            }
+        };
+        self.counters.invalidations.fetch_add(1, Ordering::Relaxed);
+        metrics::counter!(CACHE_INVALIDATIONS_TOTAL, "kind" => kind).increment(1);
+    }
+}
+
+/// Write-through cache combining a local [`InMemoryStateStore`] with a remote [`StateStore`].
+///
+/// Reads are served locally when the whole document is cached and fetched from the remote
+/// store otherwise. Writes go to the remote store first; whole-document writes then refresh the
+/// local copy while path writes and deletes evict it. Cached entries live at most
+/// [`CacheConfig::entry_ttl_secs`] (or the write's own TTL when shorter), which bounds staleness
+/// when invalidations are missed.
+pub struct CachedStateStore<R> {
+    remote: R,
+    config: CacheConfig,
+    target: CacheInvalidationTarget,
+    invalidator: Option<Arc<dyn CacheInvalidator>>,
+}
+
+impl<R: StateStore> CachedStateStore<R> {
+    /// Wraps `remote` with an empty local cache.
+    pub fn new(remote: R, config: CacheConfig) -> Self {
+        Self {
+            remote,
+            config,
+            target: CacheInvalidationTarget {
+                origin: next_origin().into(),
+                // The remote store already enforced its limits on everything that gets cached.
+                local: InMemoryStateStore::new().with_limits(StateLimits::unlimited()),
+                counters: Arc::new(Counters::default()),
+            },
+            invalidator: None,
+        }
+    }
+
+    /// Publishes every local write through `invalidator` so other nodes evict their copies.
+    pub fn with_invalidator(mut self, invalidator: Arc<dyn CacheInvalidator>) -> Self {
+        self.invalidator = Some(invalidator);
+        self
+    }
+
+    /// Replaces the clock that expires local copies. Call it before handing out
+    /// [`invalidation_target`](Self::invalidation_target) handles.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.target.local = self.target.local.clone().with_clock(clock);
+        self
+    }
+
+    /// Returns a handle that invalidation listeners use to evict entries from this cache.
+    pub fn invalidation_target(&self) -> CacheInvalidationTarget {
+        self.target.clone()
+    }
+
+    /// Returns the wrapped remote store.
+    pub fn remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Returns a snapshot of the hit/miss counters.
+    pub fn stats(&self) -> CacheStats {
+        let counters = &self.target.counters;
+        CacheStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            invalidations: counters.invalidations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Local lifetime of a document just written with `ttl_secs`; `None` when caching is
+    /// disabled, since a local TTL of 0 would never expire.
+    fn cache_ttl(&self, ttl_secs: Option<u32>) -> Option<u32> {
+        let ttl = match ttl_secs {
+            Some(ttl) if ttl > 0 => ttl.min(self.config.entry_ttl_secs),
+            _ => self.config.entry_ttl_secs,
+        };
+        Some(ttl).filter(|ttl| *ttl > 0)
+    }
+
+    /// Local lifetime of a document just read from the remote store, capped at its remaining
+    /// remote lifetime; `None` when it expires within the second or caching is disabled.
+    fn read_through_ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> Option<u32> {
+        if self.config.entry_ttl_secs == 0 {
+            return None;
+        }
+        match self.remote.ttl(tenant, prefix, key) {
+            Ok(Some(StateTtl::ExpiresIn(remaining))) => u32::try_from(remaining.as_secs())
+                .ok()
+                .filter(|secs| *secs > 0)
+                .map(|secs| secs.min(self.config.entry_ttl_secs)),
+            Ok(None) => None,
+            // Backends without `ttl` fall back to the entry TTL bound.
+            Ok(Some(StateTtl::Persistent)) | Err(_) => Some(self.config.entry_ttl_secs),
+        }
+    }
+
+    /// Drops every cached entry here and on peers, after a purge spanning many prefixes.
+    fn invalidate_all(&self) {
+        self.target.local.clear();
+        self.publish(Invalidation::All);
+    }
+
+    fn publish(&self, invalidation: Invalidation) {
+        let Some(invalidator) = self.invalidator.as_ref() else {
+            return;
+        };
+        let message = InvalidationMessage {
+            origin: self.target.origin.to_string(),
+            invalidation,
+        };
+        // The write already succeeded remotely; peers fall back to their entry TTL.
+        if let Err(err) = invalidator.publish(&message) {
+            warn!(error = %err, "failed to publish cache invalidation");
+        }
+    }
+}
+
+impl<R: StateStore> StateStore for CachedStateStore<R> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        let local = &self.target.local;
+        let counters = &self.target.counters;
+        let document = match local.get_json(tenant, prefix, key, None)? {
+            Some(document) => {
+                counters.hits.fetch_add(1, Ordering::Relaxed);
+                metrics::counter!(CACHE_READS_TOTAL, "result" => "hit").increment(1);
+                document
+            }
+            None => {
+                counters.misses.fetch_add(1, Ordering::Relaxed);
+                metrics::counter!(CACHE_READS_TOTAL, "result" => "miss").increment(1);
+                let Some(document) = self.remote.get_json(tenant, prefix, key, None)? else {
+                    return Ok(None);
+                };
+                if let Some(ttl) = self.read_through_ttl(tenant, prefix, key) {
+                    local.set_json(tenant, prefix, key, None, &document, Some(ttl))?;
+                }
+                document
+            }
+        };
+
+        match path {
+            Some(path) => Ok(get_at_path(&document, path).cloned()),
+            None => Ok(Some(document)),
+        }
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.remote
+            .set_json(tenant, prefix, key, path, value, ttl_secs)?;
+
+        let fqn_key = fqn(tenant, prefix, key);
+        let local = &self.target.local;
+        // Without a TTL the remote keeps its current expiry, which the next read looks up.
+        let local_ttl = match (path, ttl_secs) {
+            (None, Some(_)) => self.cache_ttl(ttl_secs),
+            _ => None,
+        };
+        if let Some(ttl) = local_ttl {
+            local.set_json(tenant, prefix, key, None, value, Some(ttl))?;
+        } else {
+            local.remove_fqn(fqn_key.as_str());
+        }
+        self.publish(key_invalidation(fqn_key));
+        Ok(())
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let written = self
+            .remote
+            .set_if_absent(tenant, prefix, key, value, ttl_secs)?;
+        if written {
+            let fqn_key = fqn(tenant, prefix, key);
+            self.target.local.remove_fqn(fqn_key.as_str());
+            self.publish(key_invalidation(fqn_key));
+        }
+        Ok(written)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let removed = self.remote.del(tenant, prefix, key)?;
+        let fqn_key = fqn(tenant, prefix, key);
+        self.target.local.remove_fqn(fqn_key.as_str());
+        self.publish(key_invalidation(fqn_key));
+        Ok(removed)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let removed = self.remote.del_prefix(tenant, prefix)?;
+        let pattern = fqn_prefix(tenant, prefix);
+        self.target.local.remove_fqn_prefix(&pattern);
+        self.publish(Invalidation::Prefix { prefix: pattern });
+        Ok(removed)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let removed = self.remote.del_prefix_recursive(tenant, prefix)?;
+        for pattern in [
+            fqn_prefix(tenant, prefix),
+            fqn_subprefix_base(tenant, prefix),
+        ] {
+            self.target.local.remove_fqn_prefix(&pattern);
+            self.publish(Invalidation::Prefix { prefix: pattern });
+        }
+        Ok(removed)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.remote.prefix_stats(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.remote.list_keys(tenant, prefix)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.remote.ttl(tenant, prefix, key)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let report = self.remote.purge_tenant(tenant)?;
+        self.invalidate_all();
+        Ok(report)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let report = self.remote.purge_team(tenant, team)?;
+        self.invalidate_all();
+        Ok(report)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let report = self.remote.purge_user(tenant, user)?;
+        self.invalidate_all();
+        Ok(report)
+    }
+}
+
+fn key_invalidation(fqn: FqnKey) -> Invalidation {
+    Invalidation::Key { fqn: fqn.0 }
+}
+
+fn next_origin() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = time::OffsetDateTime::now_utc().unix_timestamp_nanos();
+    format!(
+        "{}-{nanos:x}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+#[cfg(feature = "redis")]
+pub use self::redis_bus::{InvalidationListener, RedisInvalidationBus};
+
+#[cfg(feature = "redis")]
+mod redis_bus {
+    use super::{CacheInvalidationTarget, CacheInvalidator, Invalidation, InvalidationMessage};
+    use crate::error::{from_redis, internal};
+    use greentic_types::GResult;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+    use tracing::{debug, warn};
+
+    const POLL_INTERVAL: Duration = Duration::from_millis(250);
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+    /// Redis pub/sub transport for cache invalidations.
+    ///
+    /// Use [`RedisInvalidationBus::subscribe`] together with
+    /// [`super::CachedStateStore::with_invalidator`] to exchange explicit messages on a channel,
+    /// or [`RedisInvalidationBus::subscribe_keyspace`] to rely on Redis keyspace notifications
+    /// (requires `notify-keyspace-events` to include `K` plus the relevant event classes).
+    ///
+    /// Publishing reuses one connection, opened on first use and reopened after an error.
+    #[derive(Clone)]
+    pub struct RedisInvalidationBus {
+        client: redis::Client,
+        channel: String,
+        connection: Arc<Mutex<Option<redis::Connection>>>,
+    }
+
+    /// Which notifications a listener subscribes to.
+    enum Source {
+        Channel(String),
+        Keyspace { db: i64 },
+    }
+
+    impl RedisInvalidationBus {
+        /// Creates a bus publishing on `channel`.
+        pub fn new(client: redis::Client, channel: impl Into<String>) -> Self {
+            Self {
+                client,
+                channel: channel.into(),
+                connection: Arc::new(Mutex::new(None)),
+            }
+        }
+
+        /// Spawns a listener applying messages from the bus channel to `target`.
+        pub fn subscribe(&self, target: CacheInvalidationTarget) -> GResult<InvalidationListener> {
+            InvalidationListener::spawn(
+                self.client.clone(),
+                Source::Channel(self.channel.clone()),
+                target,
+            )
+        }
+
+        /// Spawns a listener evicting keys reported by keyspace notifications on database `db`.
+        ///
+        /// Notifications also fire for this node's own writes, so freshly written entries are
+        /// re-read from Redis once.
+        pub fn subscribe_keyspace(
+            client: redis::Client,
+            db: i64,
+            target: CacheInvalidationTarget,
+        ) -> GResult<InvalidationListener> {
+            InvalidationListener::spawn(client, Source::Keyspace { db }, target)
+        }
+
+        /// Publishes on the connection in `slot`, opening one if needed and dropping it on error.
+        fn publish_on(&self, slot: &mut Option<redis::Connection>, payload: &str) -> GResult<()> {
+            let conn = match slot {
+                Some(conn) => conn,
+                None => slot.insert(
+                    self.client
+                        .get_connection()
+                        .map_err(|err| from_redis(err, "connect redis"))?,
+                ),
+            };
+            let result = redis::cmd("PUBLISH")
+                .arg(&self.channel)
+                .arg(payload)
+                .query::<i64>(conn);
+            if result.is_err() {
+                *slot = None;
+            }
+            result
+                .map(drop)
+                .map_err(|err| from_redis(err, "publish cache invalidation"))
+        }
+    }
+
+    impl CacheInvalidator for RedisInvalidationBus {
+        fn publish(&self, message: &InvalidationMessage) -> GResult<()> {
+            let payload = message.encode()?;
+            let mut slot = self.connection.lock();
+            // A reused connection may have gone stale, so retry once on a fresh one; a
+            // duplicate invalidation only costs peers a re-read.
+            let reused = slot.is_some();
+            match self.publish_on(&mut slot, &payload) {
+                Err(_) if reused => self.publish_on(&mut slot, &payload),
+                result => result,
+            }
+        }
+    }
+
+    /// Background subscription thread; stops when dropped.
+    ///
+    /// After a dropped subscription the listener reconnects and flushes the whole cache, since
+    /// notifications may have been missed in between.
+    pub struct InvalidationListener {
+        stop: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl InvalidationListener {
+        fn spawn(
+            client: redis::Client,
+            source: Source,
+            target: CacheInvalidationTarget,
+        ) -> GResult<Self> {
+            let stop = Arc::new(AtomicBool::new(false));
+            let flag = Arc::clone(&stop);
+            let handle = thread::Builder::new()
+                .name("greentic-state-invalidation".into())
+                .spawn(move || {
+                    let mut resubscribing = false;
+                    while !flag.load(Ordering::Relaxed) {
+                        if let Err(err) = listen(&client, &source, &target, &flag, resubscribing) {
+                            warn!(error = %err, "cache invalidation subscription failed");
+                            resubscribing = true;
+                            thread::sleep(RECONNECT_DELAY);
+                        }
+                    }
+                })
+                .map_err(|err| internal(format!("spawn invalidation listener: {err}")))?;
+            Ok(Self {
+                stop,
+                handle: Some(handle),
+            })
+        }
+    }
+
+    impl Drop for InvalidationListener {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Relaxed);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    fn listen(
+        client: &redis::Client,
+        source: &Source,
+        target: &CacheInvalidationTarget,
+        stop: &AtomicBool,
+        resubscribing: bool,
+    ) -> redis::RedisResult<()> {
+        let mut conn = client.get_connection()?;
+        let mut pubsub = conn.as_pubsub();
+        pubsub.set_read_timeout(Some(POLL_INTERVAL))?;
+        let keyspace_prefix = match source {
+            Source::Channel(channel) => {
+                pubsub.subscribe(channel)?;
+                None
+            }
+            Source::Keyspace { db } => {
+                pubsub.psubscribe(format!("__keyspace@{db}__:greentic:state:*"))?;
+                Some(format!("__keyspace@{db}__:"))
+            }
+        };
+        if resubscribing {
+            target.apply(&Invalidation::All);
+        }
+        debug!("cache invalidation subscription established");
+
+        while !stop.load(Ordering::Relaxed) {
+            let msg = match pubsub.get_message() {
+                Ok(msg) => msg,
+                Err(err) if err.is_timeout() => continue,
+                Err(err) => return Err(err),
+            };
+            match &keyspace_prefix {
+                Some(prefix) => {
+                    if let Some(fqn) = msg.get_channel_name().strip_prefix(prefix.as_str()) {
+                        target.apply(&Invalidation::Key {
+                            fqn: fqn.to_owned(),
+                        });
+                    }
+                }
+                None => {
+                    let payload: String = msg.get_payload()?;
+                    match InvalidationMessage::decode(&payload) {
+                        Ok(message) => target.apply_message(&message),
+                        Err(err) => warn!(error = %err, "ignoring malformed cache invalidation"),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
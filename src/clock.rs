@@ -0,0 +1,76 @@
+//! Time sources for backends that compute expiry deadlines locally.
+
+use parking_lot::Mutex;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Source of the current time used to set and check TTL deadlines.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns the current instant.
+    fn now(&self) -> OffsetDateTime;
+}
+
+/// Wall-clock time; the default for every backend.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+/// Manually driven clock for testing expiry without sleeping.
+///
+/// Time only moves when [`TestClock::advance`] or [`TestClock::set`] is called. Clones share the
+/// same instant, so a test keeps one handle and gives another to the store.
+#[derive(Clone)]
+pub struct TestClock {
+    now: Arc<Mutex<OffsetDateTime>>,
+}
+
+impl TestClock {
+    /// Creates a clock frozen at 2024-01-01T00:00:00Z.
+    pub fn new() -> Self {
+        Self::starting_at(OffsetDateTime::UNIX_EPOCH + Duration::from_secs(1_704_067_200))
+    }
+
+    /// Creates a clock frozen at `now`.
+    pub fn starting_at(now: OffsetDateTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    /// Moves the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock() += by;
+    }
+
+    /// Moves the clock to `now`, which may be in the past.
+    pub fn set(&self, now: OffsetDateTime) {
+        *self.now.lock() = now;
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TestClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestClock")
+            .field("now", &*self.now.lock())
+            .finish()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> OffsetDateTime {
+        *self.now.lock()
+    }
+}
@@ -0,0 +1,129 @@
+//! Value codecs used by byte-oriented backends to serialize documents.
+//!
+//! JSON payloads are stored as plain JSON text. Binary encodings are prefixed with a one-byte
+//! tag naming the codec, so every stored value is self-describing and a store reads documents
+//! written with any codec, whichever one it writes with. Binary codecs need the `codecs`
+//! feature.
+
+use crate::error::from_serde;
+#[cfg(not(feature = "codecs"))]
+use crate::error::internal;
+#[cfg(feature = "codecs")]
+use crate::error::invalid_input;
+use greentic_types::GResult;
+use serde_json::Value;
+
+/// Header byte of a MessagePack payload.
+const MESSAGE_PACK_TAG: u8 = 0x10;
+/// Header byte of a CBOR payload.
+const CBOR_TAG: u8 = 0x11;
+
+/// Serialization format for stored documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValueCodec {
+    /// JSON text (the default); readable by every version of this crate.
+    #[default]
+    Json,
+    /// MessagePack.
+    MessagePack,
+    /// CBOR (RFC 8949).
+    Cbor,
+}
+
+impl ValueCodec {
+    /// Serializes `value`, prefixing binary encodings with their tag.
+    pub fn encode(self, value: &Value) -> GResult<Vec<u8>> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(from_serde),
+            Self::MessagePack | Self::Cbor => encode_binary(self, value),
+        }
+    }
+
+    /// Deserializes a payload written with any codec.
+    pub fn decode(payload: &[u8]) -> GResult<Value> {
+        match payload.first() {
+            Some(&MESSAGE_PACK_TAG) => decode_binary(Self::MessagePack, &payload[1..]),
+            Some(&CBOR_TAG) => decode_binary(Self::Cbor, &payload[1..]),
+            _ => serde_json::from_slice(payload).map_err(from_serde),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::MessagePack => "messagepack",
+            Self::Cbor => "cbor",
+        }
+    }
+}
+
+#[cfg(feature = "codecs")]
+fn encode_binary(codec: ValueCodec, value: &Value) -> GResult<Vec<u8>> {
+    let mut payload = Vec::new();
+    let encoded = match codec {
+        ValueCodec::Json => return serde_json::to_vec(value).map_err(from_serde),
+        ValueCodec::MessagePack => {
+            payload.push(MESSAGE_PACK_TAG);
+            rmp_serde::encode::write(&mut payload, value).map_err(|err| err.to_string())
+        }
+        ValueCodec::Cbor => {
+            payload.push(CBOR_TAG);
+            ciborium::into_writer(value, &mut payload).map_err(|err| err.to_string())
+        }
+    };
+    encoded.map_err(|err| invalid_input(format!("{} encode: {err}", codec.label())))?;
+    Ok(payload)
+}
+
+#[cfg(feature = "codecs")]
+fn decode_binary(codec: ValueCodec, body: &[u8]) -> GResult<Value> {
+    let decoded = match codec {
+        ValueCodec::Json => return serde_json::from_slice(body).map_err(from_serde),
+        ValueCodec::MessagePack => rmp_serde::from_slice(body).map_err(|err| err.to_string()),
+        ValueCodec::Cbor => ciborium::from_reader(body).map_err(|err| err.to_string()),
+    };
+    decoded.map_err(|err| invalid_input(format!("{} decode: {err}", codec.label())))
+}
+
+#[cfg(not(feature = "codecs"))]
+fn encode_binary(codec: ValueCodec, _value: &Value) -> GResult<Vec<u8>> {
+    Err(unsupported(codec))
+}
+
+#[cfg(not(feature = "codecs"))]
+fn decode_binary(codec: ValueCodec, _body: &[u8]) -> GResult<Value> {
+    Err(unsupported(codec))
+}
+
+#[cfg(not(feature = "codecs"))]
+fn unsupported(codec: ValueCodec) -> greentic_types::GreenticError {
+    internal(format!(
+        "{} payloads require the `codecs` feature",
+        codec.label()
+    ))
+}
+
+#[cfg(all(test, feature = "codecs"))]
+mod tests {
+    #![allow(clippy::expect_used, clippy::unwrap_used)]
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn every_codec_round_trips_and_is_self_describing() {
+        let value = json!({"a": [1, -2, 3.5, null, true], "b": {"c": "text"}, "big": u64::MAX});
+        for codec in [ValueCodec::Json, ValueCodec::MessagePack, ValueCodec::Cbor] {
+            let payload = codec.encode(&value).unwrap();
+            assert_eq!(
+                ValueCodec::decode(&payload).unwrap(),
+                value,
+                "{}",
+                codec.label()
+            );
+        }
+        assert_eq!(
+            ValueCodec::Json.encode(&value).unwrap(),
+            serde_json::to_vec(&value).unwrap()
+        );
+    }
+}
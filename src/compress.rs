@@ -0,0 +1,160 @@
+//! Transparent compression of serialized documents for byte-oriented backends.
+//!
+//! Payloads at or above [`CompressionConfig::threshold_bytes`] are compressed and framed with a
+//! one-byte header naming the algorithm. JSON text never starts with those bytes, so values
+//! stored before compression was enabled (and small values, which are stored as-is) still load.
+//! Decoding framed payloads needs the `compression` feature.
+
+use crate::error::internal;
+use greentic_types::GResult;
+use std::borrow::Cow;
+
+/// Histogram of compressed size divided by original size, labelled by `algorithm`.
+pub const COMPRESSION_RATIO: &str = "greentic_state_compression_ratio";
+
+/// Header byte of a zstd frame.
+const ZSTD_TAG: u8 = 0x01;
+/// Header byte of an LZ4 block with its uncompressed size prepended.
+const LZ4_TAG: u8 = 0x02;
+
+/// Compression algorithm applied to large payloads.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Zstandard; better ratios.
+    #[default]
+    Zstd,
+    /// LZ4; faster, lower ratios.
+    Lz4,
+}
+
+impl CompressionAlgorithm {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Lz4 => "lz4",
+        }
+    }
+}
+
+/// When and how payloads are compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionConfig {
+    /// Algorithm used for new writes.
+    pub algorithm: CompressionAlgorithm,
+    /// Payloads smaller than this many bytes are stored uncompressed.
+    pub threshold_bytes: usize,
+    /// Zstandard level (ignored for LZ4).
+    pub zstd_level: i32,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: CompressionAlgorithm::Zstd,
+            threshold_bytes: 4096,
+            zstd_level: 3,
+        }
+    }
+}
+
+/// Compresses `payload` when `config` asks for it and compression actually saves space.
+pub(crate) fn encode(config: Option<&CompressionConfig>, payload: Vec<u8>) -> GResult<Vec<u8>> {
+    let Some(config) = config else {
+        return Ok(payload);
+    };
+    if payload.len() < config.threshold_bytes {
+        return Ok(payload);
+    }
+
+    let framed = compress(config, &payload)?;
+    metrics::histogram!(COMPRESSION_RATIO, "algorithm" => config.algorithm.label())
+        .record(framed.len() as f64 / payload.len() as f64);
+    if framed.len() < payload.len() {
+        Ok(framed)
+    } else {
+        Ok(payload)
+    }
+}
+
+/// Strips the compression frame from a stored payload; unframed payloads are returned as-is.
+pub(crate) fn decode(stored: &[u8]) -> GResult<Cow<'_, [u8]>> {
+    let algorithm = match stored.first() {
+        Some(&ZSTD_TAG) => CompressionAlgorithm::Zstd,
+        Some(&LZ4_TAG) => CompressionAlgorithm::Lz4,
+        _ => return Ok(Cow::Borrowed(stored)),
+    };
+    decompress(algorithm, &stored[1..]).map(Cow::Owned)
+}
+
+#[cfg(feature = "compression")]
+fn compress(config: &CompressionConfig, payload: &[u8]) -> GResult<Vec<u8>> {
+    let mut framed = Vec::with_capacity(payload.len() / 2 + 1);
+    match config.algorithm {
+        CompressionAlgorithm::Zstd => {
+            framed.push(ZSTD_TAG);
+            zstd::stream::copy_encode(payload, &mut framed, config.zstd_level)
+                .map_err(|err| internal(format!("zstd compress: {err}")))?;
+        }
+        CompressionAlgorithm::Lz4 => {
+            framed.push(LZ4_TAG);
+            framed.extend(lz4_flex::compress_prepend_size(payload));
+        }
+    }
+    Ok(framed)
+}
+
+#[cfg(not(feature = "compression"))]
+fn compress(config: &CompressionConfig, _payload: &[u8]) -> GResult<Vec<u8>> {
+    Err(unsupported(config.algorithm))
+}
+
+#[cfg(feature = "compression")]
+fn decompress(algorithm: CompressionAlgorithm, body: &[u8]) -> GResult<Vec<u8>> {
+    match algorithm {
+        CompressionAlgorithm::Zstd => zstd::stream::decode_all(body)
+            .map_err(|err| internal(format!("zstd decompress: {err}"))),
+        CompressionAlgorithm::Lz4 => lz4_flex::decompress_size_prepended(body)
+            .map_err(|err| internal(format!("lz4 decompress: {err}"))),
+    }
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(algorithm: CompressionAlgorithm, _body: &[u8]) -> GResult<Vec<u8>> {
+    Err(unsupported(algorithm))
+}
+
+#[cfg(not(feature = "compression"))]
+fn unsupported(algorithm: CompressionAlgorithm) -> greentic_types::GreenticError {
+    internal(format!(
+        "{} payloads require the `compression` feature",
+        algorithm.label()
+    ))
+}
+
+#[cfg(all(test, feature = "compression"))]
+mod tests {
+    #![allow(clippy::expect_used, clippy::unwrap_used)]
+    use super::*;
+
+    #[test]
+    fn small_payloads_stay_plain() {
+        let config = CompressionConfig::default();
+        let payload = br#"{"a":1}"#.to_vec();
+        assert_eq!(encode(Some(&config), payload.clone()).unwrap(), payload);
+        assert_eq!(decode(&payload).unwrap().as_ref(), payload.as_slice());
+    }
+
+    #[test]
+    fn large_payloads_round_trip() {
+        let payload = serde_json::to_vec(&vec!["repeated text"; 1000]).unwrap();
+        for algorithm in [CompressionAlgorithm::Zstd, CompressionAlgorithm::Lz4] {
+            let config = CompressionConfig {
+                algorithm,
+                ..CompressionConfig::default()
+            };
+            let stored = encode(Some(&config), payload.clone()).unwrap();
+            assert!(stored.len() < payload.len() / 4);
+            assert_eq!(decode(&stored).unwrap().as_ref(), payload.as_slice());
+        }
+    }
+}
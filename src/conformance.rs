@@ -0,0 +1,395 @@
+//! Backend-agnostic conformance suite for [`StateStore`] implementations.
+//!
+//! Every case writes under its own unique prefix and removes it afterwards, so the suite can run
+//! against a shared server and alongside other tests. Failures panic with the violated guarantee.
+//! Enable the `testing` feature to use it from a backend's test suite:
+//!
+//! ```ignore
+//! mod in_memory {
+//!     greentic_state::state_store_conformance!(|| {
+//!         greentic_state::inmemory::InMemoryStateStore::new()
+//!     });
+//! }
+//! ```
+//!
+//! Backends reading time from a [`Clock`](crate::clock::Clock) can take a [`TestClock`] instead,
+//! so that cases waiting for entries to expire advance it rather than sleep:
+//!
+//! ```ignore
+//! greentic_state::state_store_conformance!(clock = |clock| {
+//!     InMemoryStateStore::new().with_clock(Arc::new(clock))
+//! });
+//! ```
+
+use crate::clock::TestClock;
+use crate::key::StatePath;
+use crate::store::StateStore;
+use greentic_types::{EnvId, StateKey, TenantCtx, TenantId};
+use serde_json::{Value, json};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// A named conformance check run against a single store. The second argument lets the given
+/// time pass for the store, by sleeping or by advancing its [`TestClock`].
+pub type ConformanceCase = (&'static str, fn(&dyn StateStore, &dyn Fn(Duration)));
+
+crate::__conformance_cases!(table);
+
+/// Runs every case in [`CASES`] against a fresh store from `factory`, sleeping whenever a case
+/// waits.
+pub fn run_suite<S: StateStore>(factory: impl Fn() -> S) {
+    for (name, case) in CASES {
+        tracing::debug!(case = name, "running state store conformance case");
+        case(&factory(), &thread::sleep);
+    }
+}
+
+/// Runs every case in [`CASES`] against a fresh store from `factory`, which must read time from
+/// the [`TestClock`] it is given; waits advance that clock instead of sleeping.
+pub fn run_suite_with_clock<S: StateStore>(factory: impl Fn(TestClock) -> S) {
+    for (name, case) in CASES {
+        tracing::debug!(case = name, "running state store conformance case");
+        let clock = TestClock::new();
+        case(&factory(clock.clone()), &|by| clock.advance(by));
+    }
+}
+
+/// Generates one `#[test]` per conformance case, each calling `$factory` for a fresh store.
+///
+/// With `clock = $factory`, the factory receives a [`TestClock`](crate::clock::TestClock) that
+/// the store must read time from, and waits advance it instead of sleeping.
+#[macro_export]
+macro_rules! state_store_conformance {
+    (clock = $factory:expr) => {
+        $crate::__conformance_cases!(clocked_tests $factory);
+    };
+    ($factory:expr) => {
+        $crate::__conformance_cases!(tests $factory);
+    };
+}
+
+/// The single list of conformance cases, expanded by [`__conformance_expand`] into [`CASES`]
+/// or into tests.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __conformance_cases {
+    ($($mode:tt)+) => {
+        $crate::__conformance_expand!(
+            [$($mode)+]
+            roundtrip_and_path_upserts,
+            delete_reports_existence,
+            ttl_is_preserved_and_cleared,
+            tenants_are_isolated,
+            prefix_deletion_is_exact
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __conformance_expand {
+    ([table] $($case:ident),+) => {
+        /// Every check in the suite, in the order [`run_suite`] runs them.
+        pub const CASES: &[ConformanceCase] = &[$((stringify!($case), $case)),+];
+    };
+    ([tests $factory:expr] $($case:ident),+) => {
+        $(
+            #[test]
+            fn $case() {
+                let store = ($factory)();
+                $crate::conformance::$case(&store, &::std::thread::sleep);
+            }
+        )+
+    };
+    ([clocked_tests $factory:expr] $($case:ident),+) => {
+        $(
+            #[test]
+            fn $case() {
+                let clock = $crate::clock::TestClock::new();
+                let store = ($factory)(clock.clone());
+                $crate::conformance::$case(&store, &|by| clock.advance(by));
+            }
+        )+
+    };
+}
+
+/// Whole-document and JSON Pointer reads and writes, including upserts that create parents.
+pub fn roundtrip_and_path_upserts(store: &dyn StateStore, _wait: &dyn Fn(Duration)) {
+    let ctx = ctx("conformance");
+    let prefix = unique_prefix("roundtrip");
+    let key = StateKey::new("node/a");
+
+    assert_eq!(get(store, &ctx, &prefix, &key, None), None, "missing key");
+    let doc = json!({"a": [1, 2, 3], "status": "ready"});
+    set(store, &ctx, &prefix, &key, None, &doc, None);
+    assert_eq!(get(store, &ctx, &prefix, &key, None), Some(doc));
+    assert_eq!(
+        get(store, &ctx, &prefix, &key, Some("/a/1")),
+        Some(json!(2)),
+        "path read"
+    );
+    assert_eq!(
+        get(store, &ctx, &prefix, &key, Some("/missing")),
+        None,
+        "missing path"
+    );
+
+    set(store, &ctx, &prefix, &key, Some("/a/1"), &json!(42), None);
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &key,
+        Some("/meta/owner"),
+        &json!("x"),
+        None,
+    );
+    assert_eq!(
+        get(store, &ctx, &prefix, &key, None),
+        Some(json!({"a": [1, 42, 3], "status": "ready", "meta": {"owner": "x"}})),
+        "path upserts"
+    );
+
+    let fresh = StateKey::new("node/b");
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &fresh,
+        Some("/x/y"),
+        &json!(true),
+        None,
+    );
+    assert_eq!(
+        get(store, &ctx, &prefix, &fresh, None),
+        Some(json!({"x": {"y": true}})),
+        "path upsert into a missing key"
+    );
+
+    let replacement = json!(["replaced"]);
+    set(store, &ctx, &prefix, &key, None, &replacement, None);
+    assert_eq!(get(store, &ctx, &prefix, &key, None), Some(replacement));
+
+    cleanup(store, &ctx, &prefix);
+}
+
+/// `del` removes the entry and reports whether it existed.
+pub fn delete_reports_existence(store: &dyn StateStore, _wait: &dyn Fn(Duration)) {
+    let ctx = ctx("conformance");
+    let prefix = unique_prefix("delete");
+    let key = StateKey::new("node/a");
+
+    set(store, &ctx, &prefix, &key, None, &json!({"a": 1}), None);
+    assert!(check(store.del(&ctx, &prefix, &key), "del"), "existing key");
+    assert_eq!(get(store, &ctx, &prefix, &key, None), None, "deleted key");
+    assert!(!check(store.del(&ctx, &prefix, &key), "del"), "missing key");
+
+    cleanup(store, &ctx, &prefix);
+}
+
+/// TTLs expire entries, `None` keeps the current TTL and `Some(0)` clears it.
+pub fn ttl_is_preserved_and_cleared(store: &dyn StateStore, wait: &dyn Fn(Duration)) {
+    let ctx = ctx("conformance");
+    let prefix = unique_prefix("ttl");
+    let expiring = StateKey::new("expiring");
+    let preserved = StateKey::new("preserved");
+    let preserved_by_path = StateKey::new("preserved-by-path");
+    let cleared = StateKey::new("cleared");
+    let persistent = StateKey::new("persistent");
+
+    for key in [&expiring, &preserved, &preserved_by_path, &cleared] {
+        set(store, &ctx, &prefix, key, None, &json!({"v": 1}), Some(1));
+    }
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &persistent,
+        None,
+        &json!({"v": 1}),
+        None,
+    );
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &preserved,
+        None,
+        &json!({"v": 2}),
+        None,
+    );
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &preserved_by_path,
+        Some("/v"),
+        &json!(2),
+        None,
+    );
+    set(
+        store,
+        &ctx,
+        &prefix,
+        &cleared,
+        None,
+        &json!({"v": 2}),
+        Some(0),
+    );
+
+    wait(Duration::from_millis(1_200));
+
+    for key in [&expiring, &preserved, &preserved_by_path] {
+        assert_eq!(
+            get(store, &ctx, &prefix, key, None),
+            None,
+            "`{}` should have expired",
+            key.as_str()
+        );
+    }
+    assert_eq!(
+        get(store, &ctx, &prefix, &cleared, None),
+        Some(json!({"v": 2})),
+        "`Some(0)` should clear the TTL"
+    );
+    assert_eq!(
+        get(store, &ctx, &prefix, &persistent, None),
+        Some(json!({"v": 1})),
+        "entries written without a TTL never expire"
+    );
+
+    cleanup(store, &ctx, &prefix);
+}
+
+/// Tenants sharing a prefix and key never see, overwrite or delete each other's entries.
+pub fn tenants_are_isolated(store: &dyn StateStore, _wait: &dyn Fn(Duration)) {
+    let ctx_a = ctx("conformance-a");
+    let ctx_b = ctx("conformance-b");
+    let prefix = unique_prefix("tenants");
+    let key = StateKey::new("node/a");
+
+    set(store, &ctx_a, &prefix, &key, None, &json!({"a": 1}), None);
+    assert_eq!(get(store, &ctx_b, &prefix, &key, None), None, "read leak");
+    set(store, &ctx_b, &prefix, &key, None, &json!({"b": 2}), None);
+    assert_eq!(
+        get(store, &ctx_a, &prefix, &key, None),
+        Some(json!({"a": 1})),
+        "write leak"
+    );
+
+    assert!(!check(
+        store.del(&ctx_b, &prefix, &StateKey::new("other")),
+        "del"
+    ));
+    assert!(check(store.del(&ctx_b, &prefix, &key), "del"));
+    assert_eq!(
+        get(store, &ctx_a, &prefix, &key, None),
+        Some(json!({"a": 1})),
+        "delete leak"
+    );
+
+    set(store, &ctx_b, &prefix, &key, None, &json!({"b": 2}), None);
+    assert_eq!(check(store.del_prefix(&ctx_a, &prefix), "del_prefix"), 1);
+    assert_eq!(
+        get(store, &ctx_b, &prefix, &key, None),
+        Some(json!({"b": 2})),
+        "prefix delete leak"
+    );
+
+    cleanup(store, &ctx_b, &prefix);
+}
+
+/// `del_prefix` removes exactly the entries under that prefix and returns how many it removed.
+pub fn prefix_deletion_is_exact(store: &dyn StateStore, _wait: &dyn Fn(Duration)) {
+    let ctx = ctx("conformance");
+    let prefix = unique_prefix("prefix");
+    let sibling = format!("{prefix}-sibling");
+    let child = format!("{prefix}/child");
+
+    for name in ["node/a", "node/b", "node/c"] {
+        let key = StateKey::new(name);
+        set(
+            store,
+            &ctx,
+            &prefix,
+            &key,
+            None,
+            &json!({"k": name}),
+            Some(600),
+        );
+    }
+    let untouched = StateKey::new("node/a");
+    set(store, &ctx, &sibling, &untouched, None, &json!(1), None);
+    set(store, &ctx, &child, &untouched, None, &json!(2), None);
+
+    assert_eq!(check(store.del_prefix(&ctx, &prefix), "del_prefix"), 3);
+    assert_eq!(get(store, &ctx, &prefix, &untouched, None), None);
+    assert_eq!(check(store.del_prefix(&ctx, &prefix), "del_prefix"), 0);
+    assert_eq!(
+        get(store, &ctx, &sibling, &untouched, None),
+        Some(json!(1)),
+        "prefixes sharing a leading string are distinct"
+    );
+    assert_eq!(
+        get(store, &ctx, &child, &untouched, None),
+        Some(json!(2)),
+        "nested prefixes are distinct"
+    );
+
+    cleanup(store, &ctx, &sibling);
+    cleanup(store, &ctx, &child);
+}
+
+fn ctx(tenant: &str) -> TenantCtx {
+    TenantCtx::new(
+        check(EnvId::try_from("dev"), "env id"),
+        check(TenantId::try_from(tenant), "tenant id"),
+    )
+}
+
+/// Prefix no other run of the suite (in this or another process) writes under.
+fn unique_prefix(case: &str) -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let nanos = time::OffsetDateTime::now_utc().unix_timestamp_nanos();
+    format!(
+        "conformance/{case}-{}-{nanos:x}-{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+fn check<T, E: std::fmt::Debug>(result: Result<T, E>, operation: &str) -> T {
+    result.unwrap_or_else(|err| panic!("{operation} failed: {err:?}"))
+}
+
+fn get(
+    store: &dyn StateStore,
+    ctx: &TenantCtx,
+    prefix: &str,
+    key: &StateKey,
+    path: Option<&str>,
+) -> Option<Value> {
+    let path = path.map(StatePath::from_pointer);
+    check(store.get_json(ctx, prefix, key, path.as_ref()), "get_json")
+}
+
+fn set(
+    store: &dyn StateStore,
+    ctx: &TenantCtx,
+    prefix: &str,
+    key: &StateKey,
+    path: Option<&str>,
+    value: &Value,
+    ttl_secs: Option<u32>,
+) {
+    let path = path.map(StatePath::from_pointer);
+    check(
+        store.set_json(ctx, prefix, key, path.as_ref(), value, ttl_secs),
+        "set_json",
+    );
+}
+
+fn cleanup(store: &dyn StateStore, ctx: &TenantCtx, prefix: &str) {
+    check(store.del_prefix(ctx, prefix), "cleanup");
+}
@@ -0,0 +1,505 @@
+//! Encryption at rest for stored documents.
+//!
+//! [`EncryptedStateStore`] wraps a store and replaces every document with an
+//! [`EncryptedEnvelope`] before it reaches the backend. Documents are sealed with AES-256-GCM or
+//! ChaCha20-Poly1305 under a per-tenant [`DataKey`] supplied by a [`KeyProvider`], and the FQN
+//! is bound as associated data so an envelope cannot be replayed under another key.
+
+use crate::error::{from_serde, internal};
+use crate::key::{StatePath, fqn};
+use crate::limits::StateLimits;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::{get_at_path, set_at_path_with_limits};
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::ChaCha20Poly1305;
+use dashmap::DashMap;
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Envelope format version written by this module.
+const ENVELOPE_VERSION: u8 = 1;
+
+/// AEAD algorithm used to seal a document.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Cipher {
+    /// AES-256 in Galois/Counter Mode.
+    #[default]
+    Aes256Gcm,
+    /// ChaCha20 stream cipher with a Poly1305 authenticator.
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn seal(
+        self,
+        material: &[u8; 32],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> GResult<(Vec<u8>, Vec<u8>)> {
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let (nonce, sealed) = match self {
+            Self::Aes256Gcm => {
+                let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+                let sealed = Aes256Gcm::new(material.into()).encrypt(&nonce, payload);
+                (nonce.to_vec(), sealed)
+            }
+            Self::ChaCha20Poly1305 => {
+                let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+                let sealed = ChaCha20Poly1305::new(material.into()).encrypt(&nonce, payload);
+                (nonce.to_vec(), sealed)
+            }
+        };
+        let sealed = sealed.map_err(|_| internal("encrypt state document"))?;
+        Ok((nonce, sealed))
+    }
+
+    fn open(
+        self,
+        material: &[u8; 32],
+        nonce: &[u8],
+        sealed: &[u8],
+        aad: &[u8],
+    ) -> GResult<Vec<u8>> {
+        if nonce.len() != 12 {
+            return Err(internal("encrypted state document has a malformed nonce"));
+        }
+        let payload = Payload { msg: sealed, aad };
+        let opened = match self {
+            Self::Aes256Gcm => Aes256Gcm::new(material.into()).decrypt(nonce.into(), payload),
+            Self::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(material.into()).decrypt(nonce.into(), payload)
+            }
+        };
+        opened.map_err(|_| internal("decrypt state document: authentication failed"))
+    }
+}
+
+/// Symmetric data key identified by `id`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DataKey {
+    id: String,
+    cipher: Cipher,
+    material: [u8; 32],
+}
+
+impl DataKey {
+    /// Builds a key from existing 256-bit `material`, e.g. one unwrapped by a KMS.
+    pub fn new(id: impl Into<String>, cipher: Cipher, material: [u8; 32]) -> Self {
+        Self {
+            id: id.into(),
+            cipher,
+            material,
+        }
+    }
+
+    /// Generates a fresh random key from the operating system RNG.
+    pub fn generate(id: impl Into<String>, cipher: Cipher) -> Self {
+        let mut material = [0u8; 32];
+        OsRng.fill_bytes(&mut material);
+        Self::new(id, cipher, material)
+    }
+
+    /// Identifier recorded in every envelope sealed with this key.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Algorithm used when sealing with this key.
+    pub fn cipher(&self) -> Cipher {
+        self.cipher
+    }
+}
+
+impl fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DataKey")
+            .field("id", &self.id)
+            .field("cipher", &self.cipher)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Source of per-tenant data keys.
+pub trait KeyProvider: Send + Sync + 'static {
+    /// Key that new documents of `tenant` are sealed with.
+    fn current_key(&self, tenant: &TenantCtx) -> GResult<DataKey>;
+
+    /// Looks up a previous or current key of `tenant` by id; `None` when it is unknown.
+    fn key(&self, tenant: &TenantCtx, key_id: &str) -> GResult<Option<DataKey>>;
+}
+
+#[derive(Default)]
+struct Keyring {
+    current: Option<String>,
+    keys: HashMap<String, DataKey>,
+}
+
+/// In-process [`KeyProvider`] holding one keyring per `{env}:{tenant}`.
+///
+/// Team and user scopes share their tenant's keys. Retired keys stay available for decryption
+/// until they are removed with [`StaticKeyProvider::retire`].
+#[derive(Clone, Default)]
+pub struct StaticKeyProvider {
+    keyrings: Arc<DashMap<String, Keyring>>,
+}
+
+impl StaticKeyProvider {
+    /// Creates a provider without any keys.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `key` to the tenant's keyring and makes it the current key.
+    pub fn rotate(&self, tenant: &TenantCtx, key: DataKey) {
+        let mut keyring = self.keyrings.entry(keyring_id(tenant)).or_default();
+        keyring.current = Some(key.id.clone());
+        keyring.keys.insert(key.id.clone(), key);
+    }
+
+    /// Removes a non-current key; documents still sealed with it can no longer be read.
+    /// Returns `true` when the key was removed.
+    pub fn retire(&self, tenant: &TenantCtx, key_id: &str) -> bool {
+        let Some(mut keyring) = self.keyrings.get_mut(&keyring_id(tenant)) else {
+            return false;
+        };
+        if keyring.current.as_deref() == Some(key_id) {
+            return false;
+        }
+        keyring.keys.remove(key_id).is_some()
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key(&self, tenant: &TenantCtx) -> GResult<DataKey> {
+        let id = keyring_id(tenant);
+        self.keyrings
+            .get(&id)
+            .and_then(|keyring| {
+                let current = keyring.current.as_ref()?;
+                keyring.keys.get(current).cloned()
+            })
+            .ok_or_else(|| internal(format!("no data key configured for tenant `{id}`")))
+    }
+
+    fn key(&self, tenant: &TenantCtx, key_id: &str) -> GResult<Option<DataKey>> {
+        Ok(self
+            .keyrings
+            .get(&keyring_id(tenant))
+            .and_then(|keyring| keyring.keys.get(key_id).cloned()))
+    }
+}
+
+fn keyring_id(tenant: &TenantCtx) -> String {
+    format!("{}:{}", tenant.env.as_str(), tenant.tenant_id.as_str())
+}
+
+/// Stored form of an encrypted document.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedEnvelope {
+    /// Envelope format version.
+    #[serde(rename = "$greentic_enc")]
+    pub version: u8,
+    /// Algorithm the document was sealed with.
+    pub alg: Cipher,
+    /// Id of the [`DataKey`] the document was sealed with.
+    pub kid: String,
+    /// Base64 nonce.
+    pub nonce: String,
+    /// Base64 ciphertext including the authentication tag.
+    pub ct: String,
+}
+
+impl EncryptedEnvelope {
+    /// Recognises an envelope among stored values: values without a `$greentic_enc` field are
+    /// plaintext (`None`), while a value carrying one that is not a well-formed envelope is an
+    /// error rather than plaintext, so a damaged envelope is never handed out as data.
+    pub fn from_value(value: &Value) -> GResult<Option<Self>> {
+        if value.get("$greentic_enc").is_none() {
+            return Ok(None);
+        }
+        serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|err| internal(format!("malformed encrypted envelope: {err}")))
+    }
+
+    pub(crate) fn to_value(&self) -> GResult<Value> {
+        serde_json::to_value(self).map_err(from_serde)
+    }
+
+    /// Seals `value` under `data_key`, binding `aad` as associated data.
+    pub(crate) fn seal(data_key: &DataKey, aad: &[u8], value: &Value) -> GResult<Self> {
+        let plaintext = serde_json::to_vec(value).map_err(from_serde)?;
+        let (nonce, sealed) = data_key.cipher.seal(&data_key.material, &plaintext, aad)?;
+        Ok(Self {
+            version: ENVELOPE_VERSION,
+            alg: data_key.cipher,
+            kid: data_key.id.clone(),
+            nonce: BASE64.encode(nonce),
+            ct: BASE64.encode(sealed),
+        })
+    }
+
+    /// Opens the envelope with the tenant's key named by `kid`.
+    pub(crate) fn open(
+        &self,
+        keys: &dyn KeyProvider,
+        tenant: &TenantCtx,
+        aad: &[u8],
+    ) -> GResult<Value> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(internal(format!(
+                "unsupported encrypted envelope version {}",
+                self.version
+            )));
+        }
+        let data_key = keys
+            .key(tenant, &self.kid)?
+            .ok_or_else(|| internal(format!("unknown data key `{}`", self.kid)))?;
+        let nonce = BASE64
+            .decode(&self.nonce)
+            .map_err(|err| internal(format!("decode envelope nonce: {err}")))?;
+        let sealed = BASE64
+            .decode(&self.ct)
+            .map_err(|err| internal(format!("decode envelope ciphertext: {err}")))?;
+        let plaintext = self.alg.open(&data_key.material, &nonce, &sealed, aad)?;
+        serde_json::from_slice(&plaintext).map_err(from_serde)
+    }
+}
+
+/// [`StateStore`] wrapper that encrypts documents before they reach the inner store.
+///
+/// Path reads and writes are served by decrypting the whole document, so they behave exactly
+/// like whole-document operations on the inner store (including TTL handling). Documents sealed
+/// with a key other than the tenant's current one, or written in plaintext before encryption
+/// was enabled, are moved to the current key by [`EncryptedStateStore::reencrypt`], run during a
+/// quiet period. [`EncryptedStateStore::with_reencrypt_on_read`] makes reads write them back
+/// instead, keeping their TTL; that write-back is not atomic with the read, so a concurrent
+/// writer may be overwritten with the value just read.
+pub struct EncryptedStateStore<S> {
+    inner: S,
+    keys: Arc<dyn KeyProvider>,
+    reencrypt_on_read: bool,
+    limits: StateLimits,
+}
+
+impl<S: StateStore> EncryptedStateStore<S> {
+    /// Wraps `inner`, sealing documents with keys from `keys`. Plaintext documents are checked
+    /// against the default [`StateLimits`].
+    pub fn new(inner: S, keys: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            inner,
+            keys,
+            reencrypt_on_read: false,
+            limits: StateLimits::default(),
+        }
+    }
+
+    /// Replaces the limits checked against plaintext documents before they are sealed; the inner
+    /// store only sees the envelope.
+    pub fn with_limits(mut self, limits: StateLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Enables or disables re-encryption of stale documents on read (disabled by default, as
+    /// the write-back can overwrite a concurrent write).
+    pub fn with_reencrypt_on_read(mut self, enabled: bool) -> Self {
+        self.reencrypt_on_read = enabled;
+        self
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Re-encrypts every document of `tenant` (optionally narrowed to `prefix`) that is not
+    /// sealed with the current key. Returns the number of documents rewritten.
+    pub fn reencrypt(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<u64> {
+        let current = self.keys.current_key(tenant)?;
+        let mut rewritten = 0;
+        for entry in self.inner.list_keys(tenant, prefix)? {
+            let Some((value, kid)) = self.load(tenant, &entry.prefix, &entry.key)? else {
+                continue;
+            };
+            if kid.as_deref() != Some(current.id.as_str()) {
+                self.store(tenant, &entry.prefix, &entry.key, &current, &value, None)?;
+                rewritten += 1;
+            }
+        }
+        Ok(rewritten)
+    }
+
+    fn seal(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        data_key: &DataKey,
+        value: &Value,
+    ) -> GResult<Value> {
+        let aad = fqn(tenant, prefix, key);
+        EncryptedEnvelope::seal(data_key, aad.as_str().as_bytes(), value)?.to_value()
+    }
+
+    fn open(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        envelope: &EncryptedEnvelope,
+    ) -> GResult<Value> {
+        let aad = fqn(tenant, prefix, key);
+        envelope.open(self.keys.as_ref(), tenant, aad.as_str().as_bytes())
+    }
+
+    /// Reads and decrypts the whole document, returning it with the id of the key it was sealed
+    /// with (`None` for plaintext).
+    fn load(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+    ) -> GResult<Option<(Value, Option<String>)>> {
+        let Some(stored) = self.inner.get_json(tenant, prefix, key, None)? else {
+            return Ok(None);
+        };
+        Ok(Some(match EncryptedEnvelope::from_value(&stored)? {
+            Some(envelope) => {
+                let value = self.open(tenant, prefix, key, &envelope)?;
+                (value, Some(envelope.kid))
+            }
+            None => (stored, None),
+        }))
+    }
+
+    /// Seals `value` with `data_key` and writes it as a whole document.
+    fn store(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        data_key: &DataKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let sealed = self.seal(tenant, prefix, key, data_key, value)?;
+        self.inner
+            .set_json(tenant, prefix, key, None, &sealed, ttl_secs)
+    }
+}
+
+impl<S: StateStore> StateStore for EncryptedStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        let Some((value, kid)) = self.load(tenant, prefix, key)? else {
+            return Ok(None);
+        };
+        if self.reencrypt_on_read {
+            let current = self.keys.current_key(tenant)?;
+            if kid.as_deref() != Some(current.id.as_str())
+                && let Err(err) = self.store(tenant, prefix, key, &current, &value, None)
+            {
+                warn!(error = %err, kid = %current.id, "failed to re-encrypt state document");
+            }
+        }
+        Ok(match path {
+            Some(path) => get_at_path(&value, path).cloned(),
+            None => Some(value),
+        })
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let document = match path {
+            Some(path) => {
+                let mut document = self
+                    .load(tenant, prefix, key)?
+                    .map_or(Value::Null, |(document, _)| document);
+                set_at_path_with_limits(&mut document, path, value.clone(), &self.limits)?;
+                document
+            }
+            None => value.clone(),
+        };
+        self.limits.check_document(&document)?;
+        let current = self.keys.current_key(tenant)?;
+        self.store(tenant, prefix, key, &current, &document, ttl_secs)
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        self.limits.check_document(value)?;
+        let current = self.keys.current_key(tenant)?;
+        let sealed = self.seal(tenant, prefix, key, &current, value)?;
+        self.inner
+            .set_if_absent(tenant, prefix, key, &sealed, ttl_secs)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.inner.del(tenant, prefix, key)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix(tenant, prefix)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix_recursive(tenant, prefix)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.inner.prefix_stats(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.inner.list_keys(tenant, prefix)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        self.inner.purge_tenant(tenant)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        self.inner.purge_team(tenant, team)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        self.inner.purge_user(tenant, user)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.inner.ttl(tenant, prefix, key)
+    }
+}
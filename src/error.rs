@@ -12,11 +12,28 @@ pub fn internal(message: impl Into<String>) -> GreenticError {
     GreenticError::new(ErrorCode::Internal, message)
 }
 
+/// Builds a `Conflict` error when an operation collides with existing data.
+pub fn conflict(message: impl Into<String>) -> GreenticError {
+    GreenticError::new(ErrorCode::Conflict, message)
+}
+
 /// Builds an `Unavailable` error for backend outages.
 pub fn unavailable(message: impl Into<String>) -> GreenticError {
     GreenticError::new(ErrorCode::Unavailable, message)
 }
 
+/// Builds a `RateLimited` error when a caller exceeds its allowance.
+pub fn rate_limited(message: impl Into<String>) -> GreenticError {
+    GreenticError::new(ErrorCode::RateLimited, message)
+}
+
+/// Builds an `Internal` error for operations a backend does not implement.
+pub fn unsupported(operation: &str) -> GreenticError {
+    internal(format!(
+        "operation `{operation}` is not supported by this backend"
+    ))
+}
+
 /// Wraps a `serde_json` error as `InvalidInput`.
 pub fn from_serde(err: SerdeError) -> GreenticError {
     invalid_input(err.to_string())
@@ -28,7 +45,12 @@ pub fn with_context(err: impl Display, context: impl Into<String>) -> GreenticEr
 }
 
 #[cfg(feature = "redis")]
-/// Wraps a Redis error as `Unavailable`.
+/// Wraps a Redis error as `Timeout` when a command timed out, `Unavailable` otherwise.
 pub fn from_redis(err: redis::RedisError, context: impl Into<String>) -> GreenticError {
-    unavailable(format!("{}: {}", context.into(), err))
+    let message = format!("{}: {}", context.into(), err);
+    if err.is_timeout() {
+        GreenticError::new(ErrorCode::Timeout, message)
+    } else {
+        unavailable(message)
+    }
 }
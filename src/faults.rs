@@ -0,0 +1,382 @@
+//! Fault injection for exercising retry and error handling against a misbehaving backend.
+//!
+//! [`FaultyStateStore`] wraps any store and consults its [`FaultRule`]s, in order, before each
+//! call. A rule matches by operation, prefix and a probability drawn from a seeded RNG, so a
+//! failing run replays exactly with the same seed. [`Fault::Latency`] delays the call and keeps
+//! evaluating later rules; the first other matching fault decides the outcome.
+
+use crate::key::StatePath;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use greentic_types::{ErrorCode, GResult, GreenticError, StateKey, TeamId, TenantCtx, UserId};
+use parking_lot::Mutex;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+use tracing::debug;
+
+/// Store operation a [`FaultRule`] applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultOperation {
+    /// `get_json`.
+    Get,
+    /// `set_json`.
+    Set,
+    /// `set_if_absent`.
+    SetIfAbsent,
+    /// `del`.
+    Del,
+    /// `del_prefix`.
+    DelPrefix,
+    /// `del_prefix_recursive`.
+    DelPrefixRecursive,
+    /// `prefix_stats`.
+    PrefixStats,
+    /// `list_keys`.
+    ListKeys,
+    /// `ttl`.
+    Ttl,
+    /// `purge_tenant`, `purge_team` and `purge_user`.
+    Purge,
+}
+
+/// Misbehavior injected when a [`FaultRule`] fires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail without reaching the wrapped store.
+    Error(ErrorCode),
+    /// Sleep before the call proceeds.
+    Latency(Duration),
+    /// Report success for `set_json`, `set_if_absent` or `del` without applying it; ignored for
+    /// other operations.
+    DropWrite,
+    /// Apply `set_json`, `set_if_absent` or `del`, then fail with `Unavailable` as if the reply
+    /// was lost on the way back; ignored for other operations.
+    ReplyLost,
+    /// Delete only the first `completed` keys of a `del_prefix` (in [`StateStore::list_keys`]
+    /// order), then fail with `Unavailable`; ignored for other operations.
+    PartialBatch {
+        /// Number of keys removed before the failure.
+        completed: usize,
+    },
+}
+
+/// When and how to inject a [`Fault`].
+#[derive(Clone, Debug)]
+pub struct FaultRule {
+    fault: Fault,
+    operations: Vec<FaultOperation>,
+    prefix: Option<String>,
+    probability: f64,
+    remaining: Option<u64>,
+}
+
+impl FaultRule {
+    /// Injects `fault` on every applicable call until narrowed down.
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            operations: Vec::new(),
+            prefix: None,
+            probability: 1.0,
+            remaining: None,
+        }
+    }
+
+    /// Restricts the rule to `operation`; call repeatedly to allow several.
+    pub fn on(mut self, operation: FaultOperation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Restricts the rule to prefixes starting with `prefix`. Calls without a prefix
+    /// (`list_keys(tenant, None)`) never match.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Fires on each matching call with the given probability, clamped to `0.0..=1.0`.
+    pub fn probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Stops firing after `times` injections, e.g. to fail the first attempts of a retry loop.
+    pub fn times(mut self, times: u64) -> Self {
+        self.remaining = Some(times);
+        self
+    }
+
+    fn applies(&self, operation: FaultOperation, prefix: Option<&str>) -> bool {
+        let applicable = match self.fault {
+            Fault::DropWrite | Fault::ReplyLost => matches!(
+                operation,
+                FaultOperation::Set | FaultOperation::SetIfAbsent | FaultOperation::Del
+            ),
+            Fault::PartialBatch { .. } => operation == FaultOperation::DelPrefix,
+            Fault::Error(_) | Fault::Latency(_) => true,
+        };
+        applicable
+            && self.remaining != Some(0)
+            && (self.operations.is_empty() || self.operations.contains(&operation))
+            && self
+                .prefix
+                .as_deref()
+                .is_none_or(|wanted| prefix.is_some_and(|prefix| prefix.starts_with(wanted)))
+    }
+}
+
+/// [`StateStore`] wrapper that injects failures, latency, dropped writes and partial batch
+/// deletes according to its [`FaultRule`]s.
+pub struct FaultyStateStore<S> {
+    inner: S,
+    state: Mutex<FaultState>,
+    injected: AtomicU64,
+}
+
+struct FaultState {
+    rules: Vec<FaultRule>,
+    rng: SplitMix64,
+}
+
+impl<S: StateStore> FaultyStateStore<S> {
+    /// Wraps `inner` without any rules; `seed` drives every probability roll.
+    pub fn new(inner: S, seed: u64) -> Self {
+        Self {
+            inner,
+            state: Mutex::new(FaultState {
+                rules: Vec::new(),
+                rng: SplitMix64(seed),
+            }),
+            injected: AtomicU64::new(0),
+        }
+    }
+
+    /// Appends `rule`; rules are consulted in the order they were added.
+    pub fn with_rule(self, rule: FaultRule) -> Self {
+        self.add_rule(rule);
+        self
+    }
+
+    /// Appends `rule` to a store that is already in use.
+    pub fn add_rule(&self, rule: FaultRule) {
+        self.state.lock().rules.push(rule);
+    }
+
+    /// Removes every rule, so calls pass straight through.
+    pub fn clear_rules(&self) {
+        self.state.lock().rules.clear();
+    }
+
+    /// Number of faults injected so far, latency included.
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Applies latency rules and returns the fault that decides the call, if any.
+    fn roll(&self, operation: FaultOperation, prefix: Option<&str>) -> Option<Fault> {
+        let mut delay = Duration::ZERO;
+        let mut decided = None;
+        {
+            let mut state = self.state.lock();
+            let FaultState { rules, rng } = &mut *state;
+            for rule in rules.iter_mut() {
+                if !rule.applies(operation, prefix) || !rng.hits(rule.probability) {
+                    continue;
+                }
+                if let Some(remaining) = rule.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                self.injected.fetch_add(1, Ordering::Relaxed);
+                debug!(?operation, prefix, fault = ?rule.fault, "injecting state store fault");
+                match &rule.fault {
+                    Fault::Latency(by) => delay += *by,
+                    fault => {
+                        decided = Some(fault.clone());
+                        break;
+                    }
+                }
+            }
+        }
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+        decided
+    }
+}
+
+impl<S: StateStore> StateStore for FaultyStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::Get, Some(prefix)) {
+            return Err(injected(code, FaultOperation::Get));
+        }
+        self.inner.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        match self.roll(FaultOperation::Set, Some(prefix)) {
+            Some(Fault::Error(code)) => Err(injected(code, FaultOperation::Set)),
+            Some(Fault::DropWrite) => Ok(()),
+            Some(Fault::ReplyLost) => {
+                self.inner
+                    .set_json(tenant, prefix, key, path, value, ttl_secs)?;
+                Err(injected(ErrorCode::Unavailable, FaultOperation::Set))
+            }
+            _ => self
+                .inner
+                .set_json(tenant, prefix, key, path, value, ttl_secs),
+        }
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        match self.roll(FaultOperation::SetIfAbsent, Some(prefix)) {
+            Some(Fault::Error(code)) => Err(injected(code, FaultOperation::SetIfAbsent)),
+            Some(Fault::DropWrite) => Ok(self.inner.get_json(tenant, prefix, key, None)?.is_none()),
+            Some(Fault::ReplyLost) => {
+                self.inner
+                    .set_if_absent(tenant, prefix, key, value, ttl_secs)?;
+                Err(injected(
+                    ErrorCode::Unavailable,
+                    FaultOperation::SetIfAbsent,
+                ))
+            }
+            _ => self
+                .inner
+                .set_if_absent(tenant, prefix, key, value, ttl_secs),
+        }
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        match self.roll(FaultOperation::Del, Some(prefix)) {
+            Some(Fault::Error(code)) => Err(injected(code, FaultOperation::Del)),
+            Some(Fault::DropWrite) => Ok(self.inner.get_json(tenant, prefix, key, None)?.is_some()),
+            Some(Fault::ReplyLost) => {
+                self.inner.del(tenant, prefix, key)?;
+                Err(injected(ErrorCode::Unavailable, FaultOperation::Del))
+            }
+            _ => self.inner.del(tenant, prefix, key),
+        }
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        match self.roll(FaultOperation::DelPrefix, Some(prefix)) {
+            Some(Fault::Error(code)) => Err(injected(code, FaultOperation::DelPrefix)),
+            Some(Fault::PartialBatch { completed }) => {
+                let keys = self.inner.list_keys(tenant, Some(prefix))?;
+                for scoped in keys.iter().take(completed) {
+                    self.inner.del(tenant, &scoped.prefix, &scoped.key)?;
+                }
+                Err(injected(ErrorCode::Unavailable, FaultOperation::DelPrefix))
+            }
+            _ => self.inner.del_prefix(tenant, prefix),
+        }
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        if let Some(Fault::Error(code)) =
+            self.roll(FaultOperation::DelPrefixRecursive, Some(prefix))
+        {
+            return Err(injected(code, FaultOperation::DelPrefixRecursive));
+        }
+        self.inner.del_prefix_recursive(tenant, prefix)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::PrefixStats, Some(prefix)) {
+            return Err(injected(code, FaultOperation::PrefixStats));
+        }
+        self.inner.prefix_stats(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::ListKeys, prefix) {
+            return Err(injected(code, FaultOperation::ListKeys));
+        }
+        self.inner.list_keys(tenant, prefix)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::Ttl, Some(prefix)) {
+            return Err(injected(code, FaultOperation::Ttl));
+        }
+        self.inner.ttl(tenant, prefix, key)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::Purge, None) {
+            return Err(injected(code, FaultOperation::Purge));
+        }
+        self.inner.purge_tenant(tenant)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::Purge, None) {
+            return Err(injected(code, FaultOperation::Purge));
+        }
+        self.inner.purge_team(tenant, team)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        if let Some(Fault::Error(code)) = self.roll(FaultOperation::Purge, None) {
+            return Err(injected(code, FaultOperation::Purge));
+        }
+        self.inner.purge_user(tenant, user)
+    }
+}
+
+fn injected(code: ErrorCode, operation: FaultOperation) -> GreenticError {
+    GreenticError::new(code, format!("injected fault in {operation:?}"))
+}
+
+/// Small deterministic generator (SplitMix64); plenty for fault rolls.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with the given probability. Certain outcomes do not consume a draw, so
+    /// adding an always-on rule does not reshuffle the rolls of the others.
+    fn hits(&mut self, probability: f64) -> bool {
+        if probability >= 1.0 {
+            return true;
+        }
+        if probability <= 0.0 {
+            return false;
+        }
+        let unit = (self.next() >> 11) as f64 / (1_u64 << 53) as f64;
+        unit < probability
+    }
+}
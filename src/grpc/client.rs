@@ -0,0 +1,384 @@
+use super::proto::state_service_client::StateServiceClient;
+use super::proto::{self, watch_event::Kind};
+use super::{StateEvent, StateEventKind, error_from_status, parse_json, tenant_to_proto};
+use crate::error::{from_serde, internal, unavailable, with_context};
+use crate::snapshot::SnapshotRecord;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use greentic_types::{GResult, StateKey, StatePath, TeamId, TenantCtx, UserId};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::runtime::Runtime;
+use tonic::Streaming;
+use tonic::transport::{Channel, Endpoint};
+
+/// Blocking [`StateStore`] client for a [`GrpcStateService`](super::GrpcStateService).
+///
+/// Calls block on a runtime owned by the client, so (like the Redis backend) they must not be
+/// made from inside an async task; use `spawn_blocking` there, or [`GrpcStateStore::client`].
+#[derive(Clone)]
+pub struct GrpcStateStore {
+    runtime: Arc<Runtime>,
+    client: StateServiceClient<Channel>,
+}
+
+impl GrpcStateStore {
+    /// Connects to `endpoint` (e.g. `http://state:50051`) with a 10s request timeout.
+    pub fn connect(endpoint: impl Into<String>) -> GResult<Self> {
+        Self::connect_with_timeout(endpoint, Duration::from_secs(10))
+    }
+
+    /// Connects to `endpoint`, bounding connection setup and each unary call by `timeout`.
+    pub fn connect_with_timeout(endpoint: impl Into<String>, timeout: Duration) -> GResult<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(|err| with_context(err, "start grpc client runtime"))?;
+        let endpoint = Endpoint::from_shared(endpoint.into())
+            .map_err(|err| with_context(err, "grpc endpoint"))?
+            .connect_timeout(timeout)
+            .timeout(timeout);
+        let channel = runtime
+            .block_on(endpoint.connect())
+            .map_err(|err| unavailable(format!("connect to grpc state service: {err}")))?;
+        Ok(Self {
+            runtime: Arc::new(runtime),
+            client: StateServiceClient::new(channel),
+        })
+    }
+
+    /// Returns the underlying async tonic client, for callers already running on tokio.
+    pub fn client(&self) -> StateServiceClient<Channel> {
+        self.client.clone()
+    }
+
+    /// Streams every live entry of `tenant`, optionally under one `prefix`, with values and TTLs.
+    pub fn scan(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<ScanStream> {
+        let request = proto::ScanRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.map(str::to_owned),
+        };
+        let mut client = self.client.clone();
+        let stream = self
+            .runtime
+            .block_on(client.scan(request))
+            .map_err(error_from_status)?
+            .into_inner();
+        Ok(ScanStream {
+            runtime: Arc::clone(&self.runtime),
+            tenant: tenant_to_proto(tenant),
+            stream,
+        })
+    }
+
+    /// Subscribes to writes made through the service for `tenant`, optionally under one `prefix`.
+    pub fn watch(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<WatchStream> {
+        let request = proto::WatchRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.map(str::to_owned),
+        };
+        let mut client = self.client.clone();
+        let stream = self
+            .runtime
+            .block_on(client.watch(request))
+            .map_err(error_from_status)?
+            .into_inner();
+        Ok(WatchStream {
+            runtime: Arc::clone(&self.runtime),
+            stream,
+        })
+    }
+}
+
+/// Blocking iterator over a [`GrpcStateStore::scan`] response.
+pub struct ScanStream {
+    runtime: Arc<Runtime>,
+    tenant: proto::Tenant,
+    stream: Streaming<proto::ScanEntry>,
+}
+
+impl Iterator for ScanStream {
+    type Item = GResult<SnapshotRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = match self.runtime.block_on(self.stream.message()) {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return None,
+            Err(status) => return Some(Err(error_from_status(status))),
+        };
+        Some(parse_json(&entry.value_json).map(|value| SnapshotRecord {
+            env: self.tenant.env.clone(),
+            tenant: self.tenant.tenant.clone(),
+            team: self.tenant.team.clone(),
+            user: self.tenant.user.clone(),
+            prefix: entry.prefix,
+            key: entry.key,
+            value,
+            ttl_secs: entry.ttl_secs,
+        }))
+    }
+}
+
+/// Blocking iterator over a [`GrpcStateStore::watch`] subscription; ends when the server does.
+pub struct WatchStream {
+    runtime: Arc<Runtime>,
+    stream: Streaming<proto::WatchEvent>,
+}
+
+impl Iterator for WatchStream {
+    type Item = GResult<StateEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = match self.runtime.block_on(self.stream.message()) {
+            Ok(Some(event)) => event,
+            Ok(None) => return None,
+            Err(status) => return Some(Err(error_from_status(status))),
+        };
+        Some(decode_event(event))
+    }
+}
+
+fn decode_event(event: proto::WatchEvent) -> GResult<StateEvent> {
+    let kind = match event.kind() {
+        Kind::Set => StateEventKind::Set,
+        Kind::Deleted => StateEventKind::Deleted,
+        Kind::PrefixDeleted => StateEventKind::PrefixDeleted,
+        Kind::Unspecified => return Err(internal("watch event without a kind")),
+    };
+    Ok(StateEvent {
+        kind,
+        key: (kind != StateEventKind::PrefixDeleted).then(|| StateKey::new(event.key)),
+        prefix: event.prefix,
+        path: event.path.as_deref().map(StatePath::from_pointer),
+        value: event.value_json.as_deref().map(parse_json).transpose()?,
+    })
+}
+
+impl StateStore for GrpcStateStore {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        let request = proto::GetRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+            key: key.as_str().to_owned(),
+            path: path.map(StatePath::to_pointer),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.get(request))
+            .map_err(error_from_status)?
+            .into_inner();
+        response.value_json.as_deref().map(parse_json).transpose()
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let request = proto::SetRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+            key: key.as_str().to_owned(),
+            path: path.map(StatePath::to_pointer),
+            value_json: serde_json::to_string(value).map_err(from_serde)?,
+            ttl_secs,
+        };
+        let mut client = self.client.clone();
+        self.runtime
+            .block_on(client.set(request))
+            .map_err(error_from_status)?;
+        Ok(())
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let request = proto::SetIfAbsentRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+            key: key.as_str().to_owned(),
+            value_json: serde_json::to_string(value).map_err(from_serde)?,
+            ttl_secs,
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.set_if_absent(request))
+            .map_err(error_from_status)?;
+        Ok(response.into_inner().written)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let request = proto::DelRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+            key: key.as_str().to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.del(request))
+            .map_err(error_from_status)?;
+        Ok(response.into_inner().deleted)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let request = proto::DelPrefixRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.del_prefix(request))
+            .map_err(error_from_status)?;
+        Ok(response.into_inner().deleted)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let request = proto::DelPrefixRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.del_prefix_recursive(request))
+            .map_err(error_from_status)?;
+        Ok(response.into_inner().deleted)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let request = proto::PrefixStatsRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.prefix_stats(request))
+            .map_err(error_from_status)?;
+        prefix_stats(response.into_inner())
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let request = proto::ListKeysRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.map(str::to_owned),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.list_keys(request))
+            .map_err(error_from_status)?;
+        Ok(response
+            .into_inner()
+            .keys
+            .into_iter()
+            .map(|entry| ScopedKey {
+                prefix: entry.prefix,
+                key: StateKey::new(entry.key),
+            })
+            .collect())
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let request = proto::PurgeTenantRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.purge_tenant(request))
+            .map_err(error_from_status)?;
+        purge_report(response.into_inner())
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let request = proto::PurgeTeamRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            team: team.as_str().to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.purge_team(request))
+            .map_err(error_from_status)?;
+        purge_report(response.into_inner())
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let request = proto::PurgeUserRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            user: user.as_str().to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.purge_user(request))
+            .map_err(error_from_status)?;
+        purge_report(response.into_inner())
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let request = proto::TtlRequest {
+            tenant: Some(tenant_to_proto(tenant)),
+            prefix: prefix.to_owned(),
+            key: key.as_str().to_owned(),
+        };
+        let mut client = self.client.clone();
+        let response = self
+            .runtime
+            .block_on(client.ttl(request))
+            .map_err(error_from_status)?
+            .into_inner();
+        Ok(response.found.then(|| match response.remaining_ms {
+            Some(ms) => StateTtl::ExpiresIn(Duration::from_millis(ms)),
+            None => StateTtl::Persistent,
+        }))
+    }
+}
+
+fn purge_report(response: proto::PurgeResponse) -> GResult<PurgeReport> {
+    let completed_at = OffsetDateTime::parse(&response.completed_at, &Rfc3339)
+        .map_err(|err| with_context(err, "parse purge time"))?;
+    Ok(PurgeReport {
+        scope: response.scope,
+        deleted: response.deleted,
+        completed_at,
+    })
+}
+
+fn prefix_stats(response: proto::PrefixStatsResponse) -> GResult<PrefixStats> {
+    let parse = |at: Option<String>| {
+        at.map(|at| OffsetDateTime::parse(&at, &Rfc3339))
+            .transpose()
+            .map_err(|err| with_context(err, "parse expiry time"))
+    };
+    Ok(PrefixStats {
+        keys: response.keys,
+        total_bytes: response.total_bytes,
+        persistent: response.persistent,
+        earliest_expiry: parse(response.earliest_expiry)?,
+        latest_expiry: parse(response.latest_expiry)?,
+    })
+}
@@ -0,0 +1,104 @@
+//! gRPC access to any [`StateStore`](crate::StateStore): a tonic service with streaming `scan`
+//! and `watch`, and a blocking [`GrpcStateStore`] client.
+//!
+//! The service definition lives in `proto/greentic/state/v1/state.proto`. Documents travel as
+//! JSON text, and errors map onto gRPC status codes in both directions.
+
+mod client;
+mod server;
+
+pub use client::{GrpcStateStore, ScanStream, WatchStream};
+pub use server::{GrpcStateService, serve};
+
+/// Generated protobuf messages and tonic stubs.
+pub mod proto {
+    tonic::include_proto!("greentic.state.v1");
+}
+
+use crate::error::invalid_input;
+use crate::key::{scope_members, tenant_from_ids};
+use greentic_types::{ErrorCode, GResult, GreenticError, StateKey, StatePath, TenantCtx};
+use serde_json::Value;
+use tonic::{Code, Status};
+
+/// What a [`StateEvent`] reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateEventKind {
+    /// A document (or a path inside it) was written.
+    Set,
+    /// A single key was deleted.
+    Deleted,
+    /// Every key under a prefix was deleted.
+    PrefixDeleted,
+}
+
+/// A write observed through [`GrpcStateStore::watch`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct StateEvent {
+    /// Kind of write.
+    pub kind: StateEventKind,
+    /// Prefix the write targeted.
+    pub prefix: String,
+    /// Key the write targeted; `None` for [`StateEventKind::PrefixDeleted`].
+    pub key: Option<StateKey>,
+    /// JSON Pointer of a partial write.
+    pub path: Option<StatePath>,
+    /// Value written, for [`StateEventKind::Set`].
+    pub value: Option<Value>,
+}
+
+fn tenant_to_proto(tenant: &TenantCtx) -> proto::Tenant {
+    let (team, user) = scope_members(tenant);
+    proto::Tenant {
+        env: tenant.env.as_str().to_owned(),
+        tenant: tenant.tenant_id.as_str().to_owned(),
+        team: team.map(str::to_owned),
+        user: user.map(str::to_owned),
+    }
+}
+
+fn tenant_from_proto(tenant: Option<proto::Tenant>) -> GResult<TenantCtx> {
+    let tenant = tenant.ok_or_else(|| invalid_input("request is missing the tenant"))?;
+    tenant_from_ids(
+        &tenant.env,
+        &tenant.tenant,
+        tenant.team.as_deref(),
+        tenant.user.as_deref(),
+    )
+}
+
+fn parse_json(raw: &str) -> GResult<Value> {
+    serde_json::from_str(raw).map_err(|err| invalid_input(format!("value is not JSON: {err}")))
+}
+
+fn status_from_error(err: GreenticError) -> Status {
+    let code = match err.code {
+        ErrorCode::InvalidInput => Code::InvalidArgument,
+        ErrorCode::NotFound => Code::NotFound,
+        ErrorCode::Conflict => Code::Aborted,
+        ErrorCode::Timeout => Code::DeadlineExceeded,
+        ErrorCode::Unauthenticated => Code::Unauthenticated,
+        ErrorCode::PermissionDenied => Code::PermissionDenied,
+        ErrorCode::RateLimited => Code::ResourceExhausted,
+        ErrorCode::Unavailable => Code::Unavailable,
+        ErrorCode::Internal => Code::Internal,
+        ErrorCode::Unknown => Code::Unknown,
+    };
+    Status::new(code, err.message)
+}
+
+fn error_from_status(status: Status) -> GreenticError {
+    let code = match status.code() {
+        Code::InvalidArgument | Code::OutOfRange => ErrorCode::InvalidInput,
+        Code::NotFound => ErrorCode::NotFound,
+        Code::Aborted | Code::AlreadyExists | Code::FailedPrecondition => ErrorCode::Conflict,
+        Code::DeadlineExceeded => ErrorCode::Timeout,
+        Code::Unauthenticated => ErrorCode::Unauthenticated,
+        Code::PermissionDenied => ErrorCode::PermissionDenied,
+        Code::ResourceExhausted => ErrorCode::RateLimited,
+        Code::Unavailable | Code::Cancelled => ErrorCode::Unavailable,
+        Code::Internal | Code::DataLoss | Code::Unimplemented => ErrorCode::Internal,
+        Code::Ok | Code::Unknown => ErrorCode::Unknown,
+    };
+    GreenticError::new(code, status.message().to_owned())
+}
@@ -0,0 +1,446 @@
+use super::proto::state_service_server::{StateService, StateServiceServer};
+use super::proto::{self, watch_event::Kind};
+use super::{parse_json, status_from_error, tenant_from_proto};
+use crate::error::{from_serde, internal, invalid_input, with_context};
+use crate::key::tenant_scope;
+use crate::snapshot;
+use crate::store::{PrefixStats, PurgeReport, StateStore, StateTtl};
+use greentic_types::{GResult, StateKey, StatePath, TeamId, UserId};
+use std::pin::Pin;
+use std::sync::Arc;
+use time::format_description::well_known::Rfc3339;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream, TcpListenerStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+type SharedStore = Arc<dyn StateStore>;
+type ServiceStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Number of unread watch events a slow subscriber may lag behind before it is disconnected.
+const WATCH_BUFFER: usize = 1024;
+/// Number of scanned entries buffered ahead of the client.
+const SCAN_BUFFER: usize = 64;
+
+/// A write made through the service, tagged with the tenant scope it belongs to.
+#[derive(Clone)]
+struct ScopedEvent {
+    scope: String,
+    event: proto::WatchEvent,
+}
+
+/// tonic service exposing a [`StateStore`].
+///
+/// `watch` reports writes made through this service instance; writes that reach the backend by
+/// other routes, and purges, are not observed.
+#[derive(Clone)]
+pub struct GrpcStateService {
+    store: SharedStore,
+    events: broadcast::Sender<ScopedEvent>,
+}
+
+impl GrpcStateService {
+    /// Wraps `store`.
+    pub fn new(store: SharedStore) -> Self {
+        let (events, _) = broadcast::channel(WATCH_BUFFER);
+        Self { store, events }
+    }
+
+    /// Returns the tonic server to add to a `tonic::transport::Server`.
+    pub fn into_server(self) -> StateServiceServer<Self> {
+        StateServiceServer::new(self)
+    }
+
+    async fn blocking<T, F>(&self, call: F) -> Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn StateStore) -> GResult<T> + Send + 'static,
+    {
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || call(store.as_ref()))
+            .await
+            .map_err(|err| status_from_error(internal(format!("state task failed: {err}"))))?
+            .map_err(status_from_error)
+    }
+
+    fn publish(&self, scope: String, event: proto::WatchEvent) {
+        // No receivers simply means nobody is watching.
+        let _ = self.events.send(ScopedEvent { scope, event });
+    }
+}
+
+fn purge_response(report: PurgeReport) -> Result<Response<proto::PurgeResponse>, Status> {
+    let completed_at = report
+        .completed_at
+        .format(&Rfc3339)
+        .map_err(|err| status_from_error(with_context(err, "format purge time")))?;
+    Ok(Response::new(proto::PurgeResponse {
+        scope: report.scope,
+        deleted: report.deleted,
+        completed_at,
+    }))
+}
+
+fn prefix_stats_response(
+    stats: PrefixStats,
+) -> Result<Response<proto::PrefixStatsResponse>, Status> {
+    let format = |at: Option<time::OffsetDateTime>| {
+        at.map(|at| at.format(&Rfc3339))
+            .transpose()
+            .map_err(|err| status_from_error(with_context(err, "format expiry time")))
+    };
+    Ok(Response::new(proto::PrefixStatsResponse {
+        keys: stats.keys,
+        total_bytes: stats.total_bytes,
+        persistent: stats.persistent,
+        earliest_expiry: format(stats.earliest_expiry)?,
+        latest_expiry: format(stats.latest_expiry)?,
+    }))
+}
+
+/// Serves `store` over gRPC on `listener` until the server fails.
+pub async fn serve(listener: TcpListener, store: SharedStore) -> GResult<()> {
+    tonic::transport::Server::builder()
+        .add_service(GrpcStateService::new(store).into_server())
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await
+        .map_err(|err| with_context(err, "serve grpc"))
+}
+
+#[tonic::async_trait]
+impl StateService for GrpcStateService {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::GetResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let value = self
+            .blocking(move |store| {
+                let path = request.path.as_deref().map(StatePath::from_pointer);
+                store.get_json(
+                    &tenant,
+                    &request.prefix,
+                    &StateKey::new(request.key),
+                    path.as_ref(),
+                )
+            })
+            .await?;
+        let value_json = value
+            .map(|value| serde_json::to_string(&value).map_err(from_serde))
+            .transpose()
+            .map_err(status_from_error)?;
+        Ok(Response::new(proto::GetResponse { value_json }))
+    }
+
+    async fn set(
+        &self,
+        request: Request<proto::SetRequest>,
+    ) -> Result<Response<proto::SetResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant.clone()).map_err(status_from_error)?;
+        let value = parse_json(&request.value_json).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let (prefix, key, path) = (
+            request.prefix.clone(),
+            request.key.clone(),
+            request.path.clone(),
+        );
+        self.blocking(move |store| {
+            let path = path.as_deref().map(StatePath::from_pointer);
+            store.set_json(
+                &tenant,
+                &prefix,
+                &StateKey::new(key),
+                path.as_ref(),
+                &value,
+                request.ttl_secs,
+            )
+        })
+        .await?;
+        self.publish(
+            scope,
+            proto::WatchEvent {
+                kind: Kind::Set.into(),
+                prefix: request.prefix,
+                key: request.key,
+                path: request.path,
+                value_json: Some(request.value_json),
+            },
+        );
+        Ok(Response::new(proto::SetResponse {}))
+    }
+
+    async fn set_if_absent(
+        &self,
+        request: Request<proto::SetIfAbsentRequest>,
+    ) -> Result<Response<proto::SetIfAbsentResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant.clone()).map_err(status_from_error)?;
+        let value = parse_json(&request.value_json).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let (prefix, key) = (request.prefix.clone(), request.key.clone());
+        let written = self
+            .blocking(move |store| {
+                store.set_if_absent(
+                    &tenant,
+                    &prefix,
+                    &StateKey::new(key),
+                    &value,
+                    request.ttl_secs,
+                )
+            })
+            .await?;
+        if written {
+            self.publish(
+                scope,
+                proto::WatchEvent {
+                    kind: Kind::Set.into(),
+                    prefix: request.prefix,
+                    key: request.key,
+                    path: None,
+                    value_json: Some(request.value_json),
+                },
+            );
+        }
+        Ok(Response::new(proto::SetIfAbsentResponse { written }))
+    }
+
+    async fn del(
+        &self,
+        request: Request<proto::DelRequest>,
+    ) -> Result<Response<proto::DelResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let (prefix, key) = (request.prefix.clone(), request.key.clone());
+        let deleted = self
+            .blocking(move |store| store.del(&tenant, &prefix, &StateKey::new(key)))
+            .await?;
+        if deleted {
+            self.publish(
+                scope,
+                proto::WatchEvent {
+                    kind: Kind::Deleted.into(),
+                    prefix: request.prefix,
+                    key: request.key,
+                    ..proto::WatchEvent::default()
+                },
+            );
+        }
+        Ok(Response::new(proto::DelResponse { deleted }))
+    }
+
+    async fn del_prefix(
+        &self,
+        request: Request<proto::DelPrefixRequest>,
+    ) -> Result<Response<proto::DelPrefixResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let prefix = request.prefix.clone();
+        let deleted = self
+            .blocking(move |store| store.del_prefix(&tenant, &prefix))
+            .await?;
+        if deleted > 0 {
+            self.publish(
+                scope,
+                proto::WatchEvent {
+                    kind: Kind::PrefixDeleted.into(),
+                    prefix: request.prefix,
+                    ..proto::WatchEvent::default()
+                },
+            );
+        }
+        Ok(Response::new(proto::DelPrefixResponse { deleted }))
+    }
+
+    async fn del_prefix_recursive(
+        &self,
+        request: Request<proto::DelPrefixRequest>,
+    ) -> Result<Response<proto::DelPrefixResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let prefix = request.prefix.clone();
+        let deleted = self
+            .blocking(move |store| store.del_prefix_recursive(&tenant, &prefix))
+            .await?;
+        if deleted > 0 {
+            self.publish(
+                scope,
+                proto::WatchEvent {
+                    kind: Kind::PrefixDeleted.into(),
+                    prefix: request.prefix,
+                    ..proto::WatchEvent::default()
+                },
+            );
+        }
+        Ok(Response::new(proto::DelPrefixResponse { deleted }))
+    }
+
+    async fn prefix_stats(
+        &self,
+        request: Request<proto::PrefixStatsRequest>,
+    ) -> Result<Response<proto::PrefixStatsResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let stats = self
+            .blocking(move |store| store.prefix_stats(&tenant, &request.prefix))
+            .await?;
+        prefix_stats_response(stats)
+    }
+
+    async fn list_keys(
+        &self,
+        request: Request<proto::ListKeysRequest>,
+    ) -> Result<Response<proto::ListKeysResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let keys = self
+            .blocking(move |store| store.list_keys(&tenant, request.prefix.as_deref()))
+            .await?;
+        let keys = keys
+            .into_iter()
+            .map(|entry| proto::ScopedKey {
+                prefix: entry.prefix,
+                key: entry.key.as_str().to_owned(),
+            })
+            .collect();
+        Ok(Response::new(proto::ListKeysResponse { keys }))
+    }
+
+    async fn ttl(
+        &self,
+        request: Request<proto::TtlRequest>,
+    ) -> Result<Response<proto::TtlResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let ttl = self
+            .blocking(move |store| store.ttl(&tenant, &request.prefix, &StateKey::new(request.key)))
+            .await?;
+        let response = match ttl {
+            None => proto::TtlResponse::default(),
+            Some(StateTtl::Persistent) => proto::TtlResponse {
+                found: true,
+                remaining_ms: None,
+            },
+            Some(StateTtl::ExpiresIn(remaining)) => proto::TtlResponse {
+                found: true,
+                remaining_ms: Some(u64::try_from(remaining.as_millis()).unwrap_or(u64::MAX)),
+            },
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn purge_tenant(
+        &self,
+        request: Request<proto::PurgeTenantRequest>,
+    ) -> Result<Response<proto::PurgeResponse>, Status> {
+        let tenant = tenant_from_proto(request.into_inner().tenant).map_err(status_from_error)?;
+        let report = self
+            .blocking(move |store| store.purge_tenant(&tenant))
+            .await?;
+        purge_response(report)
+    }
+
+    async fn purge_team(
+        &self,
+        request: Request<proto::PurgeTeamRequest>,
+    ) -> Result<Response<proto::PurgeResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let team = TeamId::try_from(request.team.as_str()).map_err(|err| {
+            status_from_error(invalid_input(format!("team `{}`: {err}", request.team)))
+        })?;
+        let report = self
+            .blocking(move |store| store.purge_team(&tenant, &team))
+            .await?;
+        purge_response(report)
+    }
+
+    async fn purge_user(
+        &self,
+        request: Request<proto::PurgeUserRequest>,
+    ) -> Result<Response<proto::PurgeResponse>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let user = UserId::try_from(request.user.as_str()).map_err(|err| {
+            status_from_error(invalid_input(format!("user `{}`: {err}", request.user)))
+        })?;
+        let report = self
+            .blocking(move |store| store.purge_user(&tenant, &user))
+            .await?;
+        purge_response(report)
+    }
+
+    type ScanStream = ServiceStream<proto::ScanEntry>;
+
+    async fn scan(
+        &self,
+        request: Request<proto::ScanRequest>,
+    ) -> Result<Response<Self::ScanStream>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let (sender, receiver) = mpsc::channel(SCAN_BUFFER);
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || {
+            let records = match snapshot::export(store.as_ref(), &tenant, request.prefix.as_deref())
+            {
+                Ok(records) => records,
+                Err(err) => {
+                    let _ = sender.blocking_send(Err(status_from_error(err)));
+                    return;
+                }
+            };
+            for record in records {
+                let entry = record.and_then(|record| {
+                    Ok(proto::ScanEntry {
+                        value_json: serde_json::to_string(&record.value).map_err(from_serde)?,
+                        prefix: record.prefix,
+                        key: record.key,
+                        ttl_secs: record.ttl_secs,
+                    })
+                });
+                // A closed channel means the client went away.
+                if sender
+                    .blocking_send(entry.map_err(status_from_error))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    type WatchStream = ServiceStream<proto::WatchEvent>;
+
+    async fn watch(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let request = request.into_inner();
+        let tenant = tenant_from_proto(request.tenant).map_err(status_from_error)?;
+        let scope = tenant_scope(&tenant);
+        let prefix = request.prefix;
+        let events =
+            BroadcastStream::new(self.events.subscribe()).filter_map(move |event| match event {
+                Ok(ScopedEvent {
+                    scope: ref event_scope,
+                    ref event,
+                }) if *event_scope == scope
+                    && prefix.as_ref().is_none_or(|prefix| *prefix == event.prefix) =>
+                {
+                    Some(Ok(event.clone()))
+                }
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(missed)) => Some(Err(Status::data_loss(
+                    format!("watch fell {missed} events behind; resubscribe"),
+                ))),
+            });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
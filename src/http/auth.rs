@@ -0,0 +1,38 @@
+use super::server::ApiError;
+use axum::extract::{Request, State};
+use axum::http::request::Parts;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use greentic_types::{GResult, TenantCtx};
+use std::sync::Arc;
+
+/// Decides which tenant an HTTP request acts on, e.g. by validating a bearer token.
+///
+/// Installed with [`authenticated_router`](super::authenticated_router), it runs before every
+/// route; the tenant it returns replaces the one named by the `x-greentic-*` headers.
+/// Resolvers run on the async executor, so they should not block.
+pub trait TenantResolver: Send + Sync + 'static {
+    /// Returns the tenant `request` may act on. Reject the request with an `Unauthenticated` or
+    /// `PermissionDenied` error, which is answered with the matching status.
+    fn resolve(&self, request: &Parts) -> GResult<TenantCtx>;
+}
+
+/// Request extension carrying the tenant returned by the [`TenantResolver`].
+#[derive(Clone)]
+pub(super) struct ResolvedTenant(pub(super) TenantCtx);
+
+/// Middleware resolving the tenant of each request before it reaches a route.
+pub(super) async fn resolve_tenant(
+    State(resolver): State<Arc<dyn TenantResolver>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+    match resolver.resolve(&parts) {
+        Ok(tenant) => {
+            parts.extensions.insert(ResolvedTenant(tenant));
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(err) => ApiError::from(err).into_response(),
+    }
+}
@@ -0,0 +1,309 @@
+use super::{TTL_MS_HEADER, TTL_SECS_HEADER, tenant_headers};
+use crate::error::{from_serde, unavailable, with_context};
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use greentic_types::{
+    ErrorCode, GResult, GreenticError, StateKey, StatePath, TeamId, TenantCtx, UserId,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use ureq::http::{Response, StatusCode};
+use ureq::{Agent, Body, RequestBuilder};
+
+/// [`StateStore`] backed by a remote state service (see [`router`](super::router)).
+#[derive(Clone)]
+pub struct RemoteStateStore {
+    base_url: String,
+    agent: Agent,
+    authorization: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ListedKey {
+    prefix: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct Deleted<T> {
+    deleted: T,
+}
+
+#[derive(Deserialize)]
+struct Written {
+    written: bool,
+}
+
+impl RemoteStateStore {
+    /// Connects to the service at `base_url` (e.g. `http://state:8080`) with a 10s timeout.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self::with_timeout(base_url, Duration::from_secs(10))
+    }
+
+    /// Connects to the service at `base_url`, bounding each request by `timeout`.
+    pub fn with_timeout(base_url: impl Into<String>, timeout: Duration) -> Self {
+        let agent = Agent::config_builder()
+            .timeout_global(Some(timeout))
+            .http_status_as_error(false)
+            .build()
+            .into();
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_owned(),
+            agent,
+            authorization: None,
+        }
+    }
+
+    /// Sends `token` as an `Authorization: Bearer` header with every request, for servers
+    /// running a [`TenantResolver`](super::TenantResolver).
+    pub fn with_bearer_token(mut self, token: impl AsRef<str>) -> Self {
+        self.authorization = Some(format!("Bearer {}", token.as_ref()));
+        self
+    }
+
+    fn url(&self, route: &str) -> String {
+        format!("{}{route}", self.base_url)
+    }
+
+    fn scoped<B>(&self, request: RequestBuilder<B>, tenant: &TenantCtx) -> RequestBuilder<B> {
+        let request = match &self.authorization {
+            Some(authorization) => request.header("authorization", authorization),
+            None => request,
+        };
+        tenant_headers(tenant)
+            .into_iter()
+            .fold(request, |request, (name, value)| {
+                request.header(name, value)
+            })
+    }
+
+    fn delete_prefix(&self, tenant: &TenantCtx, prefix: &str, recursive: bool) -> GResult<u64> {
+        let mut request = self
+            .agent
+            .delete(self.url("/v1/prefixes"))
+            .query("prefix", prefix);
+        if recursive {
+            request = request.query("recursive", "true");
+        }
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        read_json::<Deleted<u64>>(&mut response).map(|body| body.deleted)
+    }
+
+    fn purge(&self, tenant: &TenantCtx, route: &str) -> GResult<PurgeReport> {
+        let request = self.agent.delete(self.url(route));
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        read_json(&mut response)
+    }
+}
+
+fn transport(err: ureq::Error) -> GreenticError {
+    match err {
+        ureq::Error::Timeout(_) => GreenticError::new(
+            ErrorCode::Timeout,
+            format!("state service request timed out: {err}"),
+        ),
+        other => unavailable(format!("state service request failed: {other}")),
+    }
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(response: &mut Response<Body>) -> GResult<T> {
+    let text = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|err| with_context(err, "read state service response"))?;
+    serde_json::from_str(&text).map_err(from_serde)
+}
+
+/// Turns a non-success response into the error the server reported.
+fn into_error(mut response: Response<Body>) -> GreenticError {
+    let status = response.status();
+    read_json::<GreenticError>(&mut response).unwrap_or_else(|_| {
+        GreenticError::new(
+            ErrorCode::Unavailable,
+            format!("state service answered {status}"),
+        )
+    })
+}
+
+impl StateStore for RemoteStateStore {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        let mut request = self
+            .agent
+            .get(self.url("/v1/state"))
+            .query("prefix", prefix)
+            .query("key", key.as_str());
+        if let Some(path) = path {
+            request = request.query("path", path.to_pointer());
+        }
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        match response.status() {
+            StatusCode::OK => read_json(&mut response).map(Some),
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(into_error(response)),
+        }
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let mut request = self
+            .agent
+            .put(self.url("/v1/state"))
+            .query("prefix", prefix)
+            .query("key", key.as_str())
+            .header("content-type", "application/json");
+        if let Some(path) = path {
+            request = request.query("path", path.to_pointer());
+        }
+        if let Some(ttl) = ttl_secs {
+            request = request.header(TTL_SECS_HEADER, ttl.to_string());
+        }
+        let body = serde_json::to_vec(value).map_err(from_serde)?;
+        let response = self
+            .scoped(request, tenant)
+            .send(&body[..])
+            .map_err(transport)?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(into_error(response))
+        }
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let mut request = self
+            .agent
+            .post(self.url("/v1/state"))
+            .query("prefix", prefix)
+            .query("key", key.as_str())
+            .header("content-type", "application/json");
+        if let Some(ttl) = ttl_secs {
+            request = request.header(TTL_SECS_HEADER, ttl.to_string());
+        }
+        let body = serde_json::to_vec(value).map_err(from_serde)?;
+        let mut response = self
+            .scoped(request, tenant)
+            .send(&body[..])
+            .map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        read_json::<Written>(&mut response).map(|body| body.written)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let request = self
+            .agent
+            .delete(self.url("/v1/state"))
+            .query("prefix", prefix)
+            .query("key", key.as_str());
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        read_json::<Deleted<bool>>(&mut response).map(|body| body.deleted)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.delete_prefix(tenant, prefix, false)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.delete_prefix(tenant, prefix, true)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let request = self
+            .agent
+            .get(self.url("/v1/prefixes"))
+            .query("prefix", prefix);
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        read_json(&mut response)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let mut request = self.agent.get(self.url("/v1/keys"));
+        if let Some(prefix) = prefix {
+            request = request.query("prefix", prefix);
+        }
+        let mut response = self.scoped(request, tenant).call().map_err(transport)?;
+        if !response.status().is_success() {
+            return Err(into_error(response));
+        }
+        let keys: Vec<ListedKey> = read_json(&mut response)?;
+        Ok(keys
+            .into_iter()
+            .map(|entry| ScopedKey {
+                prefix: entry.prefix,
+                key: StateKey::new(entry.key.as_str()),
+            })
+            .collect())
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        self.purge(tenant, "/v1/tenant")
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        self.purge(tenant, &format!("/v1/teams/{}", team.as_str()))
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        self.purge(tenant, &format!("/v1/users/{}", user.as_str()))
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let request = self
+            .agent
+            .head(self.url("/v1/state"))
+            .query("prefix", prefix)
+            .query("key", key.as_str());
+        let response = self.scoped(request, tenant).call().map_err(transport)?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => {
+                let remaining = response
+                    .headers()
+                    .get(TTL_MS_HEADER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse::<u64>().ok());
+                Ok(Some(match remaining {
+                    Some(ms) => StateTtl::ExpiresIn(Duration::from_millis(ms)),
+                    None => StateTtl::Persistent,
+                }))
+            }
+            status => Err(GreenticError::new(
+                ErrorCode::Unavailable,
+                format!("state service answered {status}"),
+            )),
+        }
+    }
+}
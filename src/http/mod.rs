@@ -0,0 +1,81 @@
+//! HTTP/JSON access to any [`StateStore`](crate::StateStore): an axum server and a blocking
+//! [`RemoteStateStore`] client.
+//!
+//! | Method   | Route          | Query                        | Result                          |
+//! |----------|----------------|------------------------------|---------------------------------|
+//! | `GET`    | `/v1/state`    | `prefix`, `key`, `path?`     | document (404 when missing)     |
+//! | `HEAD`   | `/v1/state`    | `prefix`, `key`              | TTL header (404 when missing)   |
+//! | `PUT`    | `/v1/state`    | `prefix`, `key`, `path?`     | 204; TTL from the request header |
+//! | `POST`   | `/v1/state`    | `prefix`, `key`              | `{"written": bool}` (`set_if_absent`) |
+//! | `DELETE` | `/v1/state`    | `prefix`, `key`              | `{"deleted": bool}`             |
+//! | `DELETE` | `/v1/prefixes` | `prefix`, `recursive?`       | `{"deleted": n}`                |
+//! | `GET`    | `/v1/prefixes` | `prefix`                     | [`PrefixStats`](crate::PrefixStats) |
+//! | `GET`    | `/v1/keys`     | `prefix?`                    | `[{"prefix", "key"}]`           |
+//! | `DELETE` | `/v1/tenant`   |                              | [`PurgeReport`](crate::PurgeReport) |
+//! | `DELETE` | `/v1/teams/{team}` |                          | [`PurgeReport`](crate::PurgeReport) |
+//! | `DELETE` | `/v1/users/{user}` |                          | [`PurgeReport`](crate::PurgeReport) |
+//!
+//! `recursive=true` deletes sub-prefixes too ([`del_prefix_recursive`]); the stats route covers
+//! sub-prefixes the same way ([`prefix_stats`]).
+//!
+//! [`del_prefix_recursive`]: crate::StateStore::del_prefix_recursive
+//! [`prefix_stats`]: crate::StateStore::prefix_stats
+//!
+//! The tenant comes from the `x-greentic-env`, `x-greentic-tenant`, `x-greentic-team` and
+//! `x-greentic-user` headers; the purge routes only use the environment and tenant. Errors are
+//! returned as the serialized `GreenticError`.
+//!
+//! Headers are not authenticated, so [`router`] keeps the purge routes disabled. Serve
+//! [`authenticated_router`] with a [`TenantResolver`] to decide the tenant of each request
+//! (for example from a bearer token) and enable them.
+
+mod auth;
+mod client;
+mod server;
+
+pub use auth::TenantResolver;
+pub use client::RemoteStateStore;
+pub use server::{authenticated_router, router, serve};
+
+use crate::error::invalid_input;
+use crate::key::{scope_members, tenant_from_ids};
+use greentic_types::{GResult, TenantCtx};
+
+/// Header carrying the environment id.
+pub const ENV_HEADER: &str = "x-greentic-env";
+/// Header carrying the tenant id.
+pub const TENANT_HEADER: &str = "x-greentic-tenant";
+/// Optional header carrying the team id.
+pub const TEAM_HEADER: &str = "x-greentic-team";
+/// Optional header carrying the user id.
+pub const USER_HEADER: &str = "x-greentic-user";
+/// `PUT` and `POST` request header with the TTL in seconds. For `PUT`, `0` clears the TTL and
+/// an absent header keeps it; `POST` writes a persistent entry without it.
+pub const TTL_SECS_HEADER: &str = "x-greentic-ttl-secs";
+/// `HEAD` response header with the remaining TTL in milliseconds; absent for persistent entries.
+pub const TTL_MS_HEADER: &str = "x-greentic-ttl-ms";
+
+/// Header values describing `tenant`, in the order of the header constants above.
+pub(crate) fn tenant_headers(tenant: &TenantCtx) -> Vec<(&'static str, &str)> {
+    let (team, user) = scope_members(tenant);
+    let mut headers = vec![
+        (ENV_HEADER, tenant.env.as_str()),
+        (TENANT_HEADER, tenant.tenant_id.as_str()),
+    ];
+    headers.extend(team.map(|team| (TEAM_HEADER, team)));
+    headers.extend(user.map(|user| (USER_HEADER, user)));
+    headers
+}
+
+/// Rebuilds a [`TenantCtx`] from header values.
+pub(crate) fn tenant_from_parts(
+    env: Option<&str>,
+    tenant: Option<&str>,
+    team: Option<&str>,
+    user: Option<&str>,
+) -> GResult<TenantCtx> {
+    let env = env.ok_or_else(|| invalid_input(format!("missing `{ENV_HEADER}` header")))?;
+    let tenant =
+        tenant.ok_or_else(|| invalid_input(format!("missing `{TENANT_HEADER}` header")))?;
+    tenant_from_ids(env, tenant, team, user)
+}
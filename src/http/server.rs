@@ -0,0 +1,364 @@
+use super::auth::{ResolvedTenant, TenantResolver, resolve_tenant};
+use super::{
+    ENV_HEADER, TEAM_HEADER, TENANT_HEADER, TTL_MS_HEADER, TTL_SECS_HEADER, USER_HEADER,
+    tenant_from_parts,
+};
+use crate::error::{internal, invalid_input};
+use crate::store::{PrefixStats, PurgeReport, StateStore, StateTtl};
+use axum::extract::{FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use greentic_types::{
+    ErrorCode, GResult, GreenticError, StateKey, StatePath, TeamId, TenantCtx, UserId,
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+type SharedStore = Arc<dyn StateStore>;
+
+#[derive(Deserialize)]
+struct EntryQuery {
+    prefix: String,
+    key: String,
+    path: Option<String>,
+}
+
+impl EntryQuery {
+    fn state_key(&self) -> StateKey {
+        StateKey::new(self.key.as_str())
+    }
+
+    fn state_path(&self) -> Option<StatePath> {
+        self.path.as_deref().map(StatePath::from_pointer)
+    }
+}
+
+#[derive(Deserialize)]
+struct PrefixQuery {
+    prefix: String,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    prefix: Option<String>,
+}
+
+/// Wraps a [`GreenticError`] so it renders with a matching HTTP status.
+pub(super) struct ApiError(GreenticError);
+
+impl From<GreenticError> for ApiError {
+    fn from(err: GreenticError) -> Self {
+        Self(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0.code {
+            ErrorCode::InvalidInput => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::Unauthenticated => StatusCode::UNAUTHORIZED,
+            ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+            ErrorCode::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::Unknown | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(self.0)).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Builds the `/v1` routes serving `store`, trusting the tenant named by the request headers.
+///
+/// The purge routes answer `403` here, since any caller could name any tenant; they are only
+/// served by [`authenticated_router`].
+pub fn router(store: SharedStore) -> Router {
+    Router::new()
+        .merge(entry_routes())
+        .route("/v1/tenant", delete(purge_disabled))
+        .route("/v1/teams/{team}", delete(purge_disabled))
+        .route("/v1/users/{user}", delete(purge_disabled))
+        .with_state(store)
+}
+
+/// Builds the `/v1` routes serving `store`, including the purge routes, with `resolver`
+/// deciding the tenant of every request.
+pub fn authenticated_router(store: SharedStore, resolver: Arc<dyn TenantResolver>) -> Router {
+    Router::new()
+        .merge(entry_routes())
+        .route("/v1/tenant", delete(purge_tenant))
+        .route("/v1/teams/{team}", delete(purge_team))
+        .route("/v1/users/{user}", delete(purge_user))
+        .layer(axum::middleware::from_fn_with_state(
+            resolver,
+            resolve_tenant,
+        ))
+        .with_state(store)
+}
+
+fn entry_routes() -> Router<SharedStore> {
+    Router::new()
+        .route(
+            "/v1/state",
+            get(get_entry)
+                .head(head_entry)
+                .put(put_entry)
+                .post(post_entry)
+                .delete(delete_entry),
+        )
+        .route("/v1/prefixes", get(prefix_stats).delete(delete_prefix))
+        .route("/v1/keys", get(list_keys))
+}
+
+/// Serves `store` with [`router`] on `listener` until the server fails; use `axum::serve` with
+/// [`authenticated_router`] to enable the purge routes.
+pub async fn serve(listener: TcpListener, store: SharedStore) -> std::io::Result<()> {
+    axum::serve(listener, router(store)).await
+}
+
+/// Runs a blocking store call off the async executor.
+async fn blocking<T, F>(store: &SharedStore, call: F) -> GResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&dyn StateStore) -> GResult<T> + Send + 'static,
+{
+    let store = Arc::clone(store);
+    tokio::task::spawn_blocking(move || call(store.as_ref()))
+        .await
+        .map_err(|err| internal(format!("state task failed: {err}")))?
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> GResult<Option<&'a str>> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map_err(|_| invalid_input(format!("`{name}` header is not valid text")))
+        })
+        .transpose()
+}
+
+fn tenant(headers: &HeaderMap) -> GResult<TenantCtx> {
+    tenant_from_parts(
+        header(headers, ENV_HEADER)?,
+        header(headers, TENANT_HEADER)?,
+        header(headers, TEAM_HEADER)?,
+        header(headers, USER_HEADER)?,
+    )
+}
+
+/// Tenant of a request: the one resolved by the [`TenantResolver`] when the router has one,
+/// otherwise the one named by the request headers.
+struct RequestTenant(TenantCtx);
+
+impl<S: Send + Sync> FromRequestParts<S> for RequestTenant {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<ResolvedTenant>() {
+            Some(ResolvedTenant(tenant)) => Ok(Self(tenant.clone())),
+            None => Ok(Self(tenant(&parts.headers)?)),
+        }
+    }
+}
+
+fn missing_entry() -> Response {
+    ApiError(GreenticError::new(
+        ErrorCode::NotFound,
+        "state entry not found",
+    ))
+    .into_response()
+}
+
+async fn get_entry(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<EntryQuery>,
+) -> ApiResult<Response> {
+    let value = blocking(&store, move |store| {
+        store.get_json(
+            &tenant,
+            &query.prefix,
+            &query.state_key(),
+            query.state_path().as_ref(),
+        )
+    })
+    .await?;
+    Ok(match value {
+        Some(value) => Json(value).into_response(),
+        None => missing_entry(),
+    })
+}
+
+async fn head_entry(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<EntryQuery>,
+) -> ApiResult<Response> {
+    let ttl = blocking(&store, move |store| {
+        store.ttl(&tenant, &query.prefix, &query.state_key())
+    })
+    .await?;
+    Ok(match ttl {
+        None => StatusCode::NOT_FOUND.into_response(),
+        Some(StateTtl::Persistent) => StatusCode::OK.into_response(),
+        Some(StateTtl::ExpiresIn(remaining)) => {
+            let mut response = StatusCode::OK.into_response();
+            response.headers_mut().insert(
+                TTL_MS_HEADER,
+                HeaderValue::from(remaining.as_millis() as u64),
+            );
+            response
+        }
+    })
+}
+
+fn ttl_secs(headers: &HeaderMap) -> GResult<Option<u32>> {
+    header(headers, TTL_SECS_HEADER)?
+        .map(|raw| {
+            raw.parse::<u32>()
+                .map_err(|_| invalid_input(format!("`{TTL_SECS_HEADER}` must be whole seconds")))
+        })
+        .transpose()
+}
+
+async fn put_entry(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    headers: HeaderMap,
+    Query(query): Query<EntryQuery>,
+    Json(value): Json<Value>,
+) -> ApiResult<StatusCode> {
+    let ttl_secs = ttl_secs(&headers)?;
+    blocking(&store, move |store| {
+        store.set_json(
+            &tenant,
+            &query.prefix,
+            &query.state_key(),
+            query.state_path().as_ref(),
+            &value,
+            ttl_secs,
+        )
+    })
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn post_entry(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    headers: HeaderMap,
+    Query(query): Query<EntryQuery>,
+    Json(value): Json<Value>,
+) -> ApiResult<Json<Value>> {
+    let ttl_secs = ttl_secs(&headers)?;
+    let written = blocking(&store, move |store| {
+        store.set_if_absent(&tenant, &query.prefix, &query.state_key(), &value, ttl_secs)
+    })
+    .await?;
+    Ok(Json(json!({ "written": written })))
+}
+
+async fn delete_entry(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<EntryQuery>,
+) -> ApiResult<Json<Value>> {
+    let deleted = blocking(&store, move |store| {
+        store.del(&tenant, &query.prefix, &query.state_key())
+    })
+    .await?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
+
+async fn delete_prefix(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<PrefixQuery>,
+) -> ApiResult<Json<Value>> {
+    let deleted = blocking(&store, move |store| {
+        if query.recursive {
+            store.del_prefix_recursive(&tenant, &query.prefix)
+        } else {
+            store.del_prefix(&tenant, &query.prefix)
+        }
+    })
+    .await?;
+    Ok(Json(json!({ "deleted": deleted })))
+}
+
+async fn prefix_stats(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<PrefixQuery>,
+) -> ApiResult<Json<PrefixStats>> {
+    let stats = blocking(&store, move |store| {
+        store.prefix_stats(&tenant, &query.prefix)
+    })
+    .await?;
+    Ok(Json(stats))
+}
+
+async fn list_keys(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Query(query): Query<ListQuery>,
+) -> ApiResult<Json<Value>> {
+    let keys = blocking(&store, move |store| {
+        store.list_keys(&tenant, query.prefix.as_deref())
+    })
+    .await?;
+    let keys = keys
+        .into_iter()
+        .map(|entry| json!({ "prefix": entry.prefix, "key": entry.key.as_str() }))
+        .collect();
+    Ok(Json(Value::Array(keys)))
+}
+
+async fn purge_disabled() -> ApiError {
+    ApiError(GreenticError::new(
+        ErrorCode::PermissionDenied,
+        "purge routes require a tenant resolver (see `authenticated_router`)",
+    ))
+}
+
+async fn purge_tenant(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+) -> ApiResult<Json<PurgeReport>> {
+    let report = blocking(&store, move |store| store.purge_tenant(&tenant)).await?;
+    Ok(Json(report))
+}
+
+async fn purge_team(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Path(team): Path<String>,
+) -> ApiResult<Json<PurgeReport>> {
+    let team = TeamId::try_from(team.as_str())
+        .map_err(|err| invalid_input(format!("team `{team}`: {err}")))?;
+    let report = blocking(&store, move |store| store.purge_team(&tenant, &team)).await?;
+    Ok(Json(report))
+}
+
+async fn purge_user(
+    State(store): State<SharedStore>,
+    RequestTenant(tenant): RequestTenant,
+    Path(user): Path<String>,
+) -> ApiResult<Json<PurgeReport>> {
+    let user = UserId::try_from(user.as_str())
+        .map_err(|err| invalid_input(format!("user `{user}`: {err}")))?;
+    let report = blocking(&store, move |store| store.purge_user(&tenant, &user)).await?;
+    Ok(Json(report))
+}
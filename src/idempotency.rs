@@ -0,0 +1,191 @@
+//! "Process once" semantics for retried requests such as webhook deliveries.
+//!
+//! [`Idempotency::run`] claims a request id with [`StateStore::set_if_absent`] before running
+//! the handler, then stores the handler's result under the same key. Retries of a completed
+//! request get the stored result back without running the handler again, while a retry that
+//! arrives during the first attempt fails with `Conflict`. A failed attempt releases its claim
+//! so the request can be retried.
+
+use crate::error::{conflict, from_serde, invalid_input};
+use crate::store::StateStore;
+use greentic_types::{GResult, StateKey, TenantCtx};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Prefix request records are stored under unless [`Idempotency::prefix`] overrides it.
+pub const DEFAULT_PREFIX: &str = "idempotency";
+
+/// Result of [`Idempotency::run`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Processed<T> {
+    /// Handler result, computed now or by an earlier attempt.
+    pub value: T,
+    /// `true` when `value` was stored by an earlier attempt and the handler did not run.
+    pub replayed: bool,
+}
+
+/// Stored state of one request id.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum RequestRecord {
+    Pending,
+    Done { result: Value },
+}
+
+/// Runs handlers at most once per request id, replaying their results on retries.
+#[derive(Clone)]
+pub struct Idempotency {
+    store: Arc<dyn StateStore>,
+    prefix: String,
+    pending_ttl_secs: u32,
+    result_ttl_secs: Option<u32>,
+}
+
+impl Idempotency {
+    /// Records requests in `store` under [`DEFAULT_PREFIX`], holding claims for 5 minutes and
+    /// results for 24 hours.
+    pub fn new(store: Arc<dyn StateStore>) -> Self {
+        Self {
+            store,
+            prefix: DEFAULT_PREFIX.to_owned(),
+            pending_ttl_secs: 300,
+            result_ttl_secs: Some(86_400),
+        }
+    }
+
+    /// Stores request records under `prefix`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// How long a claim blocks retries when its handler never finishes (e.g. the process
+    /// crashed); at least one second.
+    pub fn pending_ttl(mut self, secs: u32) -> Self {
+        self.pending_ttl_secs = secs.max(1);
+        self
+    }
+
+    /// How long results are replayed; `None` keeps them until [`Idempotency::forget`].
+    pub fn result_ttl(mut self, secs: Option<u32>) -> Self {
+        self.result_ttl_secs = secs.filter(|secs| *secs > 0);
+        self
+    }
+
+    /// Runs `handler` unless `request_id` was already processed for `tenant`, in which case the
+    /// stored result is returned instead.
+    ///
+    /// Fails with `Conflict` while another attempt for `request_id` is still running. When
+    /// `handler` fails its error is returned and nothing is stored.
+    ///
+    /// When the store cannot tell whether the claim was written (e.g. its reply was lost), the
+    /// store error is returned without running `handler`. A claim that did land blocks retries
+    /// with `Conflict` until it lapses after the pending TTL, so the request is never mistaken
+    /// for a completed one.
+    pub fn run<T, F>(
+        &self,
+        tenant: &TenantCtx,
+        request_id: &str,
+        handler: F,
+    ) -> GResult<Processed<T>>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> GResult<T>,
+    {
+        let key = request_key(request_id)?;
+        let pending = encode(&RequestRecord::Pending)?;
+        // A claim that lapses between the failed claim and the read is retried once.
+        let mut claimed = false;
+        for _ in 0..2 {
+            if self.store.set_if_absent(
+                tenant,
+                &self.prefix,
+                &key,
+                &pending,
+                Some(self.pending_ttl_secs),
+            )? {
+                claimed = true;
+                break;
+            }
+            match self.load(tenant, &key)? {
+                Some(RequestRecord::Done { result }) => {
+                    return Ok(Processed {
+                        value: serde_json::from_value(result).map_err(from_serde)?,
+                        replayed: true,
+                    });
+                }
+                Some(RequestRecord::Pending) => break,
+                None => continue,
+            }
+        }
+        if !claimed {
+            return Err(conflict(format!(
+                "request `{request_id}` is already being processed"
+            )));
+        }
+
+        let value = match handler() {
+            Ok(value) => value,
+            Err(err) => {
+                if let Err(release) = self.store.del(tenant, &self.prefix, &key) {
+                    warn!(request_id, error = %release, "failed to release idempotency claim");
+                }
+                return Err(err);
+            }
+        };
+        let result = serde_json::to_value(&value).map_err(from_serde)?;
+        self.store.set_json(
+            tenant,
+            &self.prefix,
+            &key,
+            None,
+            &encode(&RequestRecord::Done { result })?,
+            Some(self.result_ttl_secs.unwrap_or(0)),
+        )?;
+        Ok(Processed {
+            value,
+            replayed: false,
+        })
+    }
+
+    /// Returns the stored result of `request_id`, or `None` when it has not completed.
+    pub fn lookup<T: DeserializeOwned>(
+        &self,
+        tenant: &TenantCtx,
+        request_id: &str,
+    ) -> GResult<Option<T>> {
+        match self.load(tenant, &request_key(request_id)?)? {
+            Some(RequestRecord::Done { result }) => {
+                serde_json::from_value(result).map(Some).map_err(from_serde)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Drops the record of `request_id` so the next [`Idempotency::run`] processes it again.
+    pub fn forget(&self, tenant: &TenantCtx, request_id: &str) -> GResult<bool> {
+        self.store
+            .del(tenant, &self.prefix, &request_key(request_id)?)
+    }
+
+    fn load(&self, tenant: &TenantCtx, key: &StateKey) -> GResult<Option<RequestRecord>> {
+        self.store
+            .get_json(tenant, &self.prefix, key, None)?
+            .map(|record| serde_json::from_value(record).map_err(from_serde))
+            .transpose()
+    }
+}
+
+fn request_key(request_id: &str) -> GResult<StateKey> {
+    if request_id.is_empty() {
+        return Err(invalid_input("request id must not be empty"));
+    }
+    Ok(StateKey::new(request_id))
+}
+
+fn encode(record: &RequestRecord) -> GResult<Value> {
+    serde_json::to_value(record).map_err(from_serde)
+}
@@ -1,28 +1,205 @@
-use crate::key::{FqnKey, StatePath, fqn, fqn_prefix};
-use crate::store::StateStore;
-use crate::util::{get_at_path, set_at_path};
+use crate::clock::{Clock, SystemClock};
+use crate::key::{
+    FqnKey, StatePath, fqn, fqn_prefix, lease_key, prefix_covers, purge_scope, rate_limit_key,
+    scope_members, tenant_scope,
+};
+use crate::lease::{Lease, LeaseStore, check_acquire, lease_ttl_ms};
+use crate::limits::StateLimits;
+use crate::ratelimit::{RateDecision, RateLimit, RateLimitMs, RateLimitStore, check_rate_args};
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::{get_at_path, json_size, set_at_path_with_limits};
 use dashmap::DashMap;
 use dashmap::mapref::entry::Entry;
-use greentic_types::{GResult, StateKey, TenantCtx};
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
 use serde_json::Value;
+use std::collections::VecDeque;
 use std::sync::Arc;
 use time::{Duration, OffsetDateTime};
 
+/// Number of new rate limiter keys between sweeps of the idle ones.
+const RATE_SWEEP_EVERY: usize = 1024;
+
 /// In-memory state store backed by [`DashMap`].
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct InMemoryStateStore {
     entries: Arc<DashMap<String, StoredValue>>,
+    limits: StateLimits,
+    clock: Arc<dyn Clock>,
+    leases: Arc<DashMap<String, LeaseSlot>>,
+    rates: Arc<DashMap<String, RateSlot>>,
+}
+
+impl Default for InMemoryStateStore {
+    fn default() -> Self {
+        Self {
+            entries: Arc::default(),
+            leases: Arc::default(),
+            rates: Arc::default(),
+            limits: StateLimits::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
 }
 
 #[derive(Clone)]
 struct StoredValue {
     value: Value,
     expires_at: Option<OffsetDateTime>,
+    origin: Arc<EntryOrigin>,
+}
+
+/// Structured FQN parts kept alongside each value so enumeration never has to parse FQNs.
+struct EntryOrigin {
+    scope: String,
+    /// `{env}:{tenant}` without team or user, matched by purges.
+    owner: String,
+    team: Option<String>,
+    user: Option<String>,
+    prefix: String,
+    key: StateKey,
+}
+
+impl EntryOrigin {
+    fn new(tenant: &TenantCtx, prefix: &str, key: &StateKey) -> Arc<Self> {
+        let (team, user) = scope_members(tenant);
+        Arc::new(Self {
+            scope: tenant_scope(tenant),
+            owner: purge_scope(tenant, None, None),
+            team: team.map(str::to_owned),
+            user: user.map(str::to_owned),
+            prefix: prefix.to_owned(),
+            key: key.clone(),
+        })
+    }
+}
+
+/// Fencing counter of one lease name and its current holder, if any.
+#[derive(Default)]
+struct LeaseSlot {
+    fence: u64,
+    holder: Option<(String, OffsetDateTime)>,
+}
+
+impl LeaseSlot {
+    /// Returns the live holder and its deadline, forgetting it once expired.
+    fn holder(&mut self, now: OffsetDateTime) -> Option<&mut (String, OffsetDateTime)> {
+        if self
+            .holder
+            .as_ref()
+            .is_some_and(|(_, expires_at)| *expires_at <= now)
+        {
+            self.holder = None;
+        }
+        self.holder.as_mut()
+    }
+
+    /// Reports whether `lease` is the live holding of this slot.
+    fn is_held_by(&mut self, now: OffsetDateTime, lease: &Lease) -> bool {
+        let fence = self.fence;
+        self.holder(now)
+            .is_some_and(|(owner, _)| *owner == lease.owner && fence == lease.token)
+    }
+}
+
+/// Usage recorded for one rate limiter key and when it becomes indistinguishable from none.
+struct RateSlot {
+    usage: RateUsage,
+    idle_at_ms: i64,
+}
+
+enum RateUsage {
+    /// Tokens left as of `updated_ms`.
+    Bucket { tokens: f64, updated_ms: i64 },
+    /// Grant times of the units still inside the window, oldest first.
+    Window(VecDeque<i64>),
+}
+
+impl RateSlot {
+    fn new(limit: &RateLimitMs, now_ms: i64) -> Self {
+        let usage = match *limit {
+            RateLimitMs::TokenBucket { capacity, .. } => RateUsage::Bucket {
+                tokens: capacity.into(),
+                updated_ms: now_ms,
+            },
+            RateLimitMs::SlidingWindow { .. } => RateUsage::Window(VecDeque::new()),
+        };
+        Self {
+            usage,
+            idle_at_ms: now_ms,
+        }
+    }
+
+    /// Consumes `cost` units when available; mirrors the Redis rate limit scripts.
+    fn check(&mut self, limit: &RateLimitMs, cost: u32, now_ms: i64) -> RateDecision {
+        match (*limit, &mut self.usage) {
+            (
+                RateLimitMs::TokenBucket {
+                    capacity,
+                    refill,
+                    interval_ms,
+                },
+                RateUsage::Bucket { tokens, updated_ms },
+            ) => {
+                let rate = f64::from(refill) / interval_ms as f64;
+                // Capped even without elapsed time, in case the capacity shrank since.
+                let elapsed_ms = (now_ms - *updated_ms).max(0);
+                *tokens = f64::from(capacity).min(*tokens + elapsed_ms as f64 * rate);
+                *updated_ms = now_ms;
+                let cost = f64::from(cost);
+                let retry_after = if *tokens >= cost {
+                    *tokens -= cost;
+                    None
+                } else {
+                    Some(((cost - *tokens) / rate).ceil() as u64)
+                };
+                let refill_ms = ((f64::from(capacity) - *tokens) / rate).ceil() as i64;
+                self.idle_at_ms = now_ms.saturating_add(refill_ms);
+                decision(tokens.floor() as u32, retry_after)
+            }
+            (RateLimitMs::SlidingWindow { limit, window_ms }, RateUsage::Window(grants)) => {
+                let window_ms = window_ms as i64;
+                while grants.front().is_some_and(|at| *at <= now_ms - window_ms) {
+                    grants.pop_front();
+                }
+                let used = grants.len();
+                let needed = used + cost as usize;
+                let retry_after = if needed <= limit as usize {
+                    grants.extend(std::iter::repeat_n(now_ms, cost as usize));
+                    None
+                } else {
+                    let freed_at = grants[needed - limit as usize - 1] + window_ms;
+                    Some((freed_at - now_ms) as u64)
+                };
+                self.idle_at_ms = grants
+                    .back()
+                    .map_or(now_ms, |at| at.saturating_add(window_ms));
+                // Grants recorded under a larger limit may still exceed this one.
+                let granted = u32::try_from(grants.len()).unwrap_or(u32::MAX);
+                decision(limit.saturating_sub(granted), retry_after)
+            }
+            (limit, _) => {
+                *self = Self::new(&limit, now_ms);
+                self.check(&limit, cost, now_ms)
+            }
+        }
+    }
+}
+
+fn decision(remaining: u32, retry_after_ms: Option<u64>) -> RateDecision {
+    RateDecision {
+        allowed: retry_after_ms.is_none(),
+        remaining,
+        retry_after: retry_after_ms.map(std::time::Duration::from_millis),
+    }
 }
 
 impl StoredValue {
-    fn new(value: Value, expires_at: Option<OffsetDateTime>) -> Self {
-        Self { value, expires_at }
+    fn new(value: Value, expires_at: Option<OffsetDateTime>, origin: Arc<EntryOrigin>) -> Self {
+        Self {
+            value,
+            expires_at,
+            origin,
+        }
     }
 
     fn is_expired(&self, now: OffsetDateTime) -> bool {
@@ -33,11 +210,45 @@ impl StoredValue {
 }
 
 impl InMemoryStateStore {
-    /// Creates a new empty store.
+    /// Creates a new empty store enforcing the default [`StateLimits`].
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Replaces the limits enforced on writes.
+    pub fn with_limits(mut self, limits: StateLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Replaces the clock used to set and check TTL deadlines, e.g. with a
+    /// [`TestClock`](crate::clock::TestClock). Clones made earlier keep the previous clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Builds the document stored after writing `value` at `path` over `current`, checking it
+    /// against the store's limits.
+    fn apply_write(
+        &self,
+        current: Option<&Value>,
+        path: Option<&StatePath>,
+        value: &Value,
+    ) -> GResult<Value> {
+        let document = match path {
+            Some(path) => {
+                self.limits.check_path_write(path, value)?;
+                let mut document = current.cloned().unwrap_or(Value::Null);
+                set_at_path_with_limits(&mut document, path, value.clone(), &self.limits)?;
+                document
+            }
+            None => value.clone(),
+        };
+        self.limits.check_document(&document)?;
+        Ok(document)
+    }
+
     fn entry_key(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> FqnKey {
         fqn(tenant, prefix, key)
     }
@@ -51,7 +262,7 @@ impl InMemoryStateStore {
     }
 
     fn materialize_value(&self, fqn: &FqnKey, path: Option<&StatePath>) -> GResult<Option<Value>> {
-        let now = OffsetDateTime::now_utc();
+        let now = self.clock.now();
         let Some(entry) = self.entries.get(fqn.as_str()) else {
             return Ok(None);
         };
@@ -72,28 +283,62 @@ impl InMemoryStateStore {
         Ok(Some(value))
     }
 
+    /// Removes the entry stored under `fqn`, returning `true` when it existed.
+    pub(crate) fn remove_fqn(&self, fqn: &str) -> bool {
+        self.entries.remove(fqn).is_some()
+    }
+
+    /// Removes every entry whose FQN starts with `pattern`, returning the number removed.
+    pub(crate) fn remove_fqn_prefix(&self, pattern: &str) -> u64 {
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.key().starts_with(pattern))
+            .map(|entry| entry.key().clone())
+            .collect();
+
+        let mut count = 0;
+        for key in keys {
+            if self.entries.remove(&key).is_some() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Drops every entry.
+    pub(crate) fn clear(&self) {
+        self.entries.clear();
+    }
+
+    /// Removes every entry of `tenant`'s env and tenant id accepted by `matches`.
+    fn purge_where(
+        &self,
+        tenant: &TenantCtx,
+        scope: String,
+        matches: impl Fn(&EntryOrigin) -> bool,
+    ) -> PurgeReport {
+        let owner = purge_scope(tenant, None, None);
+        let before = self.entries.len();
+        self.entries
+            .retain(|_, entry| !(entry.origin.owner == owner && matches(&entry.origin)));
+        PurgeReport::new(scope, before.saturating_sub(self.entries.len()) as u64)
+    }
+
     fn insert_new(
         &self,
         fqn: &FqnKey,
+        origin: Arc<EntryOrigin>,
         path: Option<&StatePath>,
         value: &Value,
         ttl_secs: Option<u32>,
     ) -> GResult<()> {
-        let now = OffsetDateTime::now_utc();
-        let mut stored = if path.is_some() {
-            Value::Null
-        } else {
-            value.clone()
-        };
-
-        if let Some(path) = path {
-            set_at_path(&mut stored, path, value.clone())?;
-        }
-
+        let now = self.clock.now();
+        let stored = self.apply_write(None, path, value)?;
         let expires_at = Self::compute_deadline(now, ttl_secs);
         self.entries.insert(
             fqn.as_str().to_owned(),
-            StoredValue::new(stored, expires_at),
+            StoredValue::new(stored, expires_at, origin),
         );
         Ok(())
     }
@@ -121,43 +366,63 @@ impl StateStore for InMemoryStateStore {
         ttl_secs: Option<u32>,
     ) -> GResult<()> {
         let fqn = self.entry_key(tenant, prefix, key);
-        let now = OffsetDateTime::now_utc();
+        let now = self.clock.now();
 
         match self.entries.entry(fqn.as_str().to_owned()) {
             Entry::Occupied(mut occupied) => {
                 if occupied.get().is_expired(now) {
                     occupied.remove();
-                    return self.insert_new(&fqn, path, value, ttl_secs);
+                    let origin = EntryOrigin::new(tenant, prefix, key);
+                    return self.insert_new(&fqn, origin, path, value, ttl_secs);
                 }
 
                 let entry = occupied.get_mut();
+                entry.value = self.apply_write(Some(&entry.value), path, value)?;
                 if let Some(ttl) = ttl_secs {
                     entry.expires_at = Self::compute_deadline(now, Some(ttl));
                 }
-
-                if let Some(path) = path {
-                    set_at_path(&mut entry.value, path, value.clone())?;
-                } else {
-                    entry.value = value.clone();
-                }
                 Ok(())
             }
             Entry::Vacant(vacant) => {
-                let mut stored = if path.is_some() {
-                    Value::Null
-                } else {
-                    value.clone()
-                };
-                if let Some(path) = path {
-                    set_at_path(&mut stored, path, value.clone())?;
-                }
+                let stored = self.apply_write(None, path, value)?;
                 let expires_at = Self::compute_deadline(now, ttl_secs);
-                vacant.insert(StoredValue::new(stored, expires_at));
+                let origin = EntryOrigin::new(tenant, prefix, key);
+                vacant.insert(StoredValue::new(stored, expires_at, origin));
                 Ok(())
             }
         }
     }
 
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let fqn = self.entry_key(tenant, prefix, key);
+        let now = self.clock.now();
+        let stored = |origin| -> GResult<StoredValue> {
+            let document = self.apply_write(None, None, value)?;
+            let expires_at = Self::compute_deadline(now, ttl_secs);
+            Ok(StoredValue::new(document, expires_at, origin))
+        };
+
+        match self.entries.entry(fqn.as_str().to_owned()) {
+            Entry::Occupied(mut occupied) => {
+                if !occupied.get().is_expired(now) {
+                    return Ok(false);
+                }
+                occupied.insert(stored(EntryOrigin::new(tenant, prefix, key))?);
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(stored(EntryOrigin::new(tenant, prefix, key))?);
+            }
+        }
+        Ok(true)
+    }
+
     fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
         let fqn = self.entry_key(tenant, prefix, key);
         Ok(self.entries.remove(fqn.as_str()).is_some())
@@ -165,19 +430,185 @@ impl StateStore for InMemoryStateStore {
 
     fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
         let pattern = fqn_prefix(tenant, prefix);
-        let keys: Vec<String> = self
+        Ok(self.remove_fqn_prefix(&pattern))
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let now = self.clock.now();
+        let scope = tenant_scope(tenant);
+        let mut keys: Vec<ScopedKey> = self
             .entries
             .iter()
-            .filter(|entry| entry.key().starts_with(&pattern))
-            .map(|entry| entry.key().clone())
+            .filter(|entry| !entry.is_expired(now))
+            .filter(|entry| {
+                let origin = &entry.origin;
+                origin.scope == scope && prefix.is_none_or(|prefix| origin.prefix == prefix)
+            })
+            .map(|entry| ScopedKey {
+                prefix: entry.origin.prefix.clone(),
+                key: entry.origin.key.clone(),
+            })
             .collect();
+        ScopedKey::sort(&mut keys);
+        Ok(keys)
+    }
 
-        let mut count = 0;
-        for key in keys {
-            if self.entries.remove(&key).is_some() {
-                count += 1;
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let scope = tenant_scope(tenant);
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| {
+            let origin = &entry.origin;
+            !(origin.scope == scope && prefix_covers(prefix, &origin.prefix))
+        });
+        Ok(before.saturating_sub(self.entries.len()) as u64)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let now = self.clock.now();
+        let scope = tenant_scope(tenant);
+        let mut stats = PrefixStats::default();
+        for entry in self.entries.iter() {
+            let origin = &entry.origin;
+            if entry.is_expired(now)
+                || origin.scope != scope
+                || !prefix_covers(prefix, &origin.prefix)
+            {
+                continue;
             }
+            stats.add(json_size(&entry.value), entry.expires_at);
         }
-        Ok(count)
+        Ok(stats)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, None, None);
+        Ok(self.purge_where(tenant, scope, |_| true))
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, Some(team.as_str()), None);
+        Ok(self.purge_where(tenant, scope, |origin| {
+            origin.team.as_deref() == Some(team.as_str())
+        }))
     }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, None, Some(user.as_str()));
+        Ok(self.purge_where(tenant, scope, |origin| {
+            origin.user.as_deref() == Some(user.as_str())
+        }))
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let fqn = self.entry_key(tenant, prefix, key);
+        let now = self.clock.now();
+        let Some(entry) = self.entries.get(fqn.as_str()) else {
+            return Ok(None);
+        };
+        if entry.is_expired(now) {
+            return Ok(None);
+        }
+        Ok(Some(match entry.expires_at {
+            Some(deadline) => StateTtl::ExpiresIn((deadline - now).try_into().unwrap_or_default()),
+            None => StateTtl::Persistent,
+        }))
+    }
+}
+
+impl LeaseStore for InMemoryStateStore {
+    fn acquire_lease(
+        &self,
+        tenant: &TenantCtx,
+        name: &str,
+        owner: &str,
+        ttl: std::time::Duration,
+    ) -> GResult<Option<Lease>> {
+        check_acquire(name, owner, ttl)?;
+        let now = self.clock.now();
+        let expires_at = now + ttl;
+        let mut slot = self.leases.entry(lease_key(tenant, name)).or_default();
+        match slot.holder(now) {
+            Some((holder, _)) if holder != owner => return Ok(None),
+            Some((_, deadline)) => *deadline = expires_at,
+            None => {
+                slot.fence += 1;
+                slot.holder = Some((owner.to_owned(), expires_at));
+            }
+        }
+        Ok(Some(Lease {
+            tenant: tenant.clone(),
+            name: name.to_owned(),
+            owner: owner.to_owned(),
+            token: slot.fence,
+            expires_at,
+        }))
+    }
+
+    fn renew_lease(&self, lease: &Lease, ttl: std::time::Duration) -> GResult<Option<Lease>> {
+        lease_ttl_ms(ttl)?;
+        let now = self.clock.now();
+        let Some(mut slot) = self.leases.get_mut(&lease_key(&lease.tenant, &lease.name)) else {
+            return Ok(None);
+        };
+        if !slot.is_held_by(now, lease) {
+            return Ok(None);
+        }
+        let renewed = lease.renewed(now, ttl);
+        slot.holder = Some((lease.owner.clone(), renewed.expires_at));
+        Ok(Some(renewed))
+    }
+
+    fn release_lease(&self, lease: &Lease) -> GResult<bool> {
+        let now = self.clock.now();
+        let Some(mut slot) = self.leases.get_mut(&lease_key(&lease.tenant, &lease.name)) else {
+            return Ok(false);
+        };
+        if !slot.is_held_by(now, lease) {
+            return Ok(false);
+        }
+        slot.holder = None;
+        Ok(true)
+    }
+}
+
+impl RateLimitStore for InMemoryStateStore {
+    fn check_rate(
+        &self,
+        tenant: &TenantCtx,
+        key: &str,
+        limit: &RateLimit,
+        cost: u32,
+    ) -> GResult<RateDecision> {
+        let limit = check_rate_args(key, limit, cost)?;
+        let now_ms = unix_ms(self.clock.now());
+        let name = rate_limit_key(tenant, key);
+        let mut created = false;
+        let (decision, idle) = {
+            let mut slot = self.rates.entry(name.clone()).or_insert_with(|| {
+                created = true;
+                RateSlot::new(&limit, now_ms)
+            });
+            let decision = slot.check(&limit, cost, now_ms);
+            (decision, slot.idle_at_ms <= now_ms)
+        };
+        if idle {
+            self.rates
+                .remove_if(&name, |_, slot| slot.idle_at_ms <= now_ms);
+        } else if created && self.rates.len() % RATE_SWEEP_EVERY == 0 {
+            self.rates.retain(|_, slot| slot.idle_at_ms > now_ms);
+        }
+        Ok(decision)
+    }
+
+    fn reset_rate(&self, tenant: &TenantCtx, key: &str) -> GResult<bool> {
+        let now_ms = unix_ms(self.clock.now());
+        Ok(self
+            .rates
+            .remove(&rate_limit_key(tenant, key))
+            .is_some_and(|(_, slot)| slot.idle_at_ms > now_ms))
+    }
+}
+
+fn unix_ms(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1_000_000) as i64
 }
@@ -0,0 +1,271 @@
+//! Tracing spans and [`metrics`] instrumentation for any [`StateStore`].
+//!
+//! Every call opens a `state` span carrying the operation, prefix and tenant label, then records
+//! latency, payload size, hit/miss and the error code. The same data feeds these metrics:
+//!
+//! | Metric | Kind | Labels |
+//! |---|---|---|
+//! | [`OPERATIONS_TOTAL`] | counter | `op`, `tenant`, `outcome` (`ok` or the error code) |
+//! | [`OPERATION_DURATION_SECONDS`] | histogram | `op`, `tenant` |
+//! | [`PAYLOAD_BYTES`] | histogram | `op`, `tenant` |
+//! | [`READS_TOTAL`] | counter | `tenant`, `result` (`hit` or `miss`) |
+
+use crate::key::StatePath;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::{json_size, sha256_hex};
+use greentic_types::{ErrorCode, GResult, StateKey, TeamId, TenantCtx, UserId};
+use serde_json::Value;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Span, debug_span};
+
+/// Counter of completed operations.
+pub const OPERATIONS_TOTAL: &str = "greentic_state_operations_total";
+/// Histogram of operation latency in seconds.
+pub const OPERATION_DURATION_SECONDS: &str = "greentic_state_operation_duration_seconds";
+/// Histogram of serialized JSON sizes read (`get_json` hits) and written (`set_json`).
+pub const PAYLOAD_BYTES: &str = "greentic_state_payload_bytes";
+/// Counter of `get_json` results split into hits and misses.
+pub const READS_TOTAL: &str = "greentic_state_reads_total";
+
+/// How the tenant appears in span fields and metric labels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TenantLabel {
+    /// First 16 hex digits of SHA-256 over `{env}:{tenant}`; stable across processes.
+    #[default]
+    Hashed,
+    /// `{env}:{tenant}` in clear text.
+    Plain,
+    /// The constant `redacted`, dropping per-tenant breakdowns.
+    Redacted,
+}
+
+impl TenantLabel {
+    fn render(self, tenant: &TenantCtx) -> String {
+        let scope = format!("{}:{}", tenant.env.as_str(), tenant.tenant_id.as_str());
+        match self {
+            Self::Plain => scope,
+            Self::Redacted => "redacted".to_owned(),
+            Self::Hashed => {
+                let mut digest = sha256_hex(scope.as_bytes());
+                digest.truncate(16);
+                digest
+            }
+        }
+    }
+}
+
+/// Metric label for an error code.
+pub(crate) fn error_code_label(code: ErrorCode) -> &'static str {
+    match code {
+        ErrorCode::Unknown => "unknown",
+        ErrorCode::InvalidInput => "invalid_input",
+        ErrorCode::NotFound => "not_found",
+        ErrorCode::Conflict => "conflict",
+        ErrorCode::Timeout => "timeout",
+        ErrorCode::Unauthenticated => "unauthenticated",
+        ErrorCode::PermissionDenied => "permission_denied",
+        ErrorCode::RateLimited => "rate_limited",
+        ErrorCode::Unavailable => "unavailable",
+        ErrorCode::Internal => "internal",
+    }
+}
+
+/// [`StateStore`] wrapper that traces and measures every call to the inner store.
+pub struct InstrumentedStateStore<S> {
+    inner: S,
+    tenant_label: TenantLabel,
+}
+
+impl<S: StateStore> InstrumentedStateStore<S> {
+    /// Wraps `inner`, labelling tenants with [`TenantLabel::Hashed`].
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            tenant_label: TenantLabel::default(),
+        }
+    }
+
+    /// Chooses how tenants are labelled.
+    pub fn with_tenant_label(mut self, tenant_label: TenantLabel) -> Self {
+        self.tenant_label = tenant_label;
+        self
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn observe<T>(
+        &self,
+        op: &'static str,
+        tenant: &TenantCtx,
+        prefix: Option<&str>,
+        call: impl FnOnce(&Span, &str) -> GResult<T>,
+    ) -> GResult<T> {
+        let label = self.tenant_label.render(tenant);
+        let span = debug_span!(
+            "state",
+            op,
+            prefix,
+            tenant = %label,
+            bytes = Empty,
+            hit = Empty,
+            error_code = Empty,
+            elapsed_ms = Empty,
+        );
+        let _entered = span.enter();
+        let started = Instant::now();
+        let result = call(&span, &label);
+        let elapsed = started.elapsed();
+
+        span.record("elapsed_ms", elapsed.as_secs_f64() * 1000.0);
+        let outcome = match &result {
+            Ok(_) => "ok",
+            Err(err) => {
+                let code = error_code_label(err.code);
+                span.record("error_code", code);
+                code
+            }
+        };
+        metrics::counter!(OPERATIONS_TOTAL, "op" => op, "tenant" => label.clone(), "outcome" => outcome)
+            .increment(1);
+        metrics::histogram!(OPERATION_DURATION_SECONDS, "op" => op, "tenant" => label)
+            .record(elapsed.as_secs_f64());
+        result
+    }
+}
+
+fn record_payload(span: &Span, op: &'static str, tenant: &str, value: &Value) {
+    let bytes = json_size(value);
+    span.record("bytes", bytes);
+    metrics::histogram!(PAYLOAD_BYTES, "op" => op, "tenant" => tenant.to_owned())
+        .record(bytes as f64);
+}
+
+impl<S: StateStore> StateStore for InstrumentedStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        self.observe("get_json", tenant, Some(prefix), |span, label| {
+            let value = self.inner.get_json(tenant, prefix, key, path)?;
+            span.record("hit", value.is_some());
+            let result = if value.is_some() { "hit" } else { "miss" };
+            metrics::counter!(READS_TOTAL, "tenant" => label.to_owned(), "result" => result)
+                .increment(1);
+            if let Some(value) = &value {
+                record_payload(span, "get_json", label, value);
+            }
+            Ok(value)
+        })
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.observe("set_json", tenant, Some(prefix), |span, label| {
+            record_payload(span, "set_json", label, value);
+            self.inner
+                .set_json(tenant, prefix, key, path, value, ttl_secs)
+        })
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        self.observe("set_if_absent", tenant, Some(prefix), |span, label| {
+            record_payload(span, "set_if_absent", label, value);
+            let written = self
+                .inner
+                .set_if_absent(tenant, prefix, key, value, ttl_secs)?;
+            span.record("hit", !written);
+            Ok(written)
+        })
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.observe("del", tenant, Some(prefix), |span, _| {
+            let deleted = self.inner.del(tenant, prefix, key)?;
+            span.record("hit", deleted);
+            Ok(deleted)
+        })
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.observe("del_prefix", tenant, Some(prefix), |span, _| {
+            let deleted = self.inner.del_prefix(tenant, prefix)?;
+            span.record("hit", deleted > 0);
+            Ok(deleted)
+        })
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.observe("del_prefix_recursive", tenant, Some(prefix), |span, _| {
+            let deleted = self.inner.del_prefix_recursive(tenant, prefix)?;
+            span.record("hit", deleted > 0);
+            Ok(deleted)
+        })
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.observe("prefix_stats", tenant, Some(prefix), |span, _| {
+            let stats = self.inner.prefix_stats(tenant, prefix)?;
+            span.record("hit", stats.keys > 0);
+            Ok(stats)
+        })
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.observe("list_keys", tenant, prefix, |_, _| {
+            self.inner.list_keys(tenant, prefix)
+        })
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        self.observe("purge_tenant", tenant, None, |span, _| {
+            let report = self.inner.purge_tenant(tenant)?;
+            span.record("hit", report.deleted > 0);
+            Ok(report)
+        })
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        self.observe("purge_team", tenant, None, |span, _| {
+            let report = self.inner.purge_team(tenant, team)?;
+            span.record("hit", report.deleted > 0);
+            Ok(report)
+        })
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        self.observe("purge_user", tenant, None, |span, _| {
+            let report = self.inner.purge_user(tenant, user)?;
+            span.record("hit", report.deleted > 0);
+            Ok(report)
+        })
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.observe("ttl", tenant, Some(prefix), |span, _| {
+            let ttl = self.inner.ttl(tenant, prefix, key)?;
+            span.record("hit", ttl.is_some());
+            Ok(ttl)
+        })
+    }
+}
@@ -1,4 +1,5 @@
-use greentic_types::{StateKey, TenantCtx};
+use crate::error::invalid_input;
+use greentic_types::{EnvId, GResult, StateKey, TeamId, TenantCtx, TenantId, UserId};
 use serde::{Deserialize, Serialize};
 use std::fmt::{self, Display};
 
@@ -42,26 +43,123 @@ pub fn fqn(tenant: &TenantCtx, prefix: &str, key: &StateKey) -> FqnKey {
     ))
 }
 
+/// Namespaced prefix covering every entry in the tenant's scope, regardless of caller prefix.
+#[cfg(feature = "redis")]
+pub(crate) fn fqn_scope_prefix(tenant: &TenantCtx) -> String {
+    let scope = tenant_scope(tenant);
+    format!("greentic:state:{scope}:")
+}
+
 /// Compute the namespaced prefix used for bulk deletion (namespace-level).
 pub fn fqn_prefix(tenant: &TenantCtx, prefix: &str) -> String {
     let scope = tenant_scope(tenant);
     format!("greentic:state:{scope}:{prefix}:")
 }
 
-fn tenant_scope(tenant: &TenantCtx) -> String {
-    let mut segments = vec![tenant.env.as_str(), tenant.tenant_id.as_str()];
+/// Namespaced base shared by every sub-prefix `{prefix}/…` of `prefix`.
+pub(crate) fn fqn_subprefix_base(tenant: &TenantCtx, prefix: &str) -> String {
+    let scope = tenant_scope(tenant);
+    format!("greentic:state:{scope}:{}/", prefix.trim_end_matches('/'))
+}
+
+/// Reports whether `candidate` is `prefix` itself or one of its sub-prefixes `{prefix}/…`.
+///
+/// `pack/p1` covers `pack/p1` and `pack/p1/flow/ingest`, but not `pack/p10`.
+pub fn prefix_covers(prefix: &str, candidate: &str) -> bool {
+    candidate == prefix
+        || candidate
+            .strip_prefix(prefix.trim_end_matches('/'))
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Key of the lease `name` in the tenant's scope (see [`crate::lease`]).
+pub(crate) fn lease_key(tenant: &TenantCtx, name: &str) -> String {
+    format!("greentic:lease:{}:{name}", tenant_scope(tenant))
+}
+
+/// Key of the counter issuing fencing tokens for the lease `name`; it outlives the lease.
+#[cfg(feature = "redis")]
+pub(crate) fn lease_fence_key(tenant: &TenantCtx, name: &str) -> String {
+    format!("greentic:lease-fence:{}:{name}", tenant_scope(tenant))
+}
+
+/// Key of the rate limiter `key` in the tenant's scope (see [`crate::ratelimit`]).
+pub(crate) fn rate_limit_key(tenant: &TenantCtx, key: &str) -> String {
+    format!("greentic:ratelimit:{}:{key}", tenant_scope(tenant))
+}
 
-    if let Some(team) = tenant.team_id.as_ref().or(tenant.team.as_ref()) {
-        segments.push(team.as_ref());
+/// Label of a purged scope, as reported in [`crate::store::PurgeReport::scope`].
+pub(crate) fn purge_scope(tenant: &TenantCtx, team: Option<&str>, user: Option<&str>) -> String {
+    let mut scope = format!("{}:{}", tenant.env.as_str(), tenant.tenant_id.as_str());
+    if let Some(team) = team {
+        scope.push_str(&format!(":team={team}"));
     }
+    if let Some(user) = user {
+        scope.push_str(&format!(":user={user}"));
+    }
+    scope
+}
 
-    if let Some(user) = tenant.user_id.as_ref().or(tenant.user.as_ref()) {
-        segments.push(user.as_ref());
+/// Key of the Redis index listing the FQNs written by the team and/or user of `tenant`, or
+/// `None` for tenant-level scopes, which `purge_tenant` finds by scanning.
+#[cfg(feature = "redis")]
+pub(crate) fn scope_index_key(tenant: &TenantCtx) -> Option<String> {
+    match scope_members(tenant) {
+        (None, None) => None,
+        (team, user) => Some(scope_index_base(&purge_scope(tenant, team, user))),
     }
+}
+
+/// Redis key (or key prefix) of the purge index for a [`purge_scope`] label.
+#[cfg(feature = "redis")]
+pub(crate) fn scope_index_base(scope: &str) -> String {
+    format!("greentic:state-index:{scope}")
+}
+
+/// Scope segment (`{env}:{tenant}[:{team}][:{user}]`) embedded in every FQN.
+pub(crate) fn tenant_scope(tenant: &TenantCtx) -> String {
+    let mut segments = vec![tenant.env.as_str(), tenant.tenant_id.as_str()];
+
+    let (team, user) = scope_members(tenant);
+    segments.extend(team);
+    segments.extend(user);
 
     segments.join(":")
 }
 
+/// Team and user ids of a tenant scope, preferring the `_id` fields over the legacy ones.
+pub(crate) fn scope_members(tenant: &TenantCtx) -> (Option<&str>, Option<&str>) {
+    let team = tenant.team_id.as_ref().or(tenant.team.as_ref());
+    let user = tenant.user_id.as_ref().or(tenant.user.as_ref());
+    (
+        team.map(|team| team.as_str()),
+        user.map(|user| user.as_str()),
+    )
+}
+
+/// Rebuilds a [`TenantCtx`] from raw id strings, validating each one.
+pub(crate) fn tenant_from_ids(
+    env: &str,
+    tenant: &str,
+    team: Option<&str>,
+    user: Option<&str>,
+) -> GResult<TenantCtx> {
+    let env = EnvId::try_from(env).map_err(|err| invalid_input(format!("env `{env}`: {err}")))?;
+    let tenant = TenantId::try_from(tenant)
+        .map_err(|err| invalid_input(format!("tenant `{tenant}`: {err}")))?;
+    let team = team
+        .map(|team| {
+            TeamId::try_from(team).map_err(|err| invalid_input(format!("team `{team}`: {err}")))
+        })
+        .transpose()?;
+    let user = user
+        .map(|user| {
+            UserId::try_from(user).map_err(|err| invalid_input(format!("user `{user}`: {err}")))
+        })
+        .transpose()?;
+    Ok(TenantCtx::new(env, tenant).with_team(team).with_user(user))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,4 +198,13 @@ mod tests {
         let prefix = fqn_prefix(&ctx, "global");
         assert!(fqn(&ctx, "global", &key).as_str().starts_with(&prefix));
     }
+
+    #[test]
+    fn prefix_covers_sub_prefixes_only() {
+        assert!(prefix_covers("pack/p1", "pack/p1"));
+        assert!(prefix_covers("pack/p1", "pack/p1/flow/ingest"));
+        assert!(prefix_covers("pack/p1/", "pack/p1/flow/ingest"));
+        assert!(!prefix_covers("pack/p1", "pack/p10"));
+        assert!(!prefix_covers("pack/p1", "pack"));
+    }
 }
@@ -0,0 +1,92 @@
+//! Leases for mutual exclusion between runners sharing a backend.
+//!
+//! A lease is a named, tenant-scoped lock held by one owner until it is released or its TTL
+//! runs out, after which another owner can take it over. Every successful acquisition is handed
+//! a fencing token that is strictly greater than the tokens of earlier holders of the same name,
+//! so downstream writes can reject a holder whose lease has already been taken over.
+//!
+//! ```ignore
+//! let ttl = Duration::from_secs(30);
+//! if let Some(lease) = store.acquire_lease(&ctx, "session/s1", "node-a", ttl)? {
+//!     resume_session(lease.token)?;
+//!     store.release_lease(&lease)?;
+//! }
+//! ```
+
+use crate::error::invalid_input;
+use greentic_types::{GResult, TenantCtx};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Longest TTL a lease can be acquired or renewed for: one year.
+pub const MAX_LEASE_TTL: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// A lease held by `owner`, as returned by [`LeaseStore::acquire_lease`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lease {
+    /// Tenant the lease belongs to.
+    pub tenant: TenantCtx,
+    /// Lease name, e.g. `session/{session_id}`.
+    pub name: String,
+    /// Holder of the lease.
+    pub owner: String,
+    /// Fencing token; greater than the token of every earlier holder of `name`.
+    pub token: u64,
+    /// When the lease lapses unless renewed, according to the caller's clock.
+    pub expires_at: OffsetDateTime,
+}
+
+/// Backends offering leases natively.
+///
+/// Acquiring a lease the owner already holds refreshes its TTL and returns the same token, so
+/// owners must be unique per contender (e.g. a node id, or a node id plus a run id).
+pub trait LeaseStore: Send + Sync {
+    /// Takes the lease `name` for `owner` for `ttl`, returning `None` while someone else holds it.
+    fn acquire_lease(
+        &self,
+        tenant: &TenantCtx,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> GResult<Option<Lease>>;
+
+    /// Extends `lease` to `ttl` from now, returning `None` when it was lost to expiry or
+    /// another owner.
+    fn renew_lease(&self, lease: &Lease, ttl: Duration) -> GResult<Option<Lease>>;
+
+    /// Gives `lease` up, returning `false` when it was no longer held with its token.
+    fn release_lease(&self, lease: &Lease) -> GResult<bool>;
+}
+
+/// Checks the arguments of an acquisition and returns the TTL in milliseconds.
+pub(crate) fn check_acquire(name: &str, owner: &str, ttl: Duration) -> GResult<u64> {
+    if name.is_empty() {
+        return Err(invalid_input("lease name must not be empty"));
+    }
+    if owner.is_empty() {
+        return Err(invalid_input("lease owner must not be empty"));
+    }
+    lease_ttl_ms(ttl)
+}
+
+/// Converts a lease TTL to whole milliseconds, rejecting TTLs shorter than one or longer than
+/// [`MAX_LEASE_TTL`].
+pub(crate) fn lease_ttl_ms(ttl: Duration) -> GResult<u64> {
+    if ttl > MAX_LEASE_TTL {
+        return Err(invalid_input("lease ttl must not exceed a year"));
+    }
+    match ttl.as_millis() {
+        0 => Err(invalid_input("lease ttl must be at least 1ms")),
+        ms => Ok(ms as u64),
+    }
+}
+
+impl Lease {
+    /// Copy of this lease expiring `ttl` after `now`.
+    pub(crate) fn renewed(&self, now: OffsetDateTime, ttl: Duration) -> Self {
+        Self {
+            expires_at: now + ttl,
+            ..self.clone()
+        }
+    }
+}
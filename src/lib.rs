@@ -3,14 +3,40 @@
 
 //! Multi-tenant JSON state store primitives for Greentic runtimes.
 
+pub mod audit;
+pub mod cache;
+pub mod clock;
+pub mod codec;
+#[cfg(feature = "redis")]
+pub mod compress;
+#[cfg(feature = "testing")]
+pub mod conformance;
+#[cfg(feature = "encryption")]
+pub mod encrypt;
 pub mod error;
+#[cfg(feature = "testing")]
+pub mod faults;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "server")]
+pub mod http;
+pub mod idempotency;
 pub mod inmemory;
+pub mod instrument;
 pub mod key;
+pub mod lease;
+pub mod limits;
+pub mod migrate;
+pub mod ratelimit;
 #[cfg(feature = "redis")]
 pub mod redis_store;
+pub mod replay;
+pub mod scope;
+pub mod secrets;
+pub mod snapshot;
 pub mod store;
 pub mod util;
 
 pub use crate::key::{FqnKey, fqn, fqn_prefix};
-pub use crate::store::StateStore;
+pub use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
 pub use greentic_types::{StateKey, StatePath, TenantCtx};
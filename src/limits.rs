@@ -0,0 +1,130 @@
+//! Size and shape limits enforced by the backends on every write.
+
+use crate::error::invalid_input;
+use crate::key::StatePath;
+use crate::util::json_size;
+use greentic_types::GResult;
+use serde_json::Value;
+
+/// Bounds on the documents a backend accepts; violations fail with `InvalidInput`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateLimits {
+    /// Largest serialized JSON size of a whole document, in bytes.
+    pub max_document_bytes: u64,
+    /// Deepest nesting of arrays and objects in a document; scalars have depth 0.
+    pub max_depth: usize,
+    /// Longest array a path write may create or pad to (e.g. by writing `/items/999999`).
+    pub max_array_len: usize,
+}
+
+impl Default for StateLimits {
+    fn default() -> Self {
+        Self {
+            max_document_bytes: 32 * 1024 * 1024,
+            // serde_json refuses to parse documents nested more than 128 levels deep.
+            max_depth: 64,
+            max_array_len: 100_000,
+        }
+    }
+}
+
+impl StateLimits {
+    /// Limits that accept every document.
+    pub fn unlimited() -> Self {
+        Self {
+            max_document_bytes: u64::MAX,
+            max_depth: usize::MAX,
+            max_array_len: usize::MAX,
+        }
+    }
+
+    /// Checks a whole document against the depth and size limits.
+    pub fn check_document(&self, document: &Value) -> GResult<()> {
+        self.check_depth(document, 0)?;
+        let bytes = json_size(document);
+        if bytes > self.max_document_bytes {
+            return Err(invalid_input(format!(
+                "state document is {bytes} bytes, above the limit of {} bytes",
+                self.max_document_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that writing `value` at `path` cannot exceed the depth limit, before the
+    /// surrounding document is touched.
+    pub fn check_path_write(&self, path: &StatePath, value: &Value) -> GResult<()> {
+        self.check_depth(value, path.segments.len())
+    }
+
+    /// Rejects padding an array to `len` elements.
+    pub(crate) fn check_array_len(&self, len: usize) -> GResult<()> {
+        if len > self.max_array_len {
+            return Err(invalid_input(format!(
+                "path write would grow an array to {len} elements, above the limit of {}",
+                self.max_array_len
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_depth(&self, value: &Value, base: usize) -> GResult<()> {
+        let depth = base.saturating_add(depth(value, self.max_depth.saturating_sub(base)));
+        if depth > self.max_depth {
+            return Err(invalid_input(format!(
+                "state document nests deeper than the limit of {} levels",
+                self.max_depth
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Nesting depth of `value`, without recursion; stops counting once `limit` is exceeded.
+fn depth(value: &Value, limit: usize) -> usize {
+    let mut deepest = 0;
+    let mut pending = vec![(value, 1_usize)];
+    while let Some((value, level)) = pending.pop() {
+        match value {
+            Value::Array(items) => pending.extend(items.iter().map(|child| (child, level + 1))),
+            Value::Object(map) => pending.extend(map.values().map(|child| (child, level + 1))),
+            _ => continue,
+        }
+        deepest = deepest.max(level);
+        if deepest > limit {
+            break;
+        }
+    }
+    deepest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn depth_counts_containers() {
+        assert_eq!(depth(&json!(1), usize::MAX), 0);
+        assert_eq!(depth(&json!({"a": [1, {"b": []}]}), usize::MAX), 4);
+    }
+
+    #[test]
+    fn deep_documents_are_rejected_without_recursion() {
+        let mut value = json!(null);
+        for _ in 0..100_000 {
+            value = Value::Array(vec![value]);
+        }
+        let limits = StateLimits::default();
+        assert!(limits.check_document(&value).is_err());
+        assert!(
+            limits
+                .check_path_write(&StatePath::from_pointer("/a/b"), &json!([[1]]))
+                .is_ok()
+        );
+        // Dropping a deeply nested `Value` recurses, so unwind it iteratively.
+        while let Value::Array(mut items) = value {
+            value = items.pop().unwrap_or(Value::Null);
+        }
+    }
+}
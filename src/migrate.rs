@@ -0,0 +1,247 @@
+//! Copies state between backends, preserving TTLs, with resumable checkpoints and a final
+//! verification pass.
+
+use crate::error::{from_serde, invalid_input, with_context};
+use crate::key::tenant_scope;
+use crate::snapshot::read_record;
+use crate::store::{ScopedKey, StateStore};
+use greentic_types::{GResult, TenantCtx};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// Settings for [`migrate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationOptions {
+    /// Only copy entries under this prefix; `None` copies the tenant's whole scope.
+    pub prefix: Option<String>,
+    /// Report what would be copied without writing to the target.
+    pub dry_run: bool,
+    /// Compare source and target values once copying finished.
+    pub verify: bool,
+    /// File recording the last copied key so an interrupted run can resume.
+    pub checkpoint: Option<PathBuf>,
+    /// Label identifying the source (e.g. its location without credentials), recorded in the
+    /// checkpoint so that a run over another source does not resume from it.
+    pub source: Option<String>,
+    /// Number of copied entries between checkpoint writes.
+    pub checkpoint_every: usize,
+}
+
+impl Default for MigrationOptions {
+    fn default() -> Self {
+        Self {
+            prefix: None,
+            dry_run: false,
+            verify: true,
+            checkpoint: None,
+            source: None,
+            checkpoint_every: 100,
+        }
+    }
+}
+
+/// Cursor persisted by [`migrate`]: the run it belongs to and the last entry copied, in
+/// [`StateStore::list_keys`] order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationCheckpoint {
+    /// Tenant scope (`{env}:{tenant}[:{team}][:{user}]`) being migrated.
+    pub tenant: String,
+    /// Prefix filter of the run ([`MigrationOptions::prefix`]).
+    pub filter: Option<String>,
+    /// Source label of the run ([`MigrationOptions::source`]).
+    pub source: Option<String>,
+    /// Prefix of the last copied entry.
+    pub prefix: String,
+    /// Key of the last copied entry.
+    pub key: String,
+}
+
+impl MigrationCheckpoint {
+    /// Checkpoint of a run over `tenant` with `options`, positioned after `entry`.
+    pub fn new(tenant: &TenantCtx, options: &MigrationOptions, entry: &ScopedKey) -> Self {
+        Self {
+            tenant: tenant_scope(tenant),
+            filter: options.prefix.clone(),
+            source: options.source.clone(),
+            prefix: entry.prefix.clone(),
+            key: entry.key.as_str().to_owned(),
+        }
+    }
+
+    /// Loads a checkpoint, returning `None` when the file does not exist.
+    pub fn load(path: &Path) -> GResult<Option<Self>> {
+        let raw = match fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(with_context(err, "read migration checkpoint")),
+        };
+        serde_json::from_str(&raw).map(Some).map_err(from_serde)
+    }
+
+    /// Atomically replaces the checkpoint file.
+    pub fn save(&self, path: &Path) -> GResult<()> {
+        let payload = serde_json::to_string(self).map_err(from_serde)?;
+        let staging = path.with_extension("tmp");
+        fs::write(&staging, payload)
+            .map_err(|err| with_context(err, "write migration checkpoint"))?;
+        fs::rename(&staging, path).map_err(|err| with_context(err, "write migration checkpoint"))
+    }
+
+    fn covers(&self, entry: &ScopedKey) -> bool {
+        (entry.prefix.as_str(), entry.key.as_str()) <= (self.prefix.as_str(), self.key.as_str())
+    }
+
+    /// Fails unless the checkpoint was written by a run over `tenant` with `options`.
+    fn ensure_run(&self, tenant: &TenantCtx, options: &MigrationOptions) -> GResult<()> {
+        let tenant = tenant_scope(tenant);
+        if self.tenant == tenant && self.filter == options.prefix && self.source == options.source {
+            return Ok(());
+        }
+        Err(invalid_input(format!(
+            "migration checkpoint belongs to another run (tenant `{}`, prefix {:?}, source \
+             {:?}) than this one (tenant `{tenant}`, prefix {:?}, source {:?}); delete it to \
+             start over",
+            self.tenant, self.filter, self.source, options.prefix, options.source
+        )))
+    }
+}
+
+/// Outcome of a [`migrate`] run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    /// Entries listed in the source.
+    pub scanned: u64,
+    /// Entries written to the target (or that would be, for dry runs).
+    pub copied: u64,
+    /// Entries skipped because an earlier run already copied them.
+    pub resumed: u64,
+    /// Entries that disappeared from the source before they could be copied.
+    pub vanished: u64,
+    /// Entries whose target value matched the source during verification.
+    pub verified: u64,
+    /// Entries whose target value differs from the source (or is missing).
+    pub mismatches: Vec<String>,
+    /// Whether this was a dry run.
+    pub dry_run: bool,
+}
+
+impl MigrationReport {
+    /// Returns `true` when verification found no differences.
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// Copies every entry of `tenant` (optionally narrowed to a prefix) from `source` to `target`.
+///
+/// Entries are copied in [`StateStore::list_keys`] order with their remaining TTL. When a
+/// checkpoint file is configured the run resumes after the last recorded entry, and the file is
+/// removed once the run completes without mismatches. A checkpoint written for another tenant,
+/// prefix filter or source is refused with `InvalidInput`.
+pub fn migrate<S, T>(
+    source: &S,
+    target: &T,
+    tenant: &TenantCtx,
+    options: &MigrationOptions,
+) -> GResult<MigrationReport>
+where
+    S: StateStore + ?Sized,
+    T: StateStore + ?Sized,
+{
+    let checkpoint_path = options.checkpoint.as_deref();
+    let resume_from = match checkpoint_path {
+        Some(path) => MigrationCheckpoint::load(path)?,
+        None => None,
+    };
+    if let Some(checkpoint) = &resume_from {
+        checkpoint.ensure_run(tenant, options)?;
+    }
+    let keys = source.list_keys(tenant, options.prefix.as_deref())?;
+    let mut report = MigrationReport {
+        scanned: keys.len() as u64,
+        dry_run: options.dry_run,
+        ..MigrationReport::default()
+    };
+
+    let mut since_checkpoint = 0;
+    let mut last_copied = None;
+    for entry in &keys {
+        if resume_from
+            .as_ref()
+            .is_some_and(|cursor| cursor.covers(entry))
+        {
+            report.resumed += 1;
+            continue;
+        }
+        let Some(record) = read_record(source, tenant, entry)? else {
+            report.vanished += 1;
+            continue;
+        };
+        if !options.dry_run {
+            target.set_json(
+                tenant,
+                &entry.prefix,
+                &entry.key,
+                None,
+                &record.value,
+                record.ttl_arg(),
+            )?;
+        }
+        report.copied += 1;
+        last_copied = Some(entry);
+
+        since_checkpoint += 1;
+        if since_checkpoint >= options.checkpoint_every.max(1) && !options.dry_run {
+            if let Some(path) = checkpoint_path {
+                MigrationCheckpoint::new(tenant, options, entry).save(path)?;
+                debug!(prefix = %entry.prefix, key = %entry.key, "saved migration checkpoint");
+            }
+            since_checkpoint = 0;
+        }
+    }
+
+    if options.dry_run {
+        return Ok(report);
+    }
+    if let (Some(path), Some(entry)) = (checkpoint_path, last_copied) {
+        MigrationCheckpoint::new(tenant, options, entry).save(path)?;
+    }
+
+    if options.verify {
+        for entry in &keys {
+            let expected = source.get_json(tenant, &entry.prefix, &entry.key, None)?;
+            let Some(expected) = expected else {
+                continue;
+            };
+            let actual = target.get_json(tenant, &entry.prefix, &entry.key, None)?;
+            if actual.as_ref() == Some(&expected) {
+                report.verified += 1;
+            } else {
+                report
+                    .mismatches
+                    .push(format!("{}:{}", entry.prefix, entry.key));
+            }
+        }
+    }
+
+    if report.is_consistent()
+        && let Some(path) = checkpoint_path
+    {
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(with_context(err, "remove migration checkpoint")),
+        }
+    }
+
+    info!(
+        scanned = report.scanned,
+        copied = report.copied,
+        resumed = report.resumed,
+        mismatches = report.mismatches.len(),
+        "state migration finished"
+    );
+    Ok(report)
+}
@@ -0,0 +1,205 @@
+//! Token-bucket and sliding-window rate limiters scoped per tenant and key.
+//!
+//! Each check is atomic: Redis runs it as a Lua script timed by the server clock, the in-memory
+//! store under the key's lock. Limiter state is dropped once it would be indistinguishable from a
+//! fresh limiter, so idle keys cost nothing: Redis keeps it in `greentic:ratelimit:*` keys whose
+//! TTL runs out at that point, while the in-memory store keeps it in a map separate from its
+//! entries, forgetting a key when a check finds it idle and sweeping idle keys periodically.
+//!
+//! ```ignore
+//! let limiter = RateLimiter::new(store, RateLimit::sliding_window(100, Duration::from_secs(60)));
+//! limiter.enforce(&ctx, &format!("webhook/{user_id}"))?;
+//! ```
+
+use crate::error::{invalid_input, rate_limited};
+use greentic_types::{GResult, TenantCtx};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest window, refill interval and time for a bucket to refill from empty: one year.
+pub const MAX_RATE_PERIOD: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Allowance enforced by a rate limiter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimit {
+    /// Holds up to `capacity` tokens and adds `refill` tokens per `interval`, continuously. Bursts
+    /// of up to `capacity` pass at once.
+    TokenBucket {
+        /// Most tokens the bucket holds; a new bucket starts full.
+        capacity: u32,
+        /// Tokens added per `interval`.
+        refill: u32,
+        /// Refill period; at least 1ms. Refilling an empty bucket may take at most
+        /// [`MAX_RATE_PERIOD`].
+        interval: Duration,
+    },
+    /// Allows `limit` units within any trailing `window`.
+    SlidingWindow {
+        /// Units granted per window.
+        limit: u32,
+        /// Window length; at least 1ms and at most [`MAX_RATE_PERIOD`].
+        window: Duration,
+    },
+}
+
+impl RateLimit {
+    /// Token bucket holding `capacity` tokens and refilling `refill` tokens per `interval`.
+    pub fn token_bucket(capacity: u32, refill: u32, interval: Duration) -> Self {
+        Self::TokenBucket {
+            capacity,
+            refill,
+            interval,
+        }
+    }
+
+    /// Sliding window allowing `limit` units per `window`.
+    pub fn sliding_window(limit: u32, window: Duration) -> Self {
+        Self::SlidingWindow { limit, window }
+    }
+}
+
+/// Outcome of a rate limit check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateDecision {
+    /// Whether the units were granted (and consumed).
+    pub allowed: bool,
+    /// Units that could still be granted right now.
+    pub remaining: u32,
+    /// When a denied request could be granted, at the earliest.
+    pub retry_after: Option<Duration>,
+}
+
+/// Backends offering rate limiters natively.
+pub trait RateLimitStore: Send + Sync {
+    /// Consumes `cost` units of `limit` for `key`, or consumes nothing when fewer remain.
+    ///
+    /// Switching the kind of limit used for a key starts it afresh.
+    fn check_rate(
+        &self,
+        tenant: &TenantCtx,
+        key: &str,
+        limit: &RateLimit,
+        cost: u32,
+    ) -> GResult<RateDecision>;
+
+    /// Forgets the usage recorded for `key`, returning `false` when there was none.
+    fn reset_rate(&self, tenant: &TenantCtx, key: &str) -> GResult<bool>;
+}
+
+/// Limits in whole milliseconds, as evaluated by the backends.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum RateLimitMs {
+    TokenBucket {
+        capacity: u32,
+        refill: u32,
+        interval_ms: u64,
+    },
+    SlidingWindow {
+        limit: u32,
+        window_ms: u64,
+    },
+}
+
+/// Checks the arguments of [`RateLimitStore::check_rate`] and converts the limit to milliseconds.
+pub(crate) fn check_rate_args(key: &str, limit: &RateLimit, cost: u32) -> GResult<RateLimitMs> {
+    if key.is_empty() {
+        return Err(invalid_input("rate limit key must not be empty"));
+    }
+    let (limit, max_cost) = match *limit {
+        RateLimit::TokenBucket {
+            capacity,
+            refill,
+            interval,
+        } => {
+            if refill == 0 {
+                return Err(invalid_input("token bucket refill must be positive"));
+            }
+            let interval_ms = duration_ms(interval, "token bucket interval")?;
+            let full_refill_ms =
+                u128::from(capacity) * u128::from(interval_ms) / u128::from(refill);
+            if full_refill_ms > MAX_RATE_PERIOD.as_millis() {
+                return Err(invalid_input(
+                    "token bucket must refill from empty within a year",
+                ));
+            }
+            (
+                RateLimitMs::TokenBucket {
+                    capacity,
+                    refill,
+                    interval_ms,
+                },
+                capacity,
+            )
+        }
+        RateLimit::SlidingWindow { limit, window } => {
+            let window_ms = duration_ms(window, "sliding window")?;
+            (RateLimitMs::SlidingWindow { limit, window_ms }, limit)
+        }
+    };
+    if max_cost == 0 {
+        return Err(invalid_input("rate limit must allow at least one unit"));
+    }
+    if cost > max_cost {
+        return Err(invalid_input(format!(
+            "cost {cost} exceeds the rate limit of {max_cost}"
+        )));
+    }
+    Ok(limit)
+}
+
+fn duration_ms(duration: Duration, what: &str) -> GResult<u64> {
+    if duration > MAX_RATE_PERIOD {
+        return Err(invalid_input(format!("{what} must not exceed a year")));
+    }
+    match duration.as_millis() {
+        0 => Err(invalid_input(format!("{what} must be at least 1ms"))),
+        // Bounded by `MAX_RATE_PERIOD` above.
+        ms => Ok(ms as u64),
+    }
+}
+
+/// Applies a [`RateLimit`] to keys of one backend.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limit: RateLimit,
+}
+
+impl RateLimiter {
+    /// Enforces `limit` with state kept in `store`.
+    pub fn new(store: Arc<dyn RateLimitStore>, limit: RateLimit) -> Self {
+        Self { store, limit }
+    }
+
+    /// Returns the enforced limit.
+    pub fn limit(&self) -> &RateLimit {
+        &self.limit
+    }
+
+    /// Consumes one unit for `key`.
+    pub fn check(&self, tenant: &TenantCtx, key: &str) -> GResult<RateDecision> {
+        self.check_cost(tenant, key, 1)
+    }
+
+    /// Consumes `cost` units for `key`.
+    pub fn check_cost(&self, tenant: &TenantCtx, key: &str, cost: u32) -> GResult<RateDecision> {
+        self.store.check_rate(tenant, key, &self.limit, cost)
+    }
+
+    /// Consumes one unit for `key`, failing with `RateLimited` when none remain.
+    pub fn enforce(&self, tenant: &TenantCtx, key: &str) -> GResult<RateDecision> {
+        let decision = self.check(tenant, key)?;
+        if decision.allowed {
+            return Ok(decision);
+        }
+        let retry_ms = decision.retry_after.unwrap_or_default().as_millis();
+        Err(rate_limited(format!(
+            "rate limit for `{key}` exceeded; retry in {retry_ms}ms"
+        )))
+    }
+
+    /// Forgets the usage recorded for `key`.
+    pub fn reset(&self, tenant: &TenantCtx, key: &str) -> GResult<bool> {
+        self.store.reset_rate(tenant, key)
+    }
+}
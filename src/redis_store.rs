@@ -1,12 +1,52 @@
-use crate::error::{from_redis, from_serde, internal};
-use crate::key::{FqnKey, StatePath, fqn, fqn_prefix};
-use crate::store::StateStore;
-use crate::util::{get_at_path, set_at_path};
-use greentic_types::{GResult, StateKey, TenantCtx};
-use parking_lot::Mutex;
-use redis::{Commands, Connection, RedisResult, Script};
+use crate::codec::ValueCodec;
+use crate::compress::{self, CompressionConfig};
+use crate::error::{from_redis, from_serde, internal, invalid_input, unavailable};
+use crate::key::{
+    FqnKey, StatePath, fqn, fqn_prefix, fqn_scope_prefix, fqn_subprefix_base, lease_fence_key,
+    lease_key, purge_scope, rate_limit_key, scope_index_base, scope_index_key, scope_members,
+};
+use crate::lease::{Lease, LeaseStore, check_acquire, lease_ttl_ms};
+use crate::limits::StateLimits;
+use crate::ratelimit::{RateDecision, RateLimit, RateLimitMs, RateLimitStore, check_rate_args};
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::{get_at_path, set_at_path_with_limits};
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use redis::{
+    Commands, Connection, ConnectionLike, ErrorKind, RedisError, RedisResult, RetryMethod, Script,
+};
 use serde_json::Value;
-use tracing::debug;
+use std::collections::HashSet;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{debug, warn};
+
+/// Records `KEYS[1]` in the purge index `KEYS[2]`, when one is passed, scored by its expiry in
+/// unix milliseconds (`+inf` when persistent), and drops index members that have already
+/// expired. Prepended to the write scripts so the entry and its index change together.
+const INDEX_LUA: &str = r#"
+local function index_entry()
+  local index = KEYS[2]
+  if index == nil then
+    return
+  end
+  local pttl = redis.call("PTTL", KEYS[1])
+  local clock = redis.call("TIME")
+  local now = tonumber(clock[1]) * 1000 + math.floor(tonumber(clock[2]) / 1000)
+  redis.call("ZREMRANGEBYSCORE", index, "-inf", now)
+  if pttl == -2 then
+    redis.call("ZREM", index, KEYS[1])
+    return
+  end
+  local score = "+inf"
+  if pttl >= 0 then
+    score = now + pttl
+  end
+  redis.call("ZADD", index, score, KEYS[1])
+end
+"#;
 
 const UPSERT_LUA: &str = r#"
 local key = KEYS[1]
@@ -15,12 +55,14 @@ local ttl_ms = tonumber(ARGV[2])
 
 if ttl_ms ~= nil and ttl_ms > 0 then
   redis.call("SET", key, payload, "PX", ttl_ms)
+  index_entry()
   return ttl_ms
 end
 
 if ttl_ms == 0 then
   redis.call("SET", key, payload)
   redis.call("PERSIST", key)
+  index_entry()
   return ttl_ms
 end
 
@@ -30,58 +72,553 @@ if current_ttl > 0 then
 else
   redis.call("SET", key, payload)
 end
+index_entry()
 return current_ttl
 "#;
 
+/// Resolves pointer segments into a JSONPath, choosing array or object syntax from the container
+/// found at each level so numeric segments keep the semantics of [`crate::util::get_at_path`].
+/// Returns `nil` when the path is missing or a segment cannot be quoted safely.
+const JSON_PATH_LUA: &str = r#"
+local function quotable(segment)
+  return string.find(segment, "['\\]") == nil
+end
+
+local function child_path(key, path, segment)
+  local kind = redis.call("JSON.TYPE", key, path)[1]
+  if kind == "array" then
+    if string.match(segment, "^%d+$") == nil or #segment > 9 then
+      return nil
+    end
+    return path .. "[" .. tonumber(segment) .. "]"
+  end
+  if kind == "object" and quotable(segment) then
+    return path .. "['" .. segment .. "']"
+  end
+  return nil
+end
+
+local function resolve_path(key, segments, count)
+  local path = "$"
+  for i = 1, count do
+    path = child_path(key, path, segments[i])
+    if path == nil then
+      return nil
+    end
+  end
+  return path
+end
+"#;
+
+const JSON_GET_LUA: &str = r#"
+local key = KEYS[1]
+local kind = redis.call("TYPE", key)["ok"]
+if kind == "none" then
+  return false
+end
+if kind == "string" then
+  return {"string", redis.call("GET", key), 0}
+end
+
+local path = resolve_path(key, ARGV, #ARGV)
+if path == nil then
+  return {"json", redis.call("JSON.GET", key, "$"), 0}
+end
+return {"json", redis.call("JSON.GET", key, path), 1}
+"#;
+
+const JSON_SET_LUA: &str = r#"
+local key = KEYS[1]
+local payload = ARGV[1]
+local ttl_ms = tonumber(ARGV[2])
+local max_bytes = tonumber(ARGV[3])
+local segments = {}
+for i = 4, #ARGV do
+  segments[#segments + 1] = ARGV[i]
+end
+
+local kind = redis.call("TYPE", key)["ok"]
+local previous_ttl = redis.call("PTTL", key)
+
+if #segments > 0 then
+  if kind ~= "ReJSON-RL" then
+    return 0
+  end
+  local parent = resolve_path(key, segments, #segments - 1)
+  if parent == nil then
+    return 0
+  end
+  local last = segments[#segments]
+  local parent_kind = redis.call("JSON.TYPE", key, parent)[1]
+  local child = nil
+  local appended = false
+  if parent_kind == "object" and quotable(last) then
+    child = parent .. "['" .. last .. "']"
+  elseif parent_kind == "array" and string.match(last, "^%d+$") ~= nil and #last <= 9 then
+    local index = tonumber(last)
+    local len = redis.call("JSON.ARRLEN", key, parent)[1]
+    if index < len then
+      child = parent .. "[" .. index .. "]"
+    elseif index == len then
+      appended = true
+    else
+      return 0
+    end
+  else
+    return 0
+  end
+  local replaced = nil
+  if appended then
+    redis.call("JSON.ARRAPPEND", key, parent, payload)
+  else
+    replaced = redis.call("JSON.GET", key, child)
+    redis.call("JSON.SET", key, child, payload)
+  end
+  -- The limit applies to the merged document, so measure it after the write and undo the
+  -- write when it grew too large.
+  if max_bytes >= 0 and #redis.call("JSON.GET", key) > max_bytes then
+    if appended then
+      redis.call("JSON.ARRPOP", key, parent)
+    elseif replaced == "[]" then
+      redis.call("JSON.DEL", key, child)
+    else
+      redis.call("JSON.SET", key, child, string.sub(replaced, 2, -2))
+    end
+    return -1
+  end
+else
+  if kind ~= "none" and kind ~= "ReJSON-RL" then
+    redis.call("DEL", key)
+  end
+  redis.call("JSON.SET", key, "$", payload)
+end
+
+if ttl_ms > 0 then
+  redis.call("PEXPIRE", key, ttl_ms)
+elseif ttl_ms == 0 then
+  redis.call("PERSIST", key)
+elseif previous_ttl > 0 and redis.call("PTTL", key) < 0 then
+  redis.call("PEXPIRE", key, previous_ttl)
+end
+index_entry()
+return 1
+"#;
+
+/// Reports `size, pttl` pairs for `KEYS`; the size is `-1` for keys that no longer exist.
+const PREFIX_STATS_LUA: &str = r#"
+local out = {}
+for _, key in ipairs(KEYS) do
+  local kind = redis.call("TYPE", key)["ok"]
+  local size = -1
+  if kind == "string" then
+    size = redis.call("STRLEN", key)
+  elseif kind == "ReJSON-RL" then
+    size = redis.call("JSON.DEBUG", "MEMORY", key)
+  end
+  table.insert(out, size)
+  table.insert(out, redis.call("PTTL", key))
+end
+return out
+"#;
+
+/// Writes `ARGV[1]` to `KEYS[1]` unless it exists, as a RedisJSON document when `ARGV[3]` is
+/// `json` and as a string otherwise, expiring after `ARGV[2]` ms when positive. A write is
+/// recorded in the purge index `KEYS[2]`, when given.
+const SET_IF_ABSENT_LUA: &str = r#"
+if redis.call("EXISTS", KEYS[1]) == 1 then
+  return 0
+end
+if ARGV[3] == "json" then
+  redis.call("JSON.SET", KEYS[1], "$", ARGV[1])
+else
+  redis.call("SET", KEYS[1], ARGV[1])
+end
+if tonumber(ARGV[2]) > 0 then
+  redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+index_entry()
+return 1
+"#;
+
+/// Takes the lease `KEYS[1]` for owner `ARGV[1]` for `ARGV[2]` ms, drawing its fencing token
+/// from `KEYS[2]`. The lease value is `{token}:{owner}`; a lease already held by the same owner
+/// is extended and keeps its token, so a retried call is harmless. Returns nil when held by
+/// another owner.
+const LEASE_ACQUIRE_LUA: &str = r#"
+local current = redis.call("GET", KEYS[1])
+if current then
+  local token, holder = string.match(current, "^(%d+):(.*)$")
+  if holder ~= ARGV[1] then
+    return false
+  end
+  redis.call("PEXPIRE", KEYS[1], ARGV[2])
+  return tonumber(token)
+end
+local token = redis.call("INCR", KEYS[2])
+redis.call("SET", KEYS[1], token .. ":" .. ARGV[1], "NX", "PX", ARGV[2])
+return token
+"#;
+
+/// Extends the lease `KEYS[1]` to `ARGV[2]` ms when it still holds `ARGV[1]`.
+const LEASE_RENEW_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+  return redis.call("PEXPIRE", KEYS[1], ARGV[2])
+end
+return 0
+"#;
+
+/// Deletes the lease `KEYS[1]` when it still holds `ARGV[1]`.
+const LEASE_RELEASE_LUA: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+  return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Takes `ARGV[4]` tokens from the bucket `KEYS[1]` holding up to `ARGV[1]` tokens and refilling
+/// `ARGV[2]` per `ARGV[3]` ms, timed by the server clock. The bucket is a hash of `tokens` and
+/// `updated` (unix ms) that expires once it would have refilled completely. Returns
+/// `{allowed, remaining, retry_after_ms}`.
+const TOKEN_BUCKET_LUA: &str = r#"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2]) / tonumber(ARGV[3])
+local cost = tonumber(ARGV[4])
+local time = redis.call("TIME")
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local kind = redis.call("TYPE", KEYS[1]).ok
+if kind ~= "none" and kind ~= "hash" then
+  redis.call("DEL", KEYS[1])
+end
+local state = redis.call("HMGET", KEYS[1], "tokens", "updated")
+local tokens = tonumber(state[1]) or capacity
+local updated = tonumber(state[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - updated) * rate)
+local allowed, retry = 1, 0
+if tokens >= cost then
+  tokens = tokens - cost
+else
+  allowed, retry = 0, math.ceil((cost - tokens) / rate)
+end
+local idle = math.ceil((capacity - tokens) / rate)
+if idle > 0 then
+  redis.call("HSET", KEYS[1], "tokens", tostring(tokens), "updated", now)
+  redis.call("PEXPIRE", KEYS[1], idle)
+else
+  redis.call("DEL", KEYS[1])
+end
+return {allowed, math.floor(tokens), retry}
+"#;
+
+/// Grants `ARGV[3]` units from the sliding window `KEYS[1]` allowing `ARGV[1]` units per
+/// `ARGV[2]` ms, timed by the server clock. The window is a sorted set with one member per
+/// granted unit scored by its grant time (unix ms); it expires with its newest unit. Returns
+/// `{allowed, remaining, retry_after_ms}`.
+const SLIDING_WINDOW_LUA: &str = r#"
+local limit = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+local time = redis.call("TIME")
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local kind = redis.call("TYPE", KEYS[1]).ok
+if kind ~= "none" and kind ~= "zset" then
+  redis.call("DEL", KEYS[1])
+end
+redis.call("ZREMRANGEBYSCORE", KEYS[1], "-inf", now - window)
+local used = redis.call("ZCARD", KEYS[1])
+if used + cost > limit then
+  local needed = used + cost - limit - 1
+  local oldest = redis.call("ZRANGE", KEYS[1], needed, needed, "WITHSCORES")
+  return {0, math.max(0, limit - used), tonumber(oldest[2]) + window - now}
+end
+if cost > 0 then
+  local stamp = time[1] .. "." .. time[2] .. ":"
+  local seq = 0
+  while redis.call("ZSCORE", KEYS[1], stamp .. seq) do
+    seq = seq + 1
+  end
+  for i = seq, seq + cost - 1 do
+    redis.call("ZADD", KEYS[1], now, stamp .. i)
+  end
+  redis.call("PEXPIRE", KEYS[1], window)
+end
+return {1, math.max(0, limit - used - cost), 0}
+"#;
+
+/// Keys handed to a single `DEL` or stats script call.
+const KEY_CHUNK: usize = 256;
+
+/// Controls whether [`RedisStateStore`] uses the RedisJSON module for path reads and writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JsonModuleMode {
+    /// Always store documents as JSON strings (the default).
+    #[default]
+    Disabled,
+    /// Use `JSON.*` commands when the server reports the module, string mode otherwise.
+    Auto,
+    /// Use `JSON.*` commands and fail with `Unavailable` when the module is missing.
+    Required,
+}
+
 /// Redis-backed [`StateStore`] implementation.
+///
+/// Commands run on connections checked out from an internal pool. Connections that hit an
+/// unrecoverable error are evicted instead of being handed out again, and idempotent commands
+/// are retried with exponential backoff. Use [`RedisStateStore::builder`] to tune the pool.
+///
+/// Writes from a team or user scope are also recorded in a per-scope index
+/// (`greentic:state-index:{env}:{tenant}[:team={team}][:user={user}]`) that
+/// [`StateStore::purge_team`] and [`StateStore::purge_user`] walk; entries written before the
+/// index existed are only reached by [`StateStore::purge_tenant`].
 pub struct RedisStateStore {
-    client: redis::Client,
-    connection: Mutex<Option<Connection>>,
+    pool: Pool<RedisConnectionManager>,
+    retry: RetryPolicy,
     upsert_script: Script,
+    json_mode: JsonModuleMode,
+    json_active: OnceLock<bool>,
+    json_get_script: Script,
+    json_set_script: Script,
+    stats_script: Script,
+    set_if_absent_script: Script,
+    index_script: Script,
+    lease_acquire_script: Script,
+    lease_renew_script: Script,
+    lease_release_script: Script,
+    token_bucket_script: Script,
+    sliding_window_script: Script,
+    compression: Option<CompressionConfig>,
+    codec: ValueCodec,
+    limits: StateLimits,
 }
 
 impl RedisStateStore {
-    /// Creates a store using an existing Redis client.
+    /// Creates a store using an existing Redis client and the default pool settings.
     pub fn new(client: redis::Client) -> Self {
-        Self {
-            client,
-            connection: Mutex::new(None),
-            upsert_script: Script::new(UPSERT_LUA),
-        }
+        RedisStateStoreBuilder::new(client).assemble()
     }
 
     /// Builds a store by connecting to the provided Redis URL.
     pub fn from_url(redis_url: impl AsRef<str>) -> GResult<Self> {
-        let client = redis::Client::open(redis_url.as_ref())
-            .map_err(|err| from_redis(err, "connect redis"))?;
-        Ok(Self::new(client))
+        Ok(RedisStateStoreBuilder::from_url(redis_url)?.assemble())
+    }
+
+    /// Starts configuring a store for the provided Redis client.
+    pub fn builder(client: redis::Client) -> RedisStateStoreBuilder {
+        RedisStateStoreBuilder::new(client)
+    }
+
+    /// Runs `f` on a pooled connection, retrying transient failures with backoff.
+    ///
+    /// Only use this for idempotent commands: a retried command may already have been applied
+    /// by the server when the connection dropped.
+    fn with_connection<T>(&self, f: impl FnMut(&mut Connection) -> RedisResult<T>) -> GResult<T> {
+        self.run_command(self.retry.max_retries, f)
+    }
+
+    /// Runs `f` on a pooled connection exactly once, for commands whose reply depends on
+    /// whether they were applied before (e.g. `SET NX`). A lost reply surfaces as an error
+    /// instead of being replaced by the reply of a retry.
+    fn with_connection_once<T>(
+        &self,
+        f: impl FnMut(&mut Connection) -> RedisResult<T>,
+    ) -> GResult<T> {
+        self.run_command(0, f)
     }
 
-    fn with_connection<T>(
+    fn run_command<T>(
         &self,
+        max_retries: u32,
         mut f: impl FnMut(&mut Connection) -> RedisResult<T>,
     ) -> GResult<T> {
-        let mut guard = self.connection.lock();
-        if guard.is_none() {
-            *guard = Some(
-                self.client
-                    .get_connection()
-                    .map_err(|err| from_redis(err, "connect redis"))?,
-            );
+        let mut attempt = 0;
+        loop {
+            let mut conn = self.checkout()?;
+            let err = match f(&mut conn.inner) {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+            if err.is_unrecoverable_error() {
+                conn.broken = true;
+            }
+            drop(conn);
+
+            if attempt >= max_retries || !is_transient(&err) {
+                return Err(from_redis(err, "redis command"));
+            }
+            let delay = self.retry.backoff(attempt);
+            warn!(attempt, delay_ms = delay.as_millis() as u64, error = %err, "retrying redis command");
+            thread::sleep(delay);
+            attempt += 1;
         }
+    }
 
-        let Some(conn) = guard.as_mut() else {
-            return Err(internal("redis connection not initialized"));
+    fn checkout(&self) -> GResult<PooledConnection<RedisConnectionManager>> {
+        self.pool
+            .get()
+            .map_err(|err| unavailable(format!("checkout redis connection: {err}")))
+    }
+
+    /// Records the existing entries of `prefix` in the purge index of `tenant`'s team/user scope
+    /// and returns how many were recorded.
+    ///
+    /// Writes record themselves, so this is only needed once per team/user prefix for entries
+    /// written before purge indexes existed: `purge_team` and `purge_user` only delete indexed
+    /// entries, since scope segments cannot be told apart from prefixes in FQNs alone.
+    pub fn backfill_purge_index(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let Some(index) = scope_index_key(tenant) else {
+            return Err(invalid_input(
+                "purge indexes only cover team and user scopes; set a team or user",
+            ));
         };
-        f(conn).map_err(|err| from_redis(err, "redis command"))
+        let keys = self.scan_keys(&format!("{}*", glob_escape(&fqn_prefix(tenant, prefix))))?;
+        for key in &keys {
+            self.with_connection(|conn| {
+                self.index_script
+                    .key(key.as_str())
+                    .key(&index)
+                    .invoke::<i64>(conn)
+            })?;
+        }
+        Ok(keys.len() as u64)
+    }
+
+    /// Reports whether documents are read and written through the RedisJSON module.
+    ///
+    /// With [`JsonModuleMode::Auto`] the first call probes the server and caches the outcome.
+    pub fn json_module_active(&self) -> GResult<bool> {
+        if self.json_mode == JsonModuleMode::Disabled {
+            return Ok(false);
+        }
+        if let Some(active) = self.json_active.get() {
+            return Ok(*active);
+        }
+
+        let reply: Vec<redis::Value> = self.with_connection(|conn| {
+            redis::cmd("COMMAND")
+                .arg("INFO")
+                .arg("JSON.GET")
+                .query(conn)
+        })?;
+        let available = reply
+            .first()
+            .is_some_and(|info| !matches!(info, redis::Value::Nil));
+        if !available && self.json_mode == JsonModuleMode::Required {
+            return Err(unavailable(
+                "redis server does not provide the RedisJSON module",
+            ));
+        }
+        if available {
+            debug!("using RedisJSON module for state documents");
+        }
+        Ok(*self.json_active.get_or_init(|| available))
+    }
+
+    fn json_get(&self, key: &FqnKey, path: Option<&StatePath>) -> GResult<Option<Value>> {
+        let segments = path
+            .map(|path| path.segments.as_slice())
+            .unwrap_or_default();
+        let reply: Option<(String, Vec<u8>, i64)> = self.with_connection(|conn| {
+            let mut invocation = self.json_get_script.key(key.as_ref());
+            for segment in segments {
+                invocation.arg(segment.as_str());
+            }
+            invocation.invoke(conn)
+        })?;
+        let Some((kind, payload, resolved)) = reply else {
+            return Ok(None);
+        };
+
+        let document = if kind == "json" {
+            let mut matches: Vec<Value> = serde_json::from_slice(&payload).map_err(from_serde)?;
+            if matches.is_empty() {
+                return Ok(None);
+            }
+            let first = matches.swap_remove(0);
+            if resolved == 1 {
+                return Ok(Some(first));
+            }
+            first
+        } else {
+            Self::decode_document(&payload)?
+        };
+
+        match path {
+            Some(path) => Ok(get_at_path(&document, path).cloned()),
+            None => Ok(Some(document)),
+        }
+    }
+
+    fn json_set(
+        &self,
+        key: &FqnKey,
+        index: Option<&str>,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        match path {
+            Some(path) => {
+                // The server patches the document in place and checks the merged size itself;
+                // padding falls back to the client-side path below.
+                self.limits.check_path_write(path, value)?;
+                self.limits.check_document(value)?;
+            }
+            None => self.limits.check_document(value)?,
+        }
+        let payload = serde_json::to_string(value).map_err(from_serde)?;
+        let ttl = Self::ttl_arg(ttl_secs);
+        let segments = path
+            .map(|path| path.segments.as_slice())
+            .unwrap_or_default();
+        // Lua numbers are doubles, so limits past 2^53 are treated as unlimited.
+        let max_bytes = i64::try_from(self.limits.max_document_bytes)
+            .ok()
+            .filter(|max| *max < 1 << 53)
+            .unwrap_or(-1);
+        let applied: i64 = self.with_connection(|conn| {
+            let mut invocation = self.json_set_script.key(key.as_ref());
+            if let Some(index) = index {
+                invocation.key(index);
+            }
+            invocation.arg(payload.as_str()).arg(ttl).arg(max_bytes);
+            for segment in segments {
+                invocation.arg(segment.as_str());
+            }
+            invocation.invoke(conn)
+        })?;
+        match applied {
+            1 => return Ok(()),
+            -1 => {
+                return Err(invalid_input(format!(
+                    "path write would grow the state document above the limit of {} bytes",
+                    self.limits.max_document_bytes
+                )));
+            }
+            _ => {}
+        }
+
+        // The server could not apply the path in place (missing parents, padding, legacy string
+        // value); fall back to a client-side read-modify-write of the whole document.
+        let Some(path) = path else {
+            return Ok(());
+        };
+        let mut document = self.json_get(key, None)?.unwrap_or(Value::Null);
+        set_at_path_with_limits(&mut document, path, value.clone(), &self.limits)?;
+        self.json_set(key, index, None, &document, ttl_secs)
     }
 
     fn load_document(&self, key: &FqnKey) -> GResult<Option<Value>> {
-        let raw: Option<String> = self.with_connection(|conn| conn.get(key.as_ref()))?;
-        let value = raw
-            .map(|payload| serde_json::from_str(&payload).map_err(from_serde))
-            .transpose()?;
-        Ok(value)
+        if self.json_module_active()? {
+            return self.json_get(key, None);
+        }
+        let raw: Option<Vec<u8>> = self.with_connection(|conn| conn.get(key.as_ref()))?;
+        raw.map(|payload| Self::decode_document(&payload))
+            .transpose()
+    }
+
+    /// Parses a string-mode value written with any codec, decompressing it first when it
+    /// carries a compression header.
+    fn decode_document(stored: &[u8]) -> GResult<Value> {
+        ValueCodec::decode(&compress::decode(stored)?)
     }
 
     fn ttl_arg(ttl_secs: Option<u32>) -> i64 {
@@ -92,13 +629,23 @@ impl RedisStateStore {
         }
     }
 
-    fn write_document(&self, key: &FqnKey, document: &Value, ttl_secs: Option<u32>) -> GResult<()> {
-        let payload = serde_json::to_string(document).map_err(from_serde)?;
+    fn write_document(
+        &self,
+        key: &FqnKey,
+        index: Option<&str>,
+        document: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let payload = self.codec.encode(document)?;
+        let payload = compress::encode(self.compression.as_ref(), payload)?;
         let ttl = Self::ttl_arg(ttl_secs);
         self.with_connection(|conn| {
-            self.upsert_script
-                .key(key.as_ref())
-                .arg(payload.as_str())
+            let mut invocation = self.upsert_script.key(key.as_ref());
+            if let Some(index) = index {
+                invocation.key(index);
+            }
+            invocation
+                .arg(payload.as_slice())
                 .arg(ttl)
                 .invoke::<i64>(conn)
         })?;
@@ -108,6 +655,118 @@ impl RedisStateStore {
     fn entry_key(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> FqnKey {
         fqn(tenant, prefix, key)
     }
+
+    /// Collects every key matching the glob `pattern` using non-blocking `SCAN`.
+    fn scan_keys(&self, pattern: &str) -> GResult<Vec<String>> {
+        let mut keys = self.with_connection(|conn| {
+            let mut keys = Vec::new();
+            let mut cursor = 0_u64;
+            loop {
+                let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(512)
+                    .query(conn)?;
+                keys.extend(batch);
+                if next == 0 {
+                    return Ok(keys);
+                }
+                cursor = next;
+            }
+        })?;
+        // SCAN may report a key more than once while the keyspace is rehashed.
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Collects the keys of `prefix` and of its sub-prefixes `{prefix}/…`.
+    fn scan_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<Vec<String>> {
+        let mut keys = self.scan_keys(&format!("{}*", glob_escape(&fqn_prefix(tenant, prefix))))?;
+        keys.extend(self.scan_keys(&format!(
+            "{}*",
+            glob_escape(&fqn_subprefix_base(tenant, prefix))
+        ))?);
+        // Both patterns match `{prefix}:` keys when `prefix` ends with `/`.
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
+
+    /// Removes deleted `keys` from the purge index of `tenant`'s team/user scope, if it has one.
+    fn unindex_entries(&self, tenant: &TenantCtx, keys: &[String]) -> GResult<()> {
+        let Some(index) = scope_index_key(tenant) else {
+            return Ok(());
+        };
+        for chunk in keys.chunks(KEY_CHUNK) {
+            self.with_connection(|conn| {
+                redis::cmd("ZREM").arg(&index).arg(chunk).query::<i64>(conn)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Deletes `keys` in chunks, returning how many existed. Each chunk is sent once, so a lost
+    /// reply fails the call instead of being undercounted by a retry.
+    fn delete_keys(&self, keys: &[String]) -> GResult<u64> {
+        let mut deleted = 0_u64;
+        for chunk in keys.chunks(KEY_CHUNK) {
+            let removed: i64 =
+                self.with_connection_once(|conn| redis::cmd("DEL").arg(chunk).query(conn))?;
+            deleted += removed as u64;
+        }
+        Ok(deleted)
+    }
+
+    /// Deletes every entry listed in the purge indexes `indexes`, then the indexes themselves.
+    fn purge_indexes(&self, scope: String, indexes: Vec<String>) -> GResult<PurgeReport> {
+        let mut deleted = 0_u64;
+        for index in &indexes {
+            let members: Vec<String> =
+                self.with_connection(|conn| conn.zrange(index.as_str(), 0, -1))?;
+            deleted += self.delete_keys(&members)?;
+        }
+        self.delete_keys(&indexes)?;
+        debug!(scope, deleted, "purged redis state scope");
+        Ok(PurgeReport::new(scope, deleted))
+    }
+
+    /// FQNs recorded in the purge indexes of the team/user scopes nested in `tenant`'s scope.
+    fn nested_scope_entries(&self, tenant: &TenantCtx) -> GResult<HashSet<String>> {
+        let (team, user) = scope_members(tenant);
+        let base = scope_index_base(&purge_scope(tenant, team, user));
+        let mut entries = HashSet::new();
+        for index in self.scan_keys(&format!("{}:*", glob_escape(&base)))? {
+            let members: Vec<String> =
+                self.with_connection(|conn| conn.zrange(index.as_str(), 0, -1))?;
+            entries.extend(members);
+        }
+        Ok(entries)
+    }
+
+    /// Lists the purge index `exact`, if it exists, along with every index matching `pattern`.
+    fn scope_indexes(&self, exact: String, pattern: &str) -> GResult<Vec<String>> {
+        let mut indexes = self.scan_keys(pattern)?;
+        let exists: bool = self.with_connection(|conn| conn.exists(exact.as_str()))?;
+        if exists {
+            indexes.push(exact);
+        }
+        Ok(indexes)
+    }
+}
+
+/// Escapes glob metacharacters so `value` matches literally in `SCAN MATCH` patterns.
+fn glob_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
 }
 
 impl StateStore for RedisStateStore {
@@ -119,6 +778,9 @@ impl StateStore for RedisStateStore {
         path: Option<&StatePath>,
     ) -> GResult<Option<Value>> {
         let fqn = self.entry_key(tenant, prefix, key);
+        if self.json_module_active()? {
+            return self.json_get(&fqn, path);
+        }
         let document = match self.load_document(&fqn)? {
             Some(doc) => doc,
             None => return Ok(None),
@@ -141,52 +803,104 @@ impl StateStore for RedisStateStore {
         ttl_secs: Option<u32>,
     ) -> GResult<()> {
         let fqn = self.entry_key(tenant, prefix, key);
+        // Team and user writes are recorded in their scope's purge index by the write script.
+        let index = scope_index_key(tenant);
+        if self.json_module_active()? {
+            return self.json_set(&fqn, index.as_deref(), path, value, ttl_secs);
+        }
         let document = if let Some(path) = path {
+            self.limits.check_path_write(path, value)?;
             let mut base = self.load_document(&fqn)?.unwrap_or(Value::Null);
-            set_at_path(&mut base, path, value.clone())?;
+            set_at_path_with_limits(&mut base, path, value.clone(), &self.limits)?;
             base
         } else {
             value.clone()
         };
+        self.limits.check_document(&document)?;
 
-        self.write_document(&fqn, &document, ttl_secs)
+        self.write_document(&fqn, index.as_deref(), &document, ttl_secs)
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let fqn = self.entry_key(tenant, prefix, key);
+        self.limits.check_document(value)?;
+        let (payload, mode) = if self.json_module_active()? {
+            let payload = serde_json::to_vec(value).map_err(from_serde)?;
+            (payload, "json")
+        } else {
+            let payload = self.codec.encode(value)?;
+            (
+                compress::encode(self.compression.as_ref(), payload)?,
+                "string",
+            )
+        };
+        let ttl = Self::ttl_arg(ttl_secs);
+        let index = scope_index_key(tenant);
+        let written: i64 = self.with_connection_once(|conn| {
+            let mut invocation = self.set_if_absent_script.key(fqn.as_ref());
+            if let Some(index) = &index {
+                invocation.key(index);
+            }
+            invocation
+                .arg(payload.as_slice())
+                .arg(ttl)
+                .arg(mode)
+                .invoke(conn)
+        })?;
+        Ok(written == 1)
     }
 
     fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
         let fqn = self.entry_key(tenant, prefix, key);
+        // A retried `DEL` would report `false` for an entry whose first deletion lost its reply.
         let removed: i64 =
-            self.with_connection(|conn| redis::cmd("DEL").arg(fqn.as_ref()).query(conn))?;
+            self.with_connection_once(|conn| redis::cmd("DEL").arg(fqn.as_ref()).query(conn))?;
+        self.unindex_entries(tenant, &[fqn.as_str().to_owned()])?;
         Ok(removed > 0)
     }
 
     fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
-        let pattern = format!("{}*", fqn_prefix(tenant, prefix));
+        let pattern = format!("{}*", glob_escape(&fqn_prefix(tenant, prefix)));
+        let index = scope_index_key(tenant);
         let mut cursor = 0_u64;
         let mut deleted = 0_u64;
 
-        self.with_connection(|conn| -> RedisResult<()> {
-            loop {
-                let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        loop {
+            let (next, keys): (u64, Vec<String>) = self.with_connection(|conn| {
+                redis::cmd("SCAN")
                     .arg(cursor)
                     .arg("MATCH")
                     .arg(&pattern)
                     .arg("COUNT")
                     .arg(512)
-                    .query(conn)?;
-
-                if !keys.is_empty() {
-                    let removed: i64 = redis::cmd("DEL").arg(keys.clone()).query(conn)?;
-                    deleted += removed as u64;
-                }
+                    .query(conn)
+            })?;
 
-                if next == 0 {
-                    break;
+            if !keys.is_empty() {
+                // Retrying a `DEL` whose reply was lost would undercount the removed keys.
+                let removed: i64 =
+                    self.with_connection_once(|conn| redis::cmd("DEL").arg(&keys).query(conn))?;
+                deleted += removed as u64;
+                if let Some(index) = &index {
+                    self.with_connection(|conn| {
+                        redis::cmd("ZREM").arg(index).arg(&keys).query::<i64>(conn)
+                    })?;
                 }
+            }
 
-                cursor = next;
+            if next == 0 {
+                break;
             }
-            Ok(())
-        })?;
+
+            cursor = next;
+        }
 
         if deleted > 0 {
             debug!(prefix = pattern, deleted, "bulk deleted redis keys");
@@ -194,4 +908,684 @@ impl StateStore for RedisStateStore {
 
         Ok(deleted)
     }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let keys = self.scan_recursive(tenant, prefix)?;
+        let deleted = self.delete_keys(&keys)?;
+        self.unindex_entries(tenant, &keys)?;
+        if deleted > 0 {
+            debug!(prefix, deleted, "recursively deleted redis keys");
+        }
+        Ok(deleted)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let keys = self.scan_recursive(tenant, prefix)?;
+        let now = OffsetDateTime::now_utc();
+        let mut stats = PrefixStats::default();
+        for chunk in keys.chunks(KEY_CHUNK) {
+            let figures: Vec<i64> = self.with_connection(|conn| {
+                let mut invocation = self.stats_script.prepare_invoke();
+                for key in chunk {
+                    invocation.key(key);
+                }
+                invocation.invoke(conn)
+            })?;
+            for pair in figures.chunks_exact(2) {
+                let (size, pttl) = (pair[0], pair[1]);
+                if size < 0 || pttl == -2 {
+                    continue;
+                }
+                let expires_at = (pttl >= 0).then(|| now + time::Duration::milliseconds(pttl));
+                stats.add(size as u64, expires_at);
+            }
+        }
+        Ok(stats)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let base = match prefix {
+            Some(prefix) => fqn_prefix(tenant, prefix),
+            None => fqn_scope_prefix(tenant),
+        };
+        let pattern = format!("{}*", glob_escape(&base));
+
+        let mut keys = Vec::new();
+        let mut ambiguous = Vec::new();
+        for raw in self.scan_keys(&pattern)? {
+            let Some(rest) = raw.strip_prefix(&base) else {
+                continue;
+            };
+            let entry = match prefix {
+                Some(prefix) => ScopedKey {
+                    prefix: prefix.to_owned(),
+                    key: StateKey::new(rest),
+                },
+                // `{prefix}:{key}` in this scope. More segments mean either a narrower team/user
+                // scope or a `:` inside the prefix or key, which the FQN alone cannot tell apart.
+                None => match rest.split_once(':') {
+                    Some((prefix, key)) if !key.contains(':') => ScopedKey {
+                        prefix: prefix.to_owned(),
+                        key: StateKey::new(key),
+                    },
+                    Some(_) => {
+                        ambiguous.push(raw);
+                        continue;
+                    }
+                    None => continue,
+                },
+            };
+            keys.push(entry);
+        }
+        if !ambiguous.is_empty() {
+            // Narrower scopes record their entries in purge indexes; anything else would be
+            // silently dropped from the listing.
+            let nested = self.nested_scope_entries(tenant)?;
+            let unresolved = ambiguous
+                .iter()
+                .filter(|raw| !nested.contains(raw.as_str()))
+                .count();
+            if unresolved > 0 {
+                return Err(internal(format!(
+                    "{unresolved} entries under `{base}` have a `:` in their prefix or key (or \
+                     belong to a team/user scope written before purge indexes existed) and \
+                     cannot be listed without a prefix; list their prefixes explicitly"
+                )));
+            }
+        }
+        ScopedKey::sort(&mut keys);
+        Ok(keys)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, None, None);
+        let keys = self.scan_keys(&format!("greentic:state:{}:*", glob_escape(&scope)))?;
+        let deleted = self.delete_keys(&keys)?;
+        let indexes = self.scan_keys(&format!("{}:*", glob_escape(&scope_index_base(&scope))))?;
+        self.delete_keys(&indexes)?;
+        debug!(scope, deleted, "purged redis state scope");
+        Ok(PurgeReport::new(scope, deleted))
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, Some(team.as_str()), None);
+        let exact = scope_index_base(&scope);
+        let indexes =
+            self.scope_indexes(exact.clone(), &format!("{}:user=*", glob_escape(&exact)))?;
+        self.purge_indexes(scope, indexes)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let scope = purge_scope(tenant, None, Some(user.as_str()));
+        let owner = scope_index_base(&purge_scope(tenant, None, None));
+        let pattern = format!(
+            "{}:team=*:user={}",
+            glob_escape(&owner),
+            glob_escape(user.as_str())
+        );
+        let indexes = self.scope_indexes(scope_index_base(&scope), &pattern)?;
+        self.purge_indexes(scope, indexes)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let fqn = self.entry_key(tenant, prefix, key);
+        let pttl: i64 =
+            self.with_connection(|conn| redis::cmd("PTTL").arg(fqn.as_ref()).query(conn))?;
+        Ok(match pttl {
+            -2 => None,
+            ms if ms < 0 => Some(StateTtl::Persistent),
+            ms => Some(StateTtl::ExpiresIn(Duration::from_millis(ms as u64))),
+        })
+    }
+}
+
+impl LeaseStore for RedisStateStore {
+    fn acquire_lease(
+        &self,
+        tenant: &TenantCtx,
+        name: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> GResult<Option<Lease>> {
+        let ttl_ms = check_acquire(name, owner, ttl)?;
+        let key = lease_key(tenant, name);
+        let fence = lease_fence_key(tenant, name);
+        let token: Option<u64> = self.with_connection(|conn| {
+            self.lease_acquire_script
+                .key(&key)
+                .key(&fence)
+                .arg(owner)
+                .arg(ttl_ms)
+                .invoke(conn)
+        })?;
+        Ok(token.map(|token| Lease {
+            tenant: tenant.clone(),
+            name: name.to_owned(),
+            owner: owner.to_owned(),
+            token,
+            expires_at: OffsetDateTime::now_utc() + ttl,
+        }))
+    }
+
+    fn renew_lease(&self, lease: &Lease, ttl: Duration) -> GResult<Option<Lease>> {
+        let ttl_ms = lease_ttl_ms(ttl)?;
+        let key = lease_key(&lease.tenant, &lease.name);
+        let renewed: i64 = self.with_connection(|conn| {
+            self.lease_renew_script
+                .key(&key)
+                .arg(lease_value(lease))
+                .arg(ttl_ms)
+                .invoke(conn)
+        })?;
+        Ok((renewed == 1).then(|| lease.renewed(OffsetDateTime::now_utc(), ttl)))
+    }
+
+    fn release_lease(&self, lease: &Lease) -> GResult<bool> {
+        let key = lease_key(&lease.tenant, &lease.name);
+        let released: i64 = self.with_connection_once(|conn| {
+            self.lease_release_script
+                .key(&key)
+                .arg(lease_value(lease))
+                .invoke(conn)
+        })?;
+        Ok(released == 1)
+    }
+}
+
+impl RateLimitStore for RedisStateStore {
+    fn check_rate(
+        &self,
+        tenant: &TenantCtx,
+        key: &str,
+        limit: &RateLimit,
+        cost: u32,
+    ) -> GResult<RateDecision> {
+        let limit = check_rate_args(key, limit, cost)?;
+        let key = rate_limit_key(tenant, key);
+        let (allowed, remaining, retry_ms): (i64, u32, u64) =
+            self.with_connection_once(|conn| match limit {
+                RateLimitMs::TokenBucket {
+                    capacity,
+                    refill,
+                    interval_ms,
+                } => self
+                    .token_bucket_script
+                    .key(&key)
+                    .arg(capacity)
+                    .arg(refill)
+                    .arg(interval_ms)
+                    .arg(cost)
+                    .invoke(conn),
+                RateLimitMs::SlidingWindow { limit, window_ms } => self
+                    .sliding_window_script
+                    .key(&key)
+                    .arg(limit)
+                    .arg(window_ms)
+                    .arg(cost)
+                    .invoke(conn),
+            })?;
+        Ok(RateDecision {
+            allowed: allowed == 1,
+            remaining,
+            retry_after: (allowed != 1).then(|| Duration::from_millis(retry_ms)),
+        })
+    }
+
+    fn reset_rate(&self, tenant: &TenantCtx, key: &str) -> GResult<bool> {
+        let key = rate_limit_key(tenant, key);
+        let deleted: i64 =
+            self.with_connection_once(|conn| redis::cmd("DEL").arg(&key).query(conn))?;
+        Ok(deleted == 1)
+    }
+}
+
+/// Value stored under a held lease key.
+fn lease_value(lease: &Lease) -> String {
+    format!("{}:{}", lease.token, lease.owner)
+}
+
+/// Builder for [`RedisStateStore`] exposing pool, timeout and retry settings.
+///
+/// Connections are opened lazily, so building a store never blocks on the network.
+#[derive(Clone, Debug)]
+pub struct RedisStateStoreBuilder {
+    client: redis::Client,
+    pool_size: u32,
+    min_idle: u32,
+    connect_timeout: Duration,
+    command_timeout: Option<Duration>,
+    checkout_timeout: Duration,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_retry_backoff: Duration,
+    json_mode: JsonModuleMode,
+    compression: Option<CompressionConfig>,
+    codec: ValueCodec,
+    limits: StateLimits,
+}
+
+impl RedisStateStoreBuilder {
+    /// Starts a builder with default settings for the provided client.
+    pub fn new(client: redis::Client) -> Self {
+        Self {
+            client,
+            pool_size: 16,
+            min_idle: 0,
+            connect_timeout: Duration::from_secs(5),
+            command_timeout: Some(Duration::from_secs(5)),
+            checkout_timeout: Duration::from_secs(5),
+            max_retries: 3,
+            retry_backoff: Duration::from_millis(50),
+            max_retry_backoff: Duration::from_secs(1),
+            json_mode: JsonModuleMode::Disabled,
+            compression: None,
+            codec: ValueCodec::Json,
+            limits: StateLimits::default(),
+        }
+    }
+
+    /// Starts a builder for the provided Redis URL.
+    pub fn from_url(redis_url: impl AsRef<str>) -> GResult<Self> {
+        let client = redis::Client::open(redis_url.as_ref())
+            .map_err(|err| from_redis(err, "connect redis"))?;
+        Ok(Self::new(client))
+    }
+
+    /// Maximum number of pooled connections (default: 16).
+    pub fn pool_size(mut self, size: u32) -> Self {
+        self.pool_size = size;
+        self
+    }
+
+    /// Number of idle connections the pool keeps warm (default: 0).
+    pub fn min_idle(mut self, min_idle: u32) -> Self {
+        self.min_idle = min_idle;
+        self
+    }
+
+    /// Timeout for establishing a new connection (default: 5s).
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Read/write timeout applied to every command; `None` waits indefinitely (default: 5s).
+    pub fn command_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.command_timeout = timeout;
+        self
+    }
+
+    /// How long a caller waits for a free pooled connection (default: 5s).
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Number of retries for idempotent commands after a transient failure (default: 3).
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Initial and maximum backoff between retries (defaults: 50ms and 1s).
+    pub fn retry_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.retry_backoff = initial;
+        self.max_retry_backoff = max;
+        self
+    }
+
+    /// Opts into server-side path operations via the RedisJSON module (default: disabled).
+    ///
+    /// Documents written in string mode stay readable, and are converted to native JSON on
+    /// their next whole-document write.
+    pub fn json_module(mut self, mode: JsonModuleMode) -> Self {
+        self.json_mode = mode;
+        self
+    }
+
+    /// Compresses string-mode documents at or above the configured size (default: disabled).
+    ///
+    /// Compressed values carry a header byte, so values written without compression (or before
+    /// it was enabled) keep loading. Documents stored through the RedisJSON module are native
+    /// JSON and are never compressed.
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, config: Option<CompressionConfig>) -> Self {
+        self.compression = config;
+        self
+    }
+
+    /// Serialization format for string-mode documents (default: [`ValueCodec::Json`]).
+    ///
+    /// Binary encodings are tagged, so the store keeps reading documents written with any
+    /// codec and the setting can be changed during a rolling deploy. Documents stored through
+    /// the RedisJSON module are always native JSON.
+    pub fn codec(mut self, codec: ValueCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    /// Size, depth and array-padding limits enforced on writes (default: [`StateLimits::default`]).
+    ///
+    /// With the RedisJSON module, path writes applied on the server are checked against the
+    /// written value only; the whole document is checked on client-side path writes and on
+    /// every whole-document write.
+    pub fn limits(mut self, limits: StateLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Validates the configuration and builds the store.
+    pub fn build(self) -> GResult<RedisStateStore> {
+        if self.pool_size == 0 {
+            return Err(invalid_input("redis pool size must be positive"));
+        }
+        if self.min_idle > self.pool_size {
+            return Err(invalid_input(
+                "redis min_idle must not exceed the pool size",
+            ));
+        }
+        if self.connect_timeout.is_zero() || self.checkout_timeout.is_zero() {
+            return Err(invalid_input("redis timeouts must be non-zero"));
+        }
+        if self
+            .command_timeout
+            .is_some_and(|timeout| timeout.is_zero())
+        {
+            return Err(invalid_input("redis command timeout must be non-zero"));
+        }
+        Ok(self.assemble())
+    }
+
+    fn assemble(self) -> RedisStateStore {
+        let manager = RedisConnectionManager {
+            client: self.client,
+            connect_timeout: self.connect_timeout,
+            command_timeout: self.command_timeout,
+        };
+        let pool = Pool::builder()
+            .max_size(self.pool_size)
+            .min_idle(Some(self.min_idle))
+            .connection_timeout(self.checkout_timeout)
+            .test_on_check_out(false)
+            .build_unchecked(manager);
+
+        RedisStateStore {
+            pool,
+            retry: RetryPolicy {
+                max_retries: self.max_retries,
+                initial_backoff: self.retry_backoff,
+                max_backoff: self.max_retry_backoff,
+            },
+            upsert_script: Script::new(&format!("{INDEX_LUA}{UPSERT_LUA}")),
+            json_mode: self.json_mode,
+            json_active: OnceLock::new(),
+            json_get_script: Script::new(&format!("{JSON_PATH_LUA}{JSON_GET_LUA}")),
+            json_set_script: Script::new(&format!("{INDEX_LUA}{JSON_PATH_LUA}{JSON_SET_LUA}")),
+            stats_script: Script::new(PREFIX_STATS_LUA),
+            set_if_absent_script: Script::new(&format!("{INDEX_LUA}{SET_IF_ABSENT_LUA}")),
+            index_script: Script::new(&format!("{INDEX_LUA}index_entry()\nreturn 1")),
+            lease_acquire_script: Script::new(LEASE_ACQUIRE_LUA),
+            lease_renew_script: Script::new(LEASE_RENEW_LUA),
+            lease_release_script: Script::new(LEASE_RELEASE_LUA),
+            token_bucket_script: Script::new(TOKEN_BUCKET_LUA),
+            sliding_window_script: Script::new(SLIDING_WINDOW_LUA),
+            compression: self.compression,
+            codec: self.codec,
+            limits: self.limits,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1_u32.checked_shl(attempt).unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+fn is_transient(err: &RedisError) -> bool {
+    if err.kind() == ErrorKind::AuthenticationFailed {
+        return false;
+    }
+    err.is_timeout()
+        || matches!(
+            err.retry_method(),
+            RetryMethod::Reconnect | RetryMethod::RetryImmediately | RetryMethod::WaitAndRetry
+        )
+}
+
+/// Pooled connection plus a flag recording whether it hit an unrecoverable error.
+struct PooledRedis {
+    inner: Connection,
+    broken: bool,
+}
+
+struct RedisConnectionManager {
+    client: redis::Client,
+    connect_timeout: Duration,
+    command_timeout: Option<Duration>,
+}
+
+impl ManageConnection for RedisConnectionManager {
+    type Connection = PooledRedis;
+    type Error = RedisError;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        let conn = self
+            .client
+            .get_connection_with_timeout(self.connect_timeout)?;
+        conn.set_read_timeout(self.command_timeout)?;
+        conn.set_write_timeout(self.command_timeout)?;
+        Ok(PooledRedis {
+            inner: conn,
+            broken: false,
+        })
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
+        redis::cmd("PING")
+            .query::<String>(&mut conn.inner)
+            .map(|_| ())
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.broken || !conn.inner.is_open()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Starts a fake server that acknowledges connection setup with `+OK` but closes the
+    /// connection instead of answering a command named in `lost`, as if the reply was lost after
+    /// the server applied it. Returns a store pointing at it and the count of lost replies.
+    fn reply_losing_store(lost: &'static [&'static str]) -> (RedisStateStore, Arc<AtomicUsize>) {
+        let listener =
+            TcpListener::bind("127.0.0.1:0").unwrap_or_else(|err| panic!("bind fake redis: {err}"));
+        let addr = listener
+            .local_addr()
+            .unwrap_or_else(|err| panic!("fake redis address: {err}"));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let counter = dropped.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let counter = counter.clone();
+                thread::spawn(move || serve_losing_replies(stream, lost, &counter));
+            }
+        });
+        let client = redis::Client::open(format!("redis://{addr}/"))
+            .unwrap_or_else(|err| panic!("invalid redis url for tests: {err}"));
+        let store = RedisStateStore::builder(client)
+            .retry_backoff(Duration::from_millis(1), Duration::from_millis(1))
+            .build()
+            .unwrap_or_else(|err| panic!("build store: {err}"));
+        (store, dropped)
+    }
+
+    fn serve_losing_replies(stream: TcpStream, lost: &[&str], dropped: &AtomicUsize) {
+        let Ok(mut writer) = stream.try_clone() else {
+            return;
+        };
+        let mut reader = BufReader::new(stream);
+        while let Some(command) = read_command(&mut reader) {
+            if lost.iter().any(|name| command.eq_ignore_ascii_case(name)) {
+                dropped.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            // `SCAN` finds a single key so that scanning deletes have something to remove.
+            let reply: &[u8] = if command.eq_ignore_ascii_case("SCAN") {
+                b"*2\r\n$1\r\n0\r\n*1\r\n$3\r\nkey\r\n"
+            } else {
+                b"+OK\r\n"
+            };
+            if writer.write_all(reply).is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Reads one RESP command and returns its name.
+    fn read_command(reader: &mut impl BufRead) -> Option<String> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut name = None;
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).ok()?;
+            arg.truncate(len);
+            name.get_or_insert_with(|| String::from_utf8_lossy(&arg).into_owned());
+        }
+        name
+    }
+
+    fn ctx() -> TenantCtx {
+        let env = greentic_types::EnvId::try_from("dev")
+            .unwrap_or_else(|err| panic!("invalid env id for tests: {err}"));
+        let tenant = greentic_types::TenantId::try_from("tenant")
+            .unwrap_or_else(|err| panic!("invalid tenant id for tests: {err}"));
+        TenantCtx::new(env, tenant)
+    }
+
+    #[test]
+    fn rate_checks_are_not_retried_after_a_lost_reply() {
+        let (store, dropped) = reply_losing_store(&["EVALSHA", "EVAL"]);
+        let limit = RateLimit::sliding_window(5, Duration::from_secs(1));
+        let Err(err) = store.check_rate(&ctx(), "api", &limit, 2) else {
+            panic!("expected the lost reply to be reported");
+        };
+        assert_eq!(err.code, greentic_types::ErrorCode::Unavailable);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1, "cost is consumed once");
+    }
+
+    #[test]
+    fn set_if_absent_is_not_retried_after_a_lost_reply() {
+        let (store, dropped) = reply_losing_store(&["EVALSHA", "EVAL"]);
+        let Err(err) = store.set_if_absent(
+            &ctx(),
+            "claims",
+            &StateKey::new("evt-1"),
+            &Value::Bool(true),
+            None,
+        ) else {
+            panic!("expected the unknown outcome to be reported");
+        };
+        assert_eq!(err.code, greentic_types::ErrorCode::Unavailable);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1, "the script ran once");
+    }
+
+    #[test]
+    fn del_is_not_retried_after_a_lost_reply() {
+        let (store, dropped) = reply_losing_store(&["DEL"]);
+        let Err(err) = store.del(&ctx(), "flow", &StateKey::new("node/a")) else {
+            panic!("expected the unknown outcome to be reported");
+        };
+        assert_eq!(err.code, greentic_types::ErrorCode::Unavailable);
+        assert_eq!(
+            dropped.load(Ordering::SeqCst),
+            1,
+            "the entry was deleted once"
+        );
+    }
+
+    #[test]
+    fn bulk_deletes_and_releases_are_not_retried_after_a_lost_reply() {
+        let lease = Lease {
+            tenant: ctx(),
+            name: "session/s1".into(),
+            owner: "node-a".into(),
+            token: 1,
+            expires_at: OffsetDateTime::now_utc(),
+        };
+        type Call<'a> = &'a dyn Fn(&RedisStateStore) -> GResult<()>;
+        let calls: [(&str, &[&str], Call); 4] = [
+            ("del_prefix", &["DEL"], &|store| {
+                store.del_prefix(&ctx(), "flow").map(drop)
+            }),
+            ("purge_tenant", &["DEL"], &|store| {
+                store.purge_tenant(&ctx()).map(drop)
+            }),
+            ("release_lease", &["EVALSHA", "EVAL"], &|store| {
+                store.release_lease(&lease).map(drop)
+            }),
+            ("reset_rate", &["DEL"], &|store| {
+                store.reset_rate(&ctx(), "api").map(drop)
+            }),
+        ];
+        for (name, lost, call) in calls {
+            let (store, dropped) = reply_losing_store(lost);
+            let Err(err) = call(&store) else {
+                panic!("{name}: expected the lost reply to be reported");
+            };
+            assert_eq!(err.code, greentic_types::ErrorCode::Unavailable, "{name}");
+            assert_eq!(dropped.load(Ordering::SeqCst), 1, "{name} ran once");
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        let policy = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(300),
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(50));
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+        assert_eq!(policy.backoff(40), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn glob_escape_quotes_metacharacters() {
+        assert_eq!(glob_escape("flow/a*b?[c]\\d"), "flow/a\\*b\\?\\[c\\]\\\\d");
+        assert_eq!(glob_escape("flow/plain"), "flow/plain");
+    }
+
+    #[test]
+    fn builder_rejects_empty_pool() {
+        let client = redis::Client::open("redis://127.0.0.1/")
+            .unwrap_or_else(|err| panic!("invalid redis url for tests: {err}"));
+        let Err(err) = RedisStateStore::builder(client).pool_size(0).build() else {
+            panic!("expected empty pool to be rejected");
+        };
+        assert_eq!(err.code, greentic_types::ErrorCode::InvalidInput);
+    }
 }
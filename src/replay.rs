@@ -0,0 +1,844 @@
+//! Record a flow's state interactions and replay them deterministically.
+//!
+//! [`RecordingStateStore`] wraps a production store and appends every call, together with its
+//! result, as one JSON line. [`ReplayStateStore`] reads such a log back and answers each call
+//! with the recorded result, in order, without touching any backend. Calls that do not match the
+//! log are reported as [`Divergence`]s; from then on the replay is served by an
+//! [`InMemoryStateStore`] holding every write seen so far.
+
+use crate::error::{conflict, from_serde, with_context};
+use crate::inmemory::InMemoryStateStore;
+use crate::key::StatePath;
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use greentic_types::{ErrorCode, GResult, GreenticError, StateKey, TeamId, TenantCtx, UserId};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::warn;
+
+/// Arguments of one [`StateStore`] call.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StateCall {
+    /// `get_json`.
+    Get {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+        /// Document key.
+        key: StateKey,
+        /// JSON Pointer, when reading part of the document.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+    },
+    /// `set_json`.
+    Set {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+        /// Document key.
+        key: StateKey,
+        /// JSON Pointer, when writing part of the document.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<String>,
+        /// Written value.
+        value: Value,
+        /// Requested TTL.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_secs: Option<u32>,
+    },
+    /// `set_if_absent`.
+    SetIfAbsent {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+        /// Document key.
+        key: StateKey,
+        /// Value written when the key was absent.
+        value: Value,
+        /// Requested TTL.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_secs: Option<u32>,
+    },
+    /// `del`.
+    Del {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+        /// Document key.
+        key: StateKey,
+    },
+    /// `del_prefix`.
+    DelPrefix {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+    },
+    /// `del_prefix_recursive`.
+    DelPrefixRecursive {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+    },
+    /// `prefix_stats`.
+    PrefixStats {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+    },
+    /// `list_keys`.
+    ListKeys {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Prefix filter.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        prefix: Option<String>,
+    },
+    /// `ttl`.
+    Ttl {
+        /// Calling scope.
+        tenant: TenantCtx,
+        /// Caller-provided prefix.
+        prefix: String,
+        /// Document key.
+        key: StateKey,
+    },
+    /// `purge_tenant`.
+    PurgeTenant {
+        /// Purged tenant.
+        tenant: TenantCtx,
+    },
+    /// `purge_team`.
+    PurgeTeam {
+        /// Tenant owning the team.
+        tenant: TenantCtx,
+        /// Purged team.
+        team: TeamId,
+    },
+    /// `purge_user`.
+    PurgeUser {
+        /// Tenant owning the user.
+        tenant: TenantCtx,
+        /// Purged user.
+        user: UserId,
+    },
+}
+
+/// Result of one [`StateStore`] call.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StateOutcome {
+    /// `get_json` result; `None` when the key or path was missing.
+    Value {
+        /// Returned value.
+        value: Option<Value>,
+    },
+    /// Successful `set_json`.
+    Written,
+    /// `set_if_absent` result.
+    Inserted {
+        /// Whether the value was written.
+        written: bool,
+    },
+    /// `del` result.
+    Deleted {
+        /// Whether the key existed.
+        existed: bool,
+    },
+    /// `del_prefix` or `del_prefix_recursive` result.
+    Removed {
+        /// Number of entries removed.
+        count: u64,
+    },
+    /// `prefix_stats` result, see [`PrefixStats`].
+    Stats {
+        /// Number of entries.
+        keys: u64,
+        /// Stored size of the entries, in bytes.
+        total_bytes: u64,
+        /// Entries without a TTL.
+        persistent: u64,
+        /// Soonest expiry among entries with a TTL.
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "time::serde::rfc3339::option"
+        )]
+        earliest_expiry: Option<OffsetDateTime>,
+        /// Latest expiry among entries with a TTL.
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "time::serde::rfc3339::option"
+        )]
+        latest_expiry: Option<OffsetDateTime>,
+    },
+    /// `list_keys` result, as `(prefix, key)` pairs.
+    Keys {
+        /// Listed entries.
+        keys: Vec<(String, StateKey)>,
+    },
+    /// `ttl` result.
+    Ttl {
+        /// Whether the key existed.
+        exists: bool,
+        /// Remaining lifetime in milliseconds; `None` for persistent entries.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires_in_ms: Option<u64>,
+    },
+    /// `purge_tenant`, `purge_team` or `purge_user` result.
+    Purged {
+        /// Purged scope, see [`PurgeReport::scope`].
+        scope: String,
+        /// Number of entries removed.
+        deleted: u64,
+    },
+    /// Any failed call.
+    Error {
+        /// Error code returned by the backend.
+        code: ErrorCode,
+        /// Error message returned by the backend.
+        message: String,
+    },
+}
+
+/// One line of a recording.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCall {
+    /// Position of the call in the recording, starting at 0.
+    pub seq: u64,
+    /// Call arguments.
+    pub call: StateCall,
+    /// Call result.
+    pub outcome: StateOutcome,
+}
+
+/// Replayed call that did not match the recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// Position of the call in the replayed run.
+    pub seq: u64,
+    /// Call the recording expected there; `None` once the recording is exhausted.
+    pub expected: Option<StateCall>,
+    /// Call actually made.
+    pub actual: StateCall,
+}
+
+/// [`StateStore`] wrapper that appends every call and its result to a JSON-lines recording.
+///
+/// Recording failures are logged and never fail the call.
+pub struct RecordingStateStore<S> {
+    inner: S,
+    log: Mutex<RecordingLog>,
+}
+
+struct RecordingLog {
+    writer: Box<dyn Write + Send>,
+    next_seq: u64,
+}
+
+impl<S: StateStore> RecordingStateStore<S> {
+    /// Records to `writer`.
+    pub fn new(inner: S, writer: impl Write + Send + 'static) -> Self {
+        Self {
+            inner,
+            log: Mutex::new(RecordingLog {
+                writer: Box::new(writer),
+                next_seq: 0,
+            }),
+        }
+    }
+
+    /// Records to `path`, replacing any existing file.
+    pub fn create(inner: S, path: impl AsRef<Path>) -> GResult<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|err| with_context(err, format!("create recording {}", path.display())))?;
+        Ok(Self::new(inner, BufWriter::new(file)))
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    fn record<T>(
+        &self,
+        call: StateCall,
+        result: GResult<T>,
+        outcome: impl FnOnce(&T) -> StateOutcome,
+    ) -> GResult<T> {
+        let outcome = match &result {
+            Ok(value) => outcome(value),
+            Err(err) => StateOutcome::Error {
+                code: err.code,
+                message: err.message.clone(),
+            },
+        };
+        let mut log = self.log.lock();
+        let line = RecordedCall {
+            seq: log.next_seq,
+            call,
+            outcome,
+        };
+        log.next_seq += 1;
+        let written = serde_json::to_vec(&line)
+            .map_err(from_serde)
+            .and_then(|mut bytes| {
+                bytes.push(b'\n');
+                log.writer
+                    .write_all(&bytes)
+                    .and_then(|()| log.writer.flush())
+                    .map_err(|err| with_context(err, "write recording"))
+            });
+        if let Err(err) = written {
+            warn!(error = %err, seq = line.seq, "failed to record state call");
+        }
+        result
+    }
+}
+
+impl<S: StateStore> StateStore for RecordingStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        let call = get_call(tenant, prefix, key, path);
+        let result = self.inner.get_json(tenant, prefix, key, path);
+        self.record(call, result, |value| StateOutcome::Value {
+            value: value.clone(),
+        })
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        let call = set_call(tenant, prefix, key, path, value, ttl_secs);
+        let result = self
+            .inner
+            .set_json(tenant, prefix, key, path, value, ttl_secs);
+        self.record(call, result, |()| StateOutcome::Written)
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let call = set_if_absent_call(tenant, prefix, key, value, ttl_secs);
+        let result = self
+            .inner
+            .set_if_absent(tenant, prefix, key, value, ttl_secs);
+        self.record(call, result, |written| StateOutcome::Inserted {
+            written: *written,
+        })
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let call = StateCall::Del {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+            key: key.clone(),
+        };
+        let result = self.inner.del(tenant, prefix, key);
+        self.record(call, result, |existed| StateOutcome::Deleted {
+            existed: *existed,
+        })
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let call = StateCall::DelPrefix {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        let result = self.inner.del_prefix(tenant, prefix);
+        self.record(call, result, |count| StateOutcome::Removed {
+            count: *count,
+        })
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let call = StateCall::DelPrefixRecursive {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        let result = self.inner.del_prefix_recursive(tenant, prefix);
+        self.record(call, result, |count| StateOutcome::Removed {
+            count: *count,
+        })
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let call = StateCall::PrefixStats {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        let result = self.inner.prefix_stats(tenant, prefix);
+        self.record(call, result, |stats| StateOutcome::Stats {
+            keys: stats.keys,
+            total_bytes: stats.total_bytes,
+            persistent: stats.persistent,
+            earliest_expiry: stats.earliest_expiry,
+            latest_expiry: stats.latest_expiry,
+        })
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let call = StateCall::ListKeys {
+            tenant: tenant.clone(),
+            prefix: prefix.map(str::to_owned),
+        };
+        let result = self.inner.list_keys(tenant, prefix);
+        self.record(call, result, |keys| StateOutcome::Keys {
+            keys: keys
+                .iter()
+                .map(|scoped| (scoped.prefix.clone(), scoped.key.clone()))
+                .collect(),
+        })
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let call = StateCall::Ttl {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+            key: key.clone(),
+        };
+        let result = self.inner.ttl(tenant, prefix, key);
+        self.record(call, result, |ttl| StateOutcome::Ttl {
+            exists: ttl.is_some(),
+            expires_in_ms: match ttl {
+                Some(StateTtl::ExpiresIn(remaining)) => {
+                    Some(u64::try_from(remaining.as_millis()).unwrap_or(u64::MAX))
+                }
+                _ => None,
+            },
+        })
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeTenant {
+            tenant: tenant.clone(),
+        };
+        let result = self.inner.purge_tenant(tenant);
+        self.record(call, result, purged)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeTeam {
+            tenant: tenant.clone(),
+            team: team.clone(),
+        };
+        let result = self.inner.purge_team(tenant, team);
+        self.record(call, result, purged)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeUser {
+            tenant: tenant.clone(),
+            user: user.clone(),
+        };
+        let result = self.inner.purge_user(tenant, user);
+        self.record(call, result, purged)
+    }
+}
+
+/// [`StateStore`] that answers calls from a recording made by [`RecordingStateStore`].
+///
+/// Calls are matched against the recording in order. Successful recorded writes are also
+/// applied to an [`InMemoryStateStore`] mirror; after the first [`Divergence`] every call is
+/// served by that mirror, or fails with `Conflict` when the replay is strict.
+pub struct ReplayStateStore {
+    calls: Vec<RecordedCall>,
+    state: Mutex<ReplayState>,
+    mirror: InMemoryStateStore,
+    strict: bool,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    cursor: usize,
+    seq: u64,
+    divergences: Vec<Divergence>,
+}
+
+impl ReplayStateStore {
+    /// Replays `calls` in the order given.
+    pub fn new(calls: Vec<RecordedCall>) -> Self {
+        Self {
+            calls,
+            state: Mutex::new(ReplayState::default()),
+            mirror: InMemoryStateStore::new(),
+            strict: false,
+        }
+    }
+
+    /// Reads a recording written by [`RecordingStateStore::create`].
+    pub fn open(path: impl AsRef<Path>) -> GResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|err| with_context(err, format!("open recording {}", path.display())))?;
+        let mut calls = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|err| with_context(err, "read recording"))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            calls.push(serde_json::from_str(&line).map_err(from_serde)?);
+        }
+        Ok(Self::new(calls))
+    }
+
+    /// Fails every call from the first divergence on instead of falling back to the mirror.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Calls that did not match the recording, in the order they were made.
+    pub fn divergences(&self) -> Vec<Divergence> {
+        self.state.lock().divergences.clone()
+    }
+
+    /// Number of recorded calls not replayed yet.
+    pub fn remaining(&self) -> usize {
+        let state = self.state.lock();
+        if state.divergences.is_empty() {
+            self.calls.len() - state.cursor
+        } else {
+            0
+        }
+    }
+
+    /// In-memory copy of the state written during the replay.
+    pub fn mirror(&self) -> &InMemoryStateStore {
+        &self.mirror
+    }
+
+    /// Returns the recorded outcome for `call`, or `None` when the mirror should answer it.
+    fn next(&self, call: StateCall) -> GResult<Option<StateOutcome>> {
+        let mut state = self.state.lock();
+        let seq = state.seq;
+        state.seq += 1;
+        if state.divergences.is_empty() {
+            match self.calls.get(state.cursor) {
+                Some(recorded) if recorded.call == call => {
+                    state.cursor += 1;
+                    return Ok(Some(recorded.outcome.clone()));
+                }
+                expected => {
+                    warn!(seq, "state replay diverged from the recording");
+                    let expected = expected.map(|recorded| recorded.call.clone());
+                    state.divergences.push(Divergence {
+                        seq,
+                        expected,
+                        actual: call,
+                    });
+                }
+            }
+        } else {
+            state.divergences.push(Divergence {
+                seq,
+                expected: None,
+                actual: call,
+            });
+        }
+        if self.strict {
+            return Err(conflict(format!(
+                "state replay diverged from the recording at call {seq}"
+            )));
+        }
+        Ok(None)
+    }
+}
+
+impl StateStore for ReplayStateStore {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        match self.next(get_call(tenant, prefix, key, path))? {
+            Some(StateOutcome::Value { value }) => Ok(value),
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.get_json(tenant, prefix, key, path),
+        }
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        match self.next(set_call(tenant, prefix, key, path, value, ttl_secs))? {
+            Some(StateOutcome::Written) | None => self
+                .mirror
+                .set_json(tenant, prefix, key, path, value, ttl_secs),
+            Some(other) => Err(unexpected(other)),
+        }
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        match self.next(set_if_absent_call(tenant, prefix, key, value, ttl_secs))? {
+            Some(StateOutcome::Inserted { written }) => {
+                if written {
+                    let ttl_secs = Some(ttl_secs.unwrap_or(0));
+                    self.mirror
+                        .set_json(tenant, prefix, key, None, value, ttl_secs)?;
+                }
+                Ok(written)
+            }
+            Some(other) => Err(unexpected(other)),
+            None => self
+                .mirror
+                .set_if_absent(tenant, prefix, key, value, ttl_secs),
+        }
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        let call = StateCall::Del {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+            key: key.clone(),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Deleted { existed }) => {
+                self.mirror.del(tenant, prefix, key)?;
+                Ok(existed)
+            }
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.del(tenant, prefix, key),
+        }
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let call = StateCall::DelPrefix {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Removed { count }) => {
+                self.mirror.del_prefix(tenant, prefix)?;
+                Ok(count)
+            }
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.del_prefix(tenant, prefix),
+        }
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let call = StateCall::DelPrefixRecursive {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Removed { count }) => {
+                self.mirror.del_prefix_recursive(tenant, prefix)?;
+                Ok(count)
+            }
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.del_prefix_recursive(tenant, prefix),
+        }
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let call = StateCall::PrefixStats {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Stats {
+                keys,
+                total_bytes,
+                persistent,
+                earliest_expiry,
+                latest_expiry,
+            }) => Ok(PrefixStats {
+                keys,
+                total_bytes,
+                persistent,
+                earliest_expiry,
+                latest_expiry,
+            }),
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.prefix_stats(tenant, prefix),
+        }
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let call = StateCall::ListKeys {
+            tenant: tenant.clone(),
+            prefix: prefix.map(str::to_owned),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Keys { keys }) => Ok(keys
+                .into_iter()
+                .map(|(prefix, key)| ScopedKey { prefix, key })
+                .collect()),
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.list_keys(tenant, prefix),
+        }
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let call = StateCall::Ttl {
+            tenant: tenant.clone(),
+            prefix: prefix.to_owned(),
+            key: key.clone(),
+        };
+        match self.next(call)? {
+            Some(StateOutcome::Ttl {
+                exists,
+                expires_in_ms,
+            }) => Ok(exists.then(|| match expires_in_ms {
+                Some(ms) => StateTtl::ExpiresIn(Duration::from_millis(ms)),
+                None => StateTtl::Persistent,
+            })),
+            Some(other) => Err(unexpected(other)),
+            None => self.mirror.ttl(tenant, prefix, key),
+        }
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeTenant {
+            tenant: tenant.clone(),
+        };
+        let outcome = self.next(call)?;
+        replay_purge(outcome, || self.mirror.purge_tenant(tenant))
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeTeam {
+            tenant: tenant.clone(),
+            team: team.clone(),
+        };
+        let outcome = self.next(call)?;
+        replay_purge(outcome, || self.mirror.purge_team(tenant, team))
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let call = StateCall::PurgeUser {
+            tenant: tenant.clone(),
+            user: user.clone(),
+        };
+        let outcome = self.next(call)?;
+        replay_purge(outcome, || self.mirror.purge_user(tenant, user))
+    }
+}
+
+fn purged(report: &PurgeReport) -> StateOutcome {
+    StateOutcome::Purged {
+        scope: report.scope.clone(),
+        deleted: report.deleted,
+    }
+}
+
+/// Answers a purge from its recorded outcome, applying it to the mirror with `purge`, or from
+/// the mirror alone once the replay has diverged.
+fn replay_purge(
+    outcome: Option<StateOutcome>,
+    purge: impl FnOnce() -> GResult<PurgeReport>,
+) -> GResult<PurgeReport> {
+    match outcome {
+        Some(StateOutcome::Purged { scope, deleted }) => {
+            purge()?;
+            Ok(PurgeReport::new(scope, deleted))
+        }
+        Some(other) => Err(unexpected(other)),
+        None => purge(),
+    }
+}
+
+fn get_call(
+    tenant: &TenantCtx,
+    prefix: &str,
+    key: &StateKey,
+    path: Option<&StatePath>,
+) -> StateCall {
+    StateCall::Get {
+        tenant: tenant.clone(),
+        prefix: prefix.to_owned(),
+        key: key.clone(),
+        path: path.map(StatePath::to_pointer),
+    }
+}
+
+fn set_call(
+    tenant: &TenantCtx,
+    prefix: &str,
+    key: &StateKey,
+    path: Option<&StatePath>,
+    value: &Value,
+    ttl_secs: Option<u32>,
+) -> StateCall {
+    StateCall::Set {
+        tenant: tenant.clone(),
+        prefix: prefix.to_owned(),
+        key: key.clone(),
+        path: path.map(StatePath::to_pointer),
+        value: value.clone(),
+        ttl_secs,
+    }
+}
+
+fn set_if_absent_call(
+    tenant: &TenantCtx,
+    prefix: &str,
+    key: &StateKey,
+    value: &Value,
+    ttl_secs: Option<u32>,
+) -> StateCall {
+    StateCall::SetIfAbsent {
+        tenant: tenant.clone(),
+        prefix: prefix.to_owned(),
+        key: key.clone(),
+        value: value.clone(),
+        ttl_secs,
+    }
+}
+
+/// Replays a recorded failure, or rejects an outcome recorded for a different operation.
+fn unexpected(outcome: StateOutcome) -> GreenticError {
+    match outcome {
+        StateOutcome::Error { code, message } => GreenticError::new(code, message),
+        other => conflict(format!(
+            "recording holds a mismatched outcome for this call: {other:?}"
+        )),
+    }
+}
@@ -0,0 +1,247 @@
+//! Flow-scoped state handles over the runner's hierarchical prefix convention.
+//!
+//! [`StatePrefix`] builds prefixes such as `pack/{pack_id}/flow/{flow_id}/run/{run_id}` one
+//! validated segment at a time, and [`ScopedState`] binds one to a store and a [`TenantCtx`] so
+//! callers no longer pass `tenant` and `prefix` on every call.
+
+use crate::error::invalid_input;
+use crate::key::StatePath;
+use crate::store::{PrefixStats, ScopedKey, StateStore};
+use greentic_types::{GResult, StateKey, TenantCtx};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Arc;
+
+/// Longest identifier accepted in a prefix segment or node key, in bytes.
+pub const MAX_SEGMENT_LEN: usize = 128;
+
+/// Hierarchical prefix made of `{kind}/{id}` segments, e.g. `pack/p1/flow/ingest/run/r7`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StatePrefix(String);
+
+impl StatePrefix {
+    /// Root prefix of a pack: `pack/{pack_id}`.
+    pub fn pack(pack_id: &str) -> GResult<Self> {
+        Ok(Self(segment("pack", pack_id)?))
+    }
+
+    /// Appends `flow/{flow_id}`.
+    pub fn flow(&self, flow_id: &str) -> GResult<Self> {
+        self.child("flow", flow_id)
+    }
+
+    /// Appends `run/{run_id}`.
+    pub fn run(&self, run_id: &str) -> GResult<Self> {
+        self.child("run", run_id)
+    }
+
+    /// Appends `session/{session_id}`.
+    pub fn session(&self, session_id: &str) -> GResult<Self> {
+        self.child("session", session_id)
+    }
+
+    /// Appends a custom `{kind}/{id}` segment.
+    pub fn child(&self, kind: &str, id: &str) -> GResult<Self> {
+        Ok(Self(format!("{}/{}", self.0, segment(kind, id)?)))
+    }
+
+    /// Returns the prefix as passed to [`StateStore`] calls.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for StatePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Checks that `id` can be used as one segment of a prefix or key.
+///
+/// Identifiers must be non-empty, at most [`MAX_SEGMENT_LEN`] bytes, and free of `/` (the
+/// segment separator), `:` (the FQN separator), whitespace and control characters.
+pub fn validate_segment(id: &str) -> GResult<()> {
+    if id.is_empty() {
+        return Err(invalid_input("state scope identifier must not be empty"));
+    }
+    if id.len() > MAX_SEGMENT_LEN {
+        return Err(invalid_input(format!(
+            "state scope identifier is {} bytes, above the limit of {MAX_SEGMENT_LEN}",
+            id.len()
+        )));
+    }
+    if let Some(ch) = id
+        .chars()
+        .find(|ch| matches!(ch, '/' | ':') || ch.is_whitespace() || ch.is_control())
+    {
+        return Err(invalid_input(format!(
+            "state scope identifier `{}` must not contain {ch:?}",
+            id.escape_debug()
+        )));
+    }
+    Ok(())
+}
+
+fn segment(kind: &str, id: &str) -> GResult<String> {
+    validate_segment(kind)?;
+    validate_segment(id)?;
+    Ok(format!("{kind}/{id}"))
+}
+
+/// Store handle bound to a tenant and a [`StatePrefix`].
+#[derive(Clone)]
+pub struct ScopedState {
+    store: Arc<dyn StateStore>,
+    tenant: TenantCtx,
+    prefix: StatePrefix,
+}
+
+impl ScopedState {
+    /// Binds `store` to `tenant` and `prefix`.
+    pub fn new(store: Arc<dyn StateStore>, tenant: TenantCtx, prefix: StatePrefix) -> Self {
+        Self {
+            store,
+            tenant,
+            prefix,
+        }
+    }
+
+    /// Returns the bound tenant.
+    pub fn tenant(&self) -> &TenantCtx {
+        &self.tenant
+    }
+
+    /// Returns the bound prefix.
+    pub fn prefix(&self) -> &StatePrefix {
+        &self.prefix
+    }
+
+    /// Child scope for `flow/{flow_id}`.
+    pub fn flow(&self, flow_id: &str) -> GResult<Self> {
+        Ok(self.with_prefix(self.prefix.flow(flow_id)?))
+    }
+
+    /// Child scope for `run/{run_id}`.
+    pub fn run(&self, run_id: &str) -> GResult<Self> {
+        Ok(self.with_prefix(self.prefix.run(run_id)?))
+    }
+
+    /// Child scope for `session/{session_id}`.
+    pub fn session(&self, session_id: &str) -> GResult<Self> {
+        Ok(self.with_prefix(self.prefix.session(session_id)?))
+    }
+
+    /// Child scope for a custom `{kind}/{id}` segment.
+    pub fn child(&self, kind: &str, id: &str) -> GResult<Self> {
+        Ok(self.with_prefix(self.prefix.child(kind, id)?))
+    }
+
+    /// Handle on the `node/{node_id}` document of this scope.
+    pub fn node(&self, node_id: &str) -> GResult<NodeState<'_>> {
+        validate_segment(node_id)?;
+        Ok(NodeState {
+            scope: self,
+            key: StateKey::new(format!("node/{node_id}")),
+        })
+    }
+
+    /// Reads `key`, or the value at `path` inside it.
+    pub fn get(&self, key: &StateKey, path: Option<&StatePath>) -> GResult<Option<Value>> {
+        self.store
+            .get_json(&self.tenant, self.prefix.as_str(), key, path)
+    }
+
+    /// Writes `key` (or upserts at `path`); see [`StateStore::set_json`] for `ttl_secs`.
+    pub fn set(
+        &self,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.store.set_json(
+            &self.tenant,
+            self.prefix.as_str(),
+            key,
+            path,
+            value,
+            ttl_secs,
+        )
+    }
+
+    /// Deletes `key`, returning `true` when it existed.
+    pub fn del(&self, key: &StateKey) -> GResult<bool> {
+        self.store.del(&self.tenant, self.prefix.as_str(), key)
+    }
+
+    /// Lists the keys stored directly under this scope's prefix.
+    pub fn keys(&self) -> GResult<Vec<ScopedKey>> {
+        self.store
+            .list_keys(&self.tenant, Some(self.prefix.as_str()))
+    }
+
+    /// Deletes every key stored directly under this scope's prefix; child scopes are separate
+    /// prefixes and are not affected (see [`ScopedState::clear_recursive`]).
+    pub fn clear(&self) -> GResult<u64> {
+        self.store.del_prefix(&self.tenant, self.prefix.as_str())
+    }
+
+    /// Deletes every key under this scope and all of its child scopes.
+    pub fn clear_recursive(&self) -> GResult<u64> {
+        self.store
+            .del_prefix_recursive(&self.tenant, self.prefix.as_str())
+    }
+
+    /// Summarizes the keys under this scope and all of its child scopes.
+    pub fn stats(&self) -> GResult<PrefixStats> {
+        self.store.prefix_stats(&self.tenant, self.prefix.as_str())
+    }
+
+    fn with_prefix(&self, prefix: StatePrefix) -> Self {
+        Self::new(Arc::clone(&self.store), self.tenant.clone(), prefix)
+    }
+}
+
+impl fmt::Debug for ScopedState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopedState")
+            .field("tenant", &self.tenant)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle on a single node document inside a [`ScopedState`].
+#[derive(Clone, Debug)]
+pub struct NodeState<'a> {
+    scope: &'a ScopedState,
+    key: StateKey,
+}
+
+impl NodeState<'_> {
+    /// Returns the node's key, `node/{node_id}`.
+    pub fn key(&self) -> &StateKey {
+        &self.key
+    }
+
+    /// Reads the node document, or the value at `path` inside it.
+    pub fn get(&self, path: Option<&StatePath>) -> GResult<Option<Value>> {
+        self.scope.get(&self.key, path)
+    }
+
+    /// Writes the node document (or upserts at `path`).
+    pub fn set(
+        &self,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.scope.set(&self.key, path, value, ttl_secs)
+    }
+
+    /// Deletes the node document, returning `true` when it existed.
+    pub fn del(&self) -> GResult<bool> {
+        self.scope.del(&self.key)
+    }
+}
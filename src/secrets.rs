@@ -0,0 +1,632 @@
+//! Detection and handling of secret-like values on write.
+//!
+//! A [`SecretPolicy`] finds secrets in a document by object key name, by regexes over string
+//! values, and by explicitly marked fields (JSON Pointer patterns or JSON Schema annotations).
+//! [`GuardedStateStore`] applies the policy to every `set_json` and either rejects the write,
+//! replaces the secrets with [`REDACTED`], or (with the `encryption` feature) seals each secret
+//! field individually. [`RedactedJson`] renders documents for logs with the same rules.
+
+use crate::error::invalid_input;
+use crate::key::{StatePath, fqn};
+use crate::store::{PrefixStats, PurgeReport, ScopedKey, StateStore, StateTtl};
+use crate::util::set_at_path;
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use regex::Regex;
+use serde_json::Value;
+use std::fmt::{self, Display};
+use tracing::{trace, warn};
+
+/// Placeholder written in place of redacted values.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Schema annotation marking a property as secret (`"x-greentic-secret": true`).
+pub const SCHEMA_SECRET_MARKER: &str = "x-greentic-secret";
+
+// Credential words must form whole snake/kebab segments or end a camelCase key, so `secretary`
+// and `credentialsCount` are not secret.
+const DEFAULT_KEY_PATTERNS: &[&str] = &[
+    r"(?i)(^|[_-])(password|passwd|secrets?([_-]?key)?|api[_-]?key|private[_-]?key|credentials?|authorization)([_-]|$)",
+    r"[a-z](Password|Passwd|Secrets?|SecretKey|ApiKey|PrivateKey|Credentials?|Authorization)$",
+    r"(?i)(^|[_-])token$",
+    r"[a-z]Token$",
+];
+
+const DEFAULT_VALUE_PATTERNS: &[&str] = &[
+    // JSON Web Tokens.
+    r"eyJ[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]{5,}\.[A-Za-z0-9_-]+",
+    // HTTP bearer credentials.
+    r"(?i)\bbearer\s+[A-Za-z0-9._~+/-]{16,}",
+    // AWS access key ids.
+    r"\bAKIA[0-9A-Z]{16}\b",
+    // GitHub tokens.
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    // Slack tokens.
+    r"\bxox[abposr]-[A-Za-z0-9-]{10,}",
+    // PEM-encoded private keys.
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----",
+];
+
+/// What [`GuardedStateStore`] does with a document containing secrets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SecretAction {
+    /// Fail the write with `InvalidInput`, naming the offending pointers.
+    Reject,
+    /// Replace each secret with [`REDACTED`] before storing.
+    #[default]
+    Redact,
+    /// Seal each secret field with the tenant's data key; reads decrypt them transparently.
+    #[cfg(feature = "encryption")]
+    Encrypt,
+}
+
+/// Why a value was classified as secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretReason {
+    /// The object key matched a key-name pattern.
+    KeyName,
+    /// The string value matched a value pattern.
+    ValuePattern,
+    /// The location matched a marked field.
+    MarkedField,
+}
+
+/// One secret found by [`SecretPolicy::scan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretFinding {
+    /// Location of the secret within the document.
+    pub path: StatePath,
+    /// Rule that matched.
+    pub reason: SecretReason,
+}
+
+/// Rules describing which parts of a document are secret.
+#[derive(Clone, Debug)]
+pub struct SecretPolicy {
+    action: SecretAction,
+    key_patterns: Vec<Regex>,
+    value_patterns: Vec<Regex>,
+    fields: Vec<Vec<String>>,
+}
+
+impl SecretPolicy {
+    /// Creates a policy without any rules.
+    pub fn new(action: SecretAction) -> Self {
+        Self {
+            action,
+            key_patterns: Vec::new(),
+            value_patterns: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Creates a policy with built-in rules for common credential key names (`password`,
+    /// `api_key`, `access_token`, ...) and token formats (JWTs, bearer credentials, AWS, GitHub
+    /// and Slack tokens, PEM private keys).
+    pub fn with_defaults(action: SecretAction) -> Self {
+        // The built-in patterns are constants, so failing to compile one is a bug rather than
+        // a reason to silently stop detecting what it covers.
+        #[allow(clippy::expect_used)]
+        let compile = |patterns: &[&str]| {
+            patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("built-in secret pattern compiles"))
+                .collect()
+        };
+        Self {
+            key_patterns: compile(DEFAULT_KEY_PATTERNS),
+            value_patterns: compile(DEFAULT_VALUE_PATTERNS),
+            ..Self::new(action)
+        }
+    }
+
+    /// Action applied when secrets are found.
+    pub fn action(&self) -> SecretAction {
+        self.action
+    }
+
+    /// Treats the whole value under any object key matching `pattern` as secret.
+    pub fn key_pattern(mut self, pattern: &str) -> GResult<Self> {
+        self.key_patterns.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Treats any string value matching `pattern` as secret.
+    pub fn value_pattern(mut self, pattern: &str) -> GResult<Self> {
+        self.value_patterns.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Marks the value at a JSON Pointer as secret; a `*` segment matches any key or index.
+    pub fn field(mut self, pointer: &str) -> Self {
+        self.fields.push(StatePath::from_pointer(pointer).segments);
+        self
+    }
+
+    /// Marks every property of a JSON Schema annotated with [`SCHEMA_SECRET_MARKER`] or
+    /// `"format": "password"`. `properties`, `items` and `additionalProperties` are followed;
+    /// `$ref`s are not resolved.
+    pub fn schema_fields(mut self, schema: &Value) -> Self {
+        collect_schema_fields(schema, &mut Vec::new(), &mut self.fields);
+        self
+    }
+
+    /// Finds the secrets in `value`, which is written at `base` within its document.
+    pub fn scan(&self, value: &Value, base: &StatePath) -> Vec<SecretFinding> {
+        let mut findings = Vec::new();
+        let mut segments = base.segments.clone();
+        // A path write below a secret key or a marked field is itself a secret.
+        let reason = if segments.iter().any(|segment| self.key_matches(segment)) {
+            Some(SecretReason::KeyName)
+        } else if (0..segments.len()).any(|len| self.field_matches(&segments[..len])) {
+            Some(SecretReason::MarkedField)
+        } else {
+            None
+        };
+        self.walk(value, &mut segments, reason, &mut findings);
+        findings
+    }
+
+    /// Returns a copy of `value` with every secret replaced by [`REDACTED`].
+    pub fn redact(&self, value: &Value) -> Value {
+        self.redact_at(value, &StatePath::default())
+    }
+
+    /// Wraps `value` so that it displays as compact JSON with secrets redacted.
+    pub fn display<'a>(&'a self, value: &'a Value) -> RedactedJson<'a> {
+        RedactedJson {
+            policy: self,
+            value,
+        }
+    }
+
+    fn redact_at(&self, value: &Value, base: &StatePath) -> Value {
+        let mut redacted = value.clone();
+        for finding in self.scan(value, base) {
+            // Findings always address existing locations, so replacing them cannot fail.
+            let _ = set_at_path(
+                &mut redacted,
+                &relative(&finding.path, base),
+                Value::String(REDACTED.to_owned()),
+            );
+        }
+        redacted
+    }
+
+    fn key_matches(&self, key: &str) -> bool {
+        self.key_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(key))
+    }
+
+    fn field_matches(&self, segments: &[String]) -> bool {
+        self.fields.iter().any(|field| {
+            field.len() == segments.len()
+                && field
+                    .iter()
+                    .zip(segments)
+                    .all(|(expected, actual)| expected == "*" || expected == actual)
+        })
+    }
+
+    fn walk(
+        &self,
+        value: &Value,
+        segments: &mut Vec<String>,
+        reason: Option<SecretReason>,
+        findings: &mut Vec<SecretFinding>,
+    ) {
+        if reason.is_none() && is_sealed(value) {
+            // Already-encrypted fields are not secrets any more.
+            return;
+        }
+        let reason = reason.or_else(|| {
+            if self.field_matches(segments) {
+                return Some(SecretReason::MarkedField);
+            }
+            match value {
+                Value::String(text)
+                    if self
+                        .value_patterns
+                        .iter()
+                        .any(|pattern| pattern.is_match(text)) =>
+                {
+                    Some(SecretReason::ValuePattern)
+                }
+                _ => None,
+            }
+        });
+        if let Some(reason) = reason {
+            findings.push(SecretFinding {
+                path: StatePath {
+                    segments: segments.clone(),
+                },
+                reason,
+            });
+            return;
+        }
+
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let reason = self.key_matches(key).then_some(SecretReason::KeyName);
+                    segments.push(key.clone());
+                    self.walk(child, segments, reason, findings);
+                    segments.pop();
+                }
+            }
+            Value::Array(items) => {
+                for (index, child) in items.iter().enumerate() {
+                    segments.push(index.to_string());
+                    self.walk(child, segments, None, findings);
+                    segments.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Default for SecretPolicy {
+    fn default() -> Self {
+        Self::with_defaults(SecretAction::default())
+    }
+}
+
+fn compile(pattern: &str) -> GResult<Regex> {
+    Regex::new(pattern).map_err(|err| invalid_input(format!("secret pattern `{pattern}`: {err}")))
+}
+
+fn collect_schema_fields(
+    schema: &Value,
+    segments: &mut Vec<String>,
+    fields: &mut Vec<Vec<String>>,
+) {
+    let Value::Object(schema) = schema else {
+        return;
+    };
+    let marked = schema.get(SCHEMA_SECRET_MARKER) == Some(&Value::Bool(true))
+        || schema.get("format").and_then(Value::as_str) == Some("password");
+    if marked {
+        fields.push(segments.clone());
+        return;
+    }
+    if let Some(Value::Object(properties)) = schema.get("properties") {
+        for (name, property) in properties {
+            segments.push(name.clone());
+            collect_schema_fields(property, segments, fields);
+            segments.pop();
+        }
+    }
+    for nested in ["items", "additionalProperties"] {
+        if let Some(child) = schema.get(nested) {
+            segments.push("*".to_owned());
+            collect_schema_fields(child, segments, fields);
+            segments.pop();
+        }
+    }
+}
+
+/// `path` with the leading `base` segments removed.
+fn relative(path: &StatePath, base: &StatePath) -> StatePath {
+    StatePath {
+        segments: path.segments[base.segments.len()..].to_vec(),
+    }
+}
+
+#[cfg(feature = "encryption")]
+fn is_sealed(value: &Value) -> bool {
+    matches!(
+        crate::encrypt::EncryptedEnvelope::from_value(value),
+        Ok(Some(_))
+    )
+}
+
+#[cfg(not(feature = "encryption"))]
+fn is_sealed(_value: &Value) -> bool {
+    false
+}
+
+/// Whether `path` lies strictly inside a sealed field of `document`.
+#[cfg(feature = "encryption")]
+fn crosses_sealed(document: &Value, path: &StatePath) -> bool {
+    (0..path.segments.len()).any(|len| {
+        let ancestor = StatePath {
+            segments: path.segments[..len].to_vec(),
+        };
+        crate::util::get_at_path(document, &ancestor).is_some_and(is_sealed)
+    })
+}
+
+/// [`Display`] adapter printing a document with its secrets redacted.
+pub struct RedactedJson<'a> {
+    policy: &'a SecretPolicy,
+    value: &'a Value,
+}
+
+impl Display for RedactedJson<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.policy.redact(self.value), f)
+    }
+}
+
+/// [`StateStore`] wrapper that enforces a [`SecretPolicy`] on every write.
+///
+/// Detected secrets are logged by pointer only, and each write is traced at `trace` level with
+/// its value redacted. With [`SecretAction::Encrypt`], secret fields are stored as
+/// [`crate::encrypt::EncryptedEnvelope`]s bound to the document's FQN; reads open every
+/// envelope they return, so callers see the original values.
+pub struct GuardedStateStore<S> {
+    inner: S,
+    policy: SecretPolicy,
+    #[cfg(feature = "encryption")]
+    keys: Option<std::sync::Arc<dyn crate::encrypt::KeyProvider>>,
+}
+
+impl<S: StateStore> GuardedStateStore<S> {
+    /// Wraps `inner`, applying `policy` to every write.
+    pub fn new(inner: S, policy: SecretPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            #[cfg(feature = "encryption")]
+            keys: None,
+        }
+    }
+
+    /// Supplies the data keys used by [`SecretAction::Encrypt`].
+    #[cfg(feature = "encryption")]
+    pub fn with_key_provider(
+        mut self,
+        keys: std::sync::Arc<dyn crate::encrypt::KeyProvider>,
+    ) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    /// Returns the wrapped store.
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the enforced policy.
+    pub fn policy(&self) -> &SecretPolicy {
+        &self.policy
+    }
+
+    #[cfg(feature = "encryption")]
+    fn key_provider(&self) -> GResult<&dyn crate::encrypt::KeyProvider> {
+        self.keys
+            .as_deref()
+            .ok_or_else(|| crate::error::internal("secret encryption requires a key provider"))
+    }
+
+    /// Seals the secrets of `value` (written at `base`) in place.
+    #[cfg(feature = "encryption")]
+    fn seal_findings(
+        &self,
+        tenant: &TenantCtx,
+        aad: &str,
+        value: &mut Value,
+        base: &StatePath,
+        findings: &[SecretFinding],
+    ) -> GResult<()> {
+        use crate::encrypt::EncryptedEnvelope;
+        use crate::util::get_at_path;
+
+        let keys = self.key_provider()?;
+        let data_key = keys.current_key(tenant)?;
+        for finding in findings {
+            let path = relative(&finding.path, base);
+            let Some(secret) = get_at_path(value, &path) else {
+                continue;
+            };
+            let sealed = EncryptedEnvelope::seal(&data_key, aad.as_bytes(), secret)?.to_value()?;
+            set_at_path(value, &path, sealed)?;
+        }
+        Ok(())
+    }
+
+    /// Opens every sealed field of a value read from the inner store.
+    #[cfg(feature = "encryption")]
+    fn open_fields(&self, tenant: &TenantCtx, aad: &str, value: &mut Value) -> GResult<()> {
+        use crate::encrypt::EncryptedEnvelope;
+
+        if let Some(envelope) = EncryptedEnvelope::from_value(value)? {
+            *value = envelope.open(self.key_provider()?, tenant, aad.as_bytes())?;
+            return Ok(());
+        }
+        match value {
+            Value::Object(map) => map
+                .values_mut()
+                .try_for_each(|child| self.open_fields(tenant, aad, child)),
+            Value::Array(items) => items
+                .iter_mut()
+                .try_for_each(|child| self.open_fields(tenant, aad, child)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Reads the whole document with every sealed field opened.
+    #[cfg(feature = "encryption")]
+    fn load_opened(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+    ) -> GResult<Option<Value>> {
+        let Some(mut document) = self.inner.get_json(tenant, prefix, key, None)? else {
+            return Ok(None);
+        };
+        self.open_fields(tenant, fqn(tenant, prefix, key).as_str(), &mut document)?;
+        Ok(Some(document))
+    }
+
+    /// Applies the policy to a write of `value` at `path`, returning the value to store instead
+    /// when secret-like fields were found.
+    fn guard_write(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+    ) -> GResult<Option<Value>> {
+        let base = path.cloned().unwrap_or_default();
+        let fqn = fqn(tenant, prefix, key);
+        let findings = self.policy.scan(value, &base);
+        trace!(
+            fqn = %fqn,
+            path = path.map(StatePath::to_pointer),
+            value = %self.policy.redact_at(value, &base),
+            "state write"
+        );
+        if findings.is_empty() {
+            return Ok(None);
+        }
+
+        let pointers: Vec<String> = findings
+            .iter()
+            .map(|finding| finding.path.to_pointer())
+            .collect();
+        warn!(
+            fqn = %fqn,
+            action = ?self.policy.action,
+            pointers = ?pointers,
+            "secret-like values in state write"
+        );
+        let guarded = match self.policy.action {
+            SecretAction::Reject => {
+                return Err(invalid_input(format!(
+                    "state value contains secret-like fields: {}",
+                    pointers.join(", ")
+                )));
+            }
+            SecretAction::Redact => self.policy.redact_at(value, &base),
+            #[cfg(feature = "encryption")]
+            SecretAction::Encrypt => {
+                let mut sealed = value.clone();
+                self.seal_findings(tenant, fqn.as_str(), &mut sealed, &base, &findings)?;
+                sealed
+            }
+        };
+        Ok(Some(guarded))
+    }
+}
+
+impl<S: StateStore> StateStore for GuardedStateStore<S> {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        // Sealed fields are opaque to the inner store, so paths are resolved on the plaintext.
+        #[cfg(feature = "encryption")]
+        if self.policy.action == SecretAction::Encrypt {
+            let document = self.load_opened(tenant, prefix, key)?;
+            return Ok(match path {
+                Some(path) => {
+                    document.and_then(|document| crate::util::get_at_path(&document, path).cloned())
+                }
+                None => document,
+            });
+        }
+        self.inner.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        // A path write into a sealed field would insert plaintext into its envelope, so the
+        // document is opened, patched and sealed again as a whole instead.
+        #[cfg(feature = "encryption")]
+        if let Some(path) = path
+            && self.policy.action == SecretAction::Encrypt
+        {
+            let stored = self.inner.get_json(tenant, prefix, key, None)?;
+            if let Some(mut document) = stored.filter(|stored| crosses_sealed(stored, path)) {
+                self.open_fields(tenant, fqn(tenant, prefix, key).as_str(), &mut document)?;
+                set_at_path(&mut document, path, value.clone())?;
+                let guarded = self.guard_write(tenant, prefix, key, None, &document)?;
+                return self.inner.set_json(
+                    tenant,
+                    prefix,
+                    key,
+                    None,
+                    guarded.as_ref().unwrap_or(&document),
+                    ttl_secs,
+                );
+            }
+        }
+        let guarded = self.guard_write(tenant, prefix, key, path, value)?;
+        self.inner.set_json(
+            tenant,
+            prefix,
+            key,
+            path,
+            guarded.as_ref().unwrap_or(value),
+            ttl_secs,
+        )
+    }
+
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let guarded = self.guard_write(tenant, prefix, key, None, value)?;
+        self.inner.set_if_absent(
+            tenant,
+            prefix,
+            key,
+            guarded.as_ref().unwrap_or(value),
+            ttl_secs,
+        )
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.inner.del(tenant, prefix, key)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix(tenant, prefix)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix_recursive(tenant, prefix)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.inner.prefix_stats(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.inner.list_keys(tenant, prefix)
+    }
+
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        self.inner.purge_tenant(tenant)
+    }
+
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        self.inner.purge_team(tenant, team)
+    }
+
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        self.inner.purge_user(tenant, user)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.inner.ttl(tenant, prefix, key)
+    }
+}
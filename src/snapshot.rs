@@ -0,0 +1,205 @@
+//! Portable NDJSON snapshots of a tenant's state, built on [`StateStore::list_keys`].
+
+use crate::error::{conflict, from_serde, invalid_input, with_context};
+use crate::key::{scope_members, tenant_from_ids};
+use crate::store::{ScopedKey, StateStore, StateTtl};
+use greentic_types::{GResult, StateKey, TenantCtx};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::{BufRead, Write};
+
+/// One exported entry: the FQN parts, the document and its remaining TTL.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    /// Environment of the owning tenant.
+    pub env: String,
+    /// Tenant identifier.
+    pub tenant: String,
+    /// Team scope, when the entry is team-scoped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub team: Option<String>,
+    /// User scope, when the entry is user-scoped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Caller-provided namespace prefix.
+    pub prefix: String,
+    /// State key within the prefix.
+    pub key: String,
+    /// Stored JSON document.
+    pub value: Value,
+    /// Remaining TTL in whole seconds (rounded up); `None` for entries that never expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
+
+impl SnapshotRecord {
+    /// Rebuilds the [`TenantCtx`] the record was exported from.
+    pub fn tenant_ctx(&self) -> GResult<TenantCtx> {
+        tenant_from_ids(
+            &self.env,
+            &self.tenant,
+            self.team.as_deref(),
+            self.user.as_deref(),
+        )
+    }
+
+    /// Returns the record's [`StateKey`].
+    pub fn state_key(&self) -> StateKey {
+        StateKey::new(self.key.as_str())
+    }
+
+    /// TTL argument that reproduces the record's expiry on `set_json` (`Some(0)` clears it).
+    pub fn ttl_arg(&self) -> Option<u32> {
+        Some(
+            self.ttl_secs
+                .map(|ttl| u32::try_from(ttl).unwrap_or(u32::MAX))
+                .unwrap_or(0),
+        )
+    }
+}
+
+/// How [`import`] treats records whose key already exists in the target store.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Replace the existing document and TTL.
+    #[default]
+    Overwrite,
+    /// Keep the existing document and count the record as skipped.
+    Skip,
+    /// Abort the import with a `Conflict` error.
+    Fail,
+}
+
+/// Outcome of an [`import`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    /// Records written to the store.
+    pub written: u64,
+    /// Records left out because of [`ConflictPolicy::Skip`].
+    pub skipped: u64,
+}
+
+/// Converts a remaining lifetime into whole seconds, rounding up so short TTLs survive.
+pub(crate) fn ttl_to_secs(ttl: StateTtl) -> Option<u64> {
+    match ttl {
+        StateTtl::Persistent => None,
+        StateTtl::ExpiresIn(remaining) => {
+            let secs = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+            Some(secs.max(1))
+        }
+    }
+}
+
+/// Lazily exports every entry for `tenant`, optionally narrowed to one `prefix`.
+///
+/// Keys are enumerated up front; entries that disappear before they are read are skipped.
+pub fn export<'a, S: StateStore + ?Sized>(
+    store: &'a S,
+    tenant: &TenantCtx,
+    prefix: Option<&str>,
+) -> GResult<impl Iterator<Item = GResult<SnapshotRecord>> + 'a> {
+    let keys = store.list_keys(tenant, prefix)?;
+    let tenant = tenant.clone();
+    Ok(keys
+        .into_iter()
+        .filter_map(move |entry| read_record(store, &tenant, &entry).transpose()))
+}
+
+/// Reads one entry as a [`SnapshotRecord`], or `None` when it vanished in the meantime.
+pub(crate) fn read_record<S: StateStore + ?Sized>(
+    store: &S,
+    tenant: &TenantCtx,
+    entry: &ScopedKey,
+) -> GResult<Option<SnapshotRecord>> {
+    let Some(value) = store.get_json(tenant, &entry.prefix, &entry.key, None)? else {
+        return Ok(None);
+    };
+    let Some(ttl) = store.ttl(tenant, &entry.prefix, &entry.key)? else {
+        return Ok(None);
+    };
+    let (team, user) = scope_members(tenant);
+    Ok(Some(SnapshotRecord {
+        env: tenant.env.as_str().to_owned(),
+        tenant: tenant.tenant_id.as_str().to_owned(),
+        team: team.map(str::to_owned),
+        user: user.map(str::to_owned),
+        prefix: entry.prefix.clone(),
+        key: entry.key.as_str().to_owned(),
+        value,
+        ttl_secs: ttl_to_secs(ttl),
+    }))
+}
+
+/// Writes `records` into `store`, resolving existing keys according to `policy`.
+pub fn import<S: StateStore + ?Sized>(
+    store: &S,
+    records: impl IntoIterator<Item = GResult<SnapshotRecord>>,
+    policy: ConflictPolicy,
+) -> GResult<ImportReport> {
+    let mut report = ImportReport::default();
+    for record in records {
+        let record = record?;
+        let tenant = record.tenant_ctx()?;
+        let key = record.state_key();
+
+        if policy != ConflictPolicy::Overwrite
+            && store
+                .get_json(&tenant, &record.prefix, &key, None)?
+                .is_some()
+        {
+            if policy == ConflictPolicy::Fail {
+                return Err(conflict(format!(
+                    "snapshot key `{}` already exists under prefix `{}`",
+                    record.key, record.prefix
+                )));
+            }
+            report.skipped += 1;
+            continue;
+        }
+
+        store.set_json(
+            &tenant,
+            &record.prefix,
+            &key,
+            None,
+            &record.value,
+            record.ttl_arg(),
+        )?;
+        report.written += 1;
+    }
+    Ok(report)
+}
+
+/// Serializes `records` as newline-delimited JSON, returning the number of records written.
+pub fn write_ndjson<W: Write>(
+    mut writer: W,
+    records: impl IntoIterator<Item = GResult<SnapshotRecord>>,
+) -> GResult<u64> {
+    let mut count = 0;
+    for record in records {
+        let line = serde_json::to_string(&record?).map_err(from_serde)?;
+        writeln!(writer, "{line}").map_err(|err| with_context(err, "write snapshot"))?;
+        count += 1;
+    }
+    writer
+        .flush()
+        .map_err(|err| with_context(err, "write snapshot"))?;
+    Ok(count)
+}
+
+/// Parses newline-delimited JSON records, skipping blank lines.
+pub fn read_ndjson<R: BufRead>(reader: R) -> impl Iterator<Item = GResult<SnapshotRecord>> {
+    reader.lines().enumerate().filter_map(|(index, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => return Some(Err(with_context(err, "read snapshot"))),
+        };
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(
+            serde_json::from_str(&line)
+                .map_err(|err| invalid_input(format!("snapshot line {}: {err}", index + 1))),
+        )
+    })
+}
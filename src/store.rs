@@ -1,6 +1,94 @@
-use crate::key::StatePath;
-use greentic_types::{GResult, StateKey, TenantCtx};
+use crate::error::unsupported;
+use crate::key::{StatePath, prefix_covers};
+use crate::util::json_size;
+use greentic_types::{GResult, StateKey, TeamId, TenantCtx, UserId};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// A stored entry identified by its caller-provided prefix and [`StateKey`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ScopedKey {
+    /// Namespace prefix the entry was written under.
+    pub prefix: String,
+    /// Key within the prefix.
+    pub key: StateKey,
+}
+
+impl ScopedKey {
+    /// Orders keys by prefix, then key, matching [`StateStore::list_keys`].
+    pub fn sort(keys: &mut [ScopedKey]) {
+        keys.sort_by(|a, b| (&a.prefix, a.key.as_str()).cmp(&(&b.prefix, b.key.as_str())));
+    }
+}
+
+/// Remaining lifetime of a stored entry, as reported by [`StateStore::ttl`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateTtl {
+    /// The entry never expires.
+    Persistent,
+    /// The entry expires after the given duration.
+    ExpiresIn(Duration),
+}
+
+/// Aggregate figures for a prefix and its sub-prefixes, as reported by
+/// [`StateStore::prefix_stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrefixStats {
+    /// Number of live entries.
+    pub keys: u64,
+    /// Size of the stored values in bytes: serialized JSON for the in-memory store, the stored
+    /// payload (after encoding and compression) for Redis.
+    pub total_bytes: u64,
+    /// Number of entries without a TTL.
+    pub persistent: u64,
+    /// Soonest expiry among entries with a TTL.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub earliest_expiry: Option<OffsetDateTime>,
+    /// Latest expiry among entries with a TTL.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub latest_expiry: Option<OffsetDateTime>,
+}
+
+impl PrefixStats {
+    /// Accounts for one entry of `bytes` expiring at `expires_at` (`None` when persistent).
+    pub fn add(&mut self, bytes: u64, expires_at: Option<OffsetDateTime>) {
+        self.keys += 1;
+        self.total_bytes += bytes;
+        match expires_at {
+            Some(at) => {
+                self.earliest_expiry = Some(self.earliest_expiry.map_or(at, |cur| cur.min(at)));
+                self.latest_expiry = Some(self.latest_expiry.map_or(at, |cur| cur.max(at)));
+            }
+            None => self.persistent += 1,
+        }
+    }
+}
+
+/// Outcome of [`StateStore::purge_tenant`], [`StateStore::purge_team`] or
+/// [`StateStore::purge_user`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PurgeReport {
+    /// Purged scope: `{env}:{tenant}`, optionally followed by `:team={team}` or `:user={user}`.
+    pub scope: String,
+    /// Number of entries removed.
+    pub deleted: u64,
+    /// When the purge finished.
+    #[serde(with = "time::serde::rfc3339")]
+    pub completed_at: OffsetDateTime,
+}
+
+impl PurgeReport {
+    /// Report for `deleted` entries removed from `scope`, completed now.
+    pub fn new(scope: impl Into<String>, deleted: u64) -> Self {
+        Self {
+            scope: scope.into(),
+            deleted,
+            completed_at: OffsetDateTime::now_utc(),
+        }
+    }
+}
 
 /// JSON state store operations shared across backends.
 pub trait StateStore: Send + Sync + 'static {
@@ -35,4 +123,99 @@ pub trait StateStore: Send + Sync + 'static {
     /// Bulk delete all keys under `(tenant, prefix)` — used for flow cleanup, etc.
     /// Returns the number of entries removed.
     fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64>;
+
+    /// List the live entries stored for `tenant`, sorted by prefix and key.
+    /// `Some(prefix)` narrows the listing to `(tenant, prefix)`; `None` lists every prefix in the
+    /// tenant's exact scope (entries of narrower team/user scopes are not included).
+    ///
+    /// Backends that only see FQN strings cannot tell a prefix or key containing `:` apart from a
+    /// narrower scope when `prefix` is `None`; they fail rather than leave such entries out.
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        let _ = (tenant, prefix);
+        Err(unsupported("list_keys"))
+    }
+
+    /// Atomically write `value` as the whole document at `(tenant, prefix, key)` unless a live
+    /// entry already exists there. Returns `true` when the value was written.
+    /// `Some(ttl)` sets the new entry's expiry; `None` and `Some(0)` write it without one.
+    fn set_if_absent(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<bool> {
+        let _ = (tenant, prefix, key, value, ttl_secs);
+        Err(unsupported("set_if_absent"))
+    }
+
+    /// Report the remaining lifetime of `(tenant, prefix, key)`, or `None` when it does not exist.
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        let _ = (tenant, prefix, key);
+        Err(unsupported("ttl"))
+    }
+
+    /// Delete every entry under `(tenant, prefix)` and under its sub-prefixes `{prefix}/…`, e.g.
+    /// all runs of a flow or all flows of a pack. Returns the number of entries removed.
+    ///
+    /// The default implementation deletes the entries reported by [`StateStore::list_keys`] one
+    /// at a time.
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        let mut removed = 0;
+        for entry in self.list_keys(tenant, None)? {
+            if prefix_covers(prefix, &entry.prefix)
+                && self.del(tenant, &entry.prefix, &entry.key)?
+            {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Delete every entry of `tenant`'s env and tenant id, across all prefixes, teams and users.
+    /// The team and user of `tenant` are ignored.
+    ///
+    /// Purges remove state entries only; leases and rate limiter state expire on their own.
+    fn purge_tenant(&self, tenant: &TenantCtx) -> GResult<PurgeReport> {
+        let _ = tenant;
+        Err(unsupported("purge_tenant"))
+    }
+
+    /// Delete every entry written in `team`'s scope or in the scope of any user within `team`,
+    /// across all prefixes. The team and user of `tenant` are ignored.
+    fn purge_team(&self, tenant: &TenantCtx, team: &TeamId) -> GResult<PurgeReport> {
+        let _ = (tenant, team);
+        Err(unsupported("purge_team"))
+    }
+
+    /// Delete every entry written in `user`'s scope, with or without a team, across all
+    /// prefixes. The team and user of `tenant` are ignored.
+    fn purge_user(&self, tenant: &TenantCtx, user: &UserId) -> GResult<PurgeReport> {
+        let _ = (tenant, user);
+        Err(unsupported("purge_user"))
+    }
+
+    /// Summarize the entries under `(tenant, prefix)` and its sub-prefixes `{prefix}/…`.
+    ///
+    /// The default implementation reads every entry reported by [`StateStore::list_keys`] and
+    /// counts its serialized JSON size.
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        let now = OffsetDateTime::now_utc();
+        let mut stats = PrefixStats::default();
+        for entry in self.list_keys(tenant, None)? {
+            if !prefix_covers(prefix, &entry.prefix) {
+                continue;
+            }
+            let Some(value) = self.get_json(tenant, &entry.prefix, &entry.key, None)? else {
+                continue;
+            };
+            let expires_at = match self.ttl(tenant, &entry.prefix, &entry.key)? {
+                Some(StateTtl::ExpiresIn(remaining)) => Some(now + remaining),
+                Some(StateTtl::Persistent) | None => None,
+            };
+            stats.add(json_size(&value), expires_at);
+        }
+        Ok(stats)
+    }
 }
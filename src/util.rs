@@ -1,7 +1,11 @@
 use crate::error::invalid_input;
 use crate::key::StatePath;
+use crate::limits::StateLimits;
 use greentic_types::GResult;
 use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::io;
 
 /// Retrieves a nested value at the provided `StatePath`.
 pub fn get_at_path<'a>(value: &'a Value, path: &StatePath) -> Option<&'a Value> {
@@ -25,8 +29,20 @@ pub fn get_at_path<'a>(value: &'a Value, path: &StatePath) -> Option<&'a Value>
     Some(current)
 }
 
-/// Upserts `new_value` at the provided `StatePath`.
+/// Upserts `new_value` at the provided `StatePath`, padding arrays within the default
+/// [`StateLimits::max_array_len`].
 pub fn set_at_path(target: &mut Value, path: &StatePath, new_value: Value) -> GResult<()> {
+    set_at_path_with_limits(target, path, new_value, &StateLimits::default())
+}
+
+/// Upserts `new_value` at the provided `StatePath`, refusing to pad an array beyond
+/// `limits.max_array_len` elements.
+pub fn set_at_path_with_limits(
+    target: &mut Value,
+    path: &StatePath,
+    new_value: Value,
+    limits: &StateLimits,
+) -> GResult<()> {
     if path.segments.is_empty() {
         *target = new_value;
         return Ok(());
@@ -52,12 +68,11 @@ pub fn set_at_path(target: &mut Value, path: &StatePath, new_value: Value) -> GR
                 let index = parse_index(segment).ok_or_else(|| {
                     invalid_input(format!("array index expected for segment `{segment}`"))
                 })?;
+                ensure_len(items, index, limits)?;
                 if idx == last {
-                    ensure_len(items, index);
                     items[index] = new_value;
                     return Ok(());
                 }
-                ensure_len(items, index);
                 current = &mut items[index];
             }
             _ => {
@@ -75,10 +90,12 @@ fn parse_index(segment: &str) -> Option<usize> {
     segment.parse::<usize>().ok()
 }
 
-fn ensure_len(items: &mut Vec<Value>, index: usize) {
+fn ensure_len(items: &mut Vec<Value>, index: usize, limits: &StateLimits) -> GResult<()> {
     if index >= items.len() {
+        limits.check_array_len(index.saturating_add(1))?;
         items.resize_with(index + 1, || Value::Null);
     }
+    Ok(())
 }
 
 fn container_for_segment(segment: &str) -> Value {
@@ -89,6 +106,37 @@ fn container_for_segment(segment: &str) -> Value {
     }
 }
 
+/// Lower-case hex SHA-256 digest of `bytes`.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::with_capacity(64), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
+
+/// Serialized size of `value` without allocating the JSON text.
+pub(crate) fn json_size(value: &Value) -> u64 {
+    struct Counter(u64);
+
+    impl io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0 += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    // Writing a `Value` into an infallible sink cannot fail.
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
 #[cfg(test)]
 mod tests {
     #![allow(clippy::expect_used, clippy::unwrap_used)]
@@ -112,6 +160,15 @@ mod tests {
         assert_eq!(extracted, &json!("leaf"));
     }
 
+    #[test]
+    fn array_padding_is_limited() {
+        let mut value = Value::Null;
+        let err =
+            set_at_path(&mut value, &StatePath::from_pointer("/999999999"), json!(1)).unwrap_err();
+        assert_eq!(err.code, greentic_types::ErrorCode::InvalidInput);
+        assert_eq!(value, json!([]));
+    }
+
     #[test]
     fn invalid_array_index_errors() {
         let mut value = Value::Array(Vec::new());
@@ -0,0 +1,164 @@
+use greentic_state::audit::{
+    AuditOperation, AuditRecord, AuditSink, AuditedStateStore, JsonLinesAuditSink, value_hash,
+};
+use greentic_state::{
+    StateKey, StatePath, StateStore, TenantCtx, fqn, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, GResult, TenantId, UserId};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::env;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+    .with_user(Some(UserId::try_from("alice").expect("valid user id")))
+}
+
+#[derive(Default)]
+struct CollectingSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl AuditSink for CollectingSink {
+    fn record(&self, record: &AuditRecord) -> GResult<()> {
+        self.records.lock().push(record.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn records_every_mutation_with_hashes() {
+    let sink = Arc::new(CollectingSink::default());
+    let store = AuditedStateStore::new(InMemoryStateStore::new(), sink.clone());
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+
+    store
+        .set_json(&ctx, "flow/audit", &key, None, &json!({"a": 1}), None)
+        .expect("set");
+    store
+        .set_json(
+            &ctx,
+            "flow/audit",
+            &key,
+            Some(&StatePath::from_pointer("/a")),
+            &json!(2),
+            None,
+        )
+        .expect("path set");
+    store.del(&ctx, "flow/audit", &key).expect("del");
+    assert!(!store.del(&ctx, "flow/audit", &key).expect("second del"));
+    store
+        .set_json(&ctx, "flow/audit", &key, None, &json!(true), None)
+        .expect("set again");
+    assert_eq!(store.del_prefix(&ctx, "flow/audit").expect("del prefix"), 1);
+
+    let records = sink.records.lock().clone();
+    let operations: Vec<_> = records.iter().map(|record| record.operation).collect();
+    assert_eq!(
+        operations,
+        vec![
+            AuditOperation::Set,
+            AuditOperation::SetPath,
+            AuditOperation::Del,
+            AuditOperation::Set,
+            AuditOperation::DelPrefix,
+        ]
+    );
+
+    let first = &records[0];
+    assert_eq!(first.fqn, fqn(&ctx, "flow/audit", &key).0);
+    assert_eq!(first.user.as_deref(), Some("alice"));
+    assert_eq!(first.old_hash, None);
+    let v1 = value_hash(&json!({"a": 1})).expect("hash");
+    let v2 = value_hash(&json!({"a": 2})).expect("hash");
+    assert_eq!(first.new_hash.as_deref(), Some(v1.as_str()));
+
+    let update = &records[1];
+    assert_eq!(update.path.as_deref(), Some("/a"));
+    assert_eq!(update.old_hash.as_deref(), Some(v1.as_str()));
+    assert_eq!(update.new_hash.as_deref(), Some(v2.as_str()));
+
+    assert_eq!(records[2].old_hash.as_deref(), Some(v2.as_str()));
+    assert_eq!(records[2].new_hash, None);
+    assert_eq!(records[4].deleted, Some(1));
+    assert!(records[4].fqn.ends_with(":flow/audit:"));
+}
+
+#[test]
+fn json_lines_sink_appends_records() {
+    let path = env::temp_dir().join(format!("greentic-audit-{}.jsonl", Uuid::new_v4()));
+    let sink = Arc::new(JsonLinesAuditSink::open(&path).expect("open"));
+    let store = AuditedStateStore::new(InMemoryStateStore::new(), sink);
+    let ctx = ctx();
+
+    store
+        .set_json(
+            &ctx,
+            "flow/audit-file",
+            &StateKey::new("node/a"),
+            None,
+            &json!(1),
+            None,
+        )
+        .expect("set");
+    store
+        .del(&ctx, "flow/audit-file", &StateKey::new("node/a"))
+        .expect("del");
+
+    let contents = std::fs::read_to_string(&path).expect("read log");
+    let records: Vec<AuditRecord> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("record"))
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].operation, AuditOperation::Del);
+    assert!(!contents.contains("\"value\""), "values are never logged");
+
+    std::fs::remove_file(&path).expect("cleanup");
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_stream_sink_appends_when_available() {
+    use greentic_state::audit::RedisStreamAuditSink;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(client) = redis::Client::open(url.as_str()) else {
+        return;
+    };
+    let Ok(mut conn) = client.get_connection() else {
+        return;
+    };
+    let stream = format!("greentic:audit:test-{}", Uuid::new_v4());
+    let sink = Arc::new(RedisStreamAuditSink::new(client, stream.clone()).with_max_len(100));
+    let store = AuditedStateStore::new(InMemoryStateStore::new(), sink);
+
+    store
+        .set_json(
+            &ctx(),
+            "flow/audit-redis",
+            &StateKey::new("node/a"),
+            None,
+            &json!(1),
+            None,
+        )
+        .expect("set");
+
+    let len: u64 = redis::cmd("XLEN")
+        .arg(&stream)
+        .query(&mut conn)
+        .expect("xlen");
+    assert_eq!(len, 1);
+    let _: i64 = redis::cmd("DEL")
+        .arg(&stream)
+        .query(&mut conn)
+        .expect("cleanup");
+}
@@ -1,6 +1,12 @@
-use greentic_state::{StateKey, StateStore, TenantCtx, inmemory::InMemoryStateStore};
-use greentic_types::{EnvId, TenantId};
-use serde_json::json;
+use greentic_state::{
+    PrefixStats, ScopedKey, StateKey, StatePath, StateStore, StateTtl, TenantCtx,
+    inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, GResult, TenantId};
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "redis")]
 use uuid::Uuid;
 
 fn ctx() -> TenantCtx {
@@ -59,6 +65,303 @@ fn in_memory_bulk_delete() {
     );
 }
 
+fn other_tenant() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("other").expect("valid tenant id"),
+    )
+}
+
+/// Seeds a pack with two flows, a sibling pack and another tenant, then checks recursive
+/// deletion and stats on the first flow.
+fn assert_recursive_delete(store: &dyn StateStore, pack: &str) {
+    let ctx = ctx();
+    let other_tenant = other_tenant();
+    let flow = format!("{pack}/flow/ingest");
+    let writes = [
+        (&ctx, flow.clone(), Some(60)),
+        (&ctx, format!("{flow}/run/r1"), Some(120)),
+        (&ctx, format!("{flow}/run/r2"), None),
+        (&ctx, format!("{pack}/flow/ingest-v2"), None),
+        (&ctx, format!("{pack}/flow/other/run/r1"), None),
+        (&other_tenant, format!("{flow}/run/r1"), None),
+    ];
+    for (tenant, prefix, ttl) in &writes {
+        store
+            .set_json(
+                tenant,
+                prefix,
+                &StateKey::new("node/a"),
+                None,
+                &json!({"n": 1}),
+                *ttl,
+            )
+            .expect("seed");
+    }
+
+    let stats = store.prefix_stats(&ctx, &flow).expect("stats");
+    assert_eq!(stats.keys, 3);
+    assert_eq!(stats.persistent, 1);
+    assert!(stats.total_bytes > 0);
+    let (earliest, latest) = (
+        stats.earliest_expiry.expect("earliest"),
+        stats.latest_expiry.expect("latest"),
+    );
+    let spread = (latest - earliest).whole_seconds();
+    assert!((59..=61).contains(&spread), "{spread}s between expiries");
+
+    assert_eq!(store.del_prefix_recursive(&ctx, &flow).expect("delete"), 3);
+    assert_eq!(store.prefix_stats(&ctx, &flow).expect("stats").keys, 0);
+    assert_eq!(store.prefix_stats(&ctx, pack).expect("stats").keys, 2);
+    assert!(
+        store
+            .get_json(
+                &other_tenant,
+                &format!("{flow}/run/r1"),
+                &StateKey::new("node/a"),
+                None
+            )
+            .expect("get")
+            .is_some(),
+        "other tenants are untouched"
+    );
+
+    assert_eq!(store.del_prefix_recursive(&ctx, pack).expect("delete"), 2);
+    store
+        .del_prefix_recursive(&other_tenant, pack)
+        .expect("cleanup");
+}
+
+#[test]
+fn in_memory_recursive_delete_and_stats() {
+    assert_recursive_delete(&InMemoryStateStore::new(), "pack/p1");
+}
+
+/// Backend implementing only the methods the default `del_prefix_recursive` and `prefix_stats`
+/// build on.
+struct DefaultsOnly(InMemoryStateStore);
+
+impl StateStore for DefaultsOnly {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        self.0.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.0.set_json(tenant, prefix, key, path, value, ttl_secs)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.0.del(tenant, prefix, key)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.0.del_prefix(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.0.list_keys(tenant, prefix)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.0.ttl(tenant, prefix, key)
+    }
+}
+
+/// Backend counting the `del_prefix_recursive` and `prefix_stats` calls that reach it.
+struct Counting {
+    inner: InMemoryStateStore,
+    calls: Arc<AtomicUsize>,
+}
+
+impl Counting {
+    fn new() -> (Self, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store = Self {
+            inner: InMemoryStateStore::new(),
+            calls: Arc::clone(&calls),
+        };
+        (store, calls)
+    }
+}
+
+impl StateStore for Counting {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        self.inner.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        self.inner
+            .set_json(tenant, prefix, key, path, value, ttl_secs)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.inner.del(tenant, prefix, key)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix(tenant, prefix)
+    }
+
+    fn list_keys(&self, tenant: &TenantCtx, prefix: Option<&str>) -> GResult<Vec<ScopedKey>> {
+        self.inner.list_keys(tenant, prefix)
+    }
+
+    fn ttl(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<Option<StateTtl>> {
+        self.inner.ttl(tenant, prefix, key)
+    }
+
+    fn del_prefix_recursive(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.del_prefix_recursive(tenant, prefix)
+    }
+
+    fn prefix_stats(&self, tenant: &TenantCtx, prefix: &str) -> GResult<PrefixStats> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.inner.prefix_stats(tenant, prefix)
+    }
+}
+
+#[test]
+fn default_recursive_delete_and_stats() {
+    assert_recursive_delete(&DefaultsOnly(InMemoryStateStore::new()), "pack/p1");
+}
+
+#[test]
+fn wrappers_forward_recursive_delete_and_stats() {
+    use greentic_state::audit::{AuditedStateStore, TracingAuditSink};
+    use greentic_state::cache::{CacheConfig, CachedStateStore};
+    use greentic_state::instrument::InstrumentedStateStore;
+    use greentic_state::replay::RecordingStateStore;
+    use greentic_state::secrets::{GuardedStateStore, SecretAction, SecretPolicy};
+
+    type Wrap = fn(Counting) -> Box<dyn StateStore>;
+    let wrappers: &[(&str, Wrap)] = &[
+        ("audit", |inner| {
+            Box::new(AuditedStateStore::new(inner, Arc::new(TracingAuditSink)))
+        }),
+        ("cache", |inner| {
+            Box::new(CachedStateStore::new(inner, CacheConfig::default()))
+        }),
+        ("instrument", |inner| {
+            Box::new(InstrumentedStateStore::new(inner))
+        }),
+        ("replay", |inner| {
+            Box::new(RecordingStateStore::new(inner, std::io::sink()))
+        }),
+        ("secrets", |inner| {
+            Box::new(GuardedStateStore::new(
+                inner,
+                SecretPolicy::with_defaults(SecretAction::Reject),
+            ))
+        }),
+        #[cfg(feature = "encryption")]
+        ("encrypt", |inner| {
+            use greentic_state::encrypt::{
+                Cipher, DataKey, EncryptedStateStore, StaticKeyProvider,
+            };
+            let keys = StaticKeyProvider::new();
+            for tenant in [ctx(), other_tenant()] {
+                keys.rotate(&tenant, DataKey::generate("k1", Cipher::Aes256Gcm));
+            }
+            Box::new(EncryptedStateStore::new(inner, Arc::new(keys)))
+        }),
+        #[cfg(feature = "testing")]
+        ("faults", |inner| {
+            Box::new(greentic_state::faults::FaultyStateStore::new(inner, 1))
+        }),
+    ];
+
+    for (name, wrap) in wrappers {
+        let (inner, calls) = Counting::new();
+        assert_recursive_delete(wrap(inner).as_ref(), "pack/p1");
+        assert_eq!(
+            calls.load(Ordering::Relaxed),
+            6,
+            "{name} forwards both calls"
+        );
+    }
+}
+
+#[test]
+fn cache_drops_recursively_deleted_entries() {
+    use greentic_state::cache::{CacheConfig, CachedStateStore};
+
+    let store = CachedStateStore::new(InMemoryStateStore::new(), CacheConfig::default());
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+    for prefix in ["pack/p1", "pack/p1/flow/ingest"] {
+        store
+            .set_json(&ctx, prefix, &key, None, &json!({"n": 1}), None)
+            .expect("seed");
+        assert!(
+            store
+                .get_json(&ctx, prefix, &key, None)
+                .expect("get")
+                .is_some()
+        );
+    }
+
+    assert_eq!(
+        store.del_prefix_recursive(&ctx, "pack/p1").expect("delete"),
+        2
+    );
+    for prefix in ["pack/p1", "pack/p1/flow/ingest"] {
+        assert!(
+            store
+                .get_json(&ctx, prefix, &key, None)
+                .expect("get")
+                .is_none(),
+            "{prefix} is no longer cached"
+        );
+    }
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_recursive_delete_and_stats() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&ctx(), Some("probe")).is_err() {
+        return;
+    }
+    assert_recursive_delete(&store, &format!("pack/{}", Uuid::new_v4()));
+}
+
 #[cfg(feature = "redis")]
 #[test]
 fn redis_bulk_delete_when_available() {
@@ -0,0 +1,350 @@
+use greentic_state::cache::{
+    CACHE_INVALIDATIONS_TOTAL, CACHE_READS_TOTAL, CacheConfig, CacheInvalidator, CachedStateStore,
+    Invalidation, InvalidationMessage,
+};
+use greentic_state::{
+    StateKey, StatePath, StateStore, TenantCtx, fqn, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, GResult, TenantId};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use parking_lot::Mutex;
+use serde_json::json;
+use std::sync::Arc;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+#[derive(Default)]
+struct CollectingInvalidator {
+    messages: Mutex<Vec<InvalidationMessage>>,
+}
+
+impl CacheInvalidator for CollectingInvalidator {
+    fn publish(&self, message: &InvalidationMessage) -> GResult<()> {
+        self.messages.lock().push(message.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn reads_are_cached_after_first_miss() {
+    let remote = InMemoryStateStore::new();
+    let cache = CachedStateStore::new(remote.clone(), CacheConfig::default());
+    let ctx = ctx();
+    let prefix = "flow/cache";
+    let key = StateKey::new("node/a");
+
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!({"a": [1, 2]}), None)
+        .expect("seed remote");
+
+    let first = cache.get_json(&ctx, prefix, &key, None).expect("first get");
+    assert_eq!(first, Some(json!({"a": [1, 2]})));
+    let path = StatePath::from_pointer("/a/1");
+    let second = cache
+        .get_json(&ctx, prefix, &key, Some(&path))
+        .expect("second get");
+    assert_eq!(second, Some(json!(2)));
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+
+    // A write that bypasses the cache is not visible until invalidated.
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!({"a": []}), None)
+        .expect("remote write");
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("stale get"),
+        Some(json!({"a": [1, 2]}))
+    );
+
+    cache.invalidation_target().apply(&Invalidation::Key {
+        fqn: fqn(&ctx, prefix, &key).0,
+    });
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("fresh get"),
+        Some(json!({"a": []}))
+    );
+    assert_eq!(cache.stats().invalidations, 1);
+}
+
+#[test]
+fn writes_go_through_to_remote() {
+    let remote = InMemoryStateStore::new();
+    let cache = CachedStateStore::new(remote.clone(), CacheConfig::default());
+    let ctx = ctx();
+    let prefix = "flow/cache-write";
+    let key = StateKey::new("node/a");
+
+    cache
+        .set_json(
+            &ctx,
+            prefix,
+            &key,
+            None,
+            &json!({"status": "ready"}),
+            Some(60),
+        )
+        .expect("set");
+    assert_eq!(
+        remote
+            .get_json(&ctx, prefix, &key, None)
+            .expect("remote get"),
+        Some(json!({"status": "ready"}))
+    );
+    assert_eq!(
+        cache
+            .get_json(&ctx, prefix, &key, None)
+            .expect("cached get"),
+        Some(json!({"status": "ready"}))
+    );
+    assert_eq!(cache.stats().hits, 1);
+
+    let path = StatePath::from_pointer("/status");
+    cache
+        .set_json(&ctx, prefix, &key, Some(&path), &json!("running"), None)
+        .expect("path set");
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"status": "running"}))
+    );
+
+    assert!(cache.del(&ctx, prefix, &key).expect("delete"));
+    assert!(
+        remote
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .is_none()
+    );
+    assert!(
+        cache
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .is_none()
+    );
+}
+
+#[test]
+fn peers_evict_entries_written_elsewhere() {
+    let remote = InMemoryStateStore::new();
+    let bus = Arc::new(CollectingInvalidator::default());
+    let writer =
+        CachedStateStore::new(remote.clone(), CacheConfig::default()).with_invalidator(bus.clone());
+    let reader = CachedStateStore::new(remote.clone(), CacheConfig::default());
+    let ctx = ctx();
+    let prefix = "flow/cache-peers";
+    let key = StateKey::new("node/a");
+
+    writer
+        .set_json(&ctx, prefix, &key, None, &json!(1), None)
+        .expect("set");
+    assert_eq!(
+        reader.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!(1))
+    );
+
+    writer
+        .set_json(&ctx, prefix, &key, None, &json!(2), None)
+        .expect("update");
+    writer.del_prefix(&ctx, prefix).expect("delete prefix");
+
+    let messages = bus.messages.lock().clone();
+    assert_eq!(messages.len(), 3);
+    let reader_target = reader.invalidation_target();
+    let writer_target = writer.invalidation_target();
+    for message in &messages {
+        let decoded =
+            InvalidationMessage::decode(&message.encode().expect("encode")).expect("decode");
+        assert_eq!(&decoded, message);
+        reader_target.apply_message(&decoded);
+        writer_target.apply_message(&decoded);
+    }
+
+    assert!(
+        reader
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .is_none()
+    );
+    assert_eq!(reader.stats().invalidations, 3);
+    assert_eq!(writer.stats().invalidations, 0, "own messages are skipped");
+}
+
+#[test]
+fn zero_entry_ttl_disables_local_caching() {
+    let remote = InMemoryStateStore::new();
+    let cache = CachedStateStore::new(remote.clone(), CacheConfig { entry_ttl_secs: 0 });
+    let ctx = ctx();
+    let prefix = "flow/cache-off";
+    let key = StateKey::new("node/a");
+
+    cache
+        .set_json(&ctx, prefix, &key, None, &json!(1), Some(60))
+        .expect("set");
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!(2), None)
+        .expect("remote write");
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!(2)),
+        "writes are not cached"
+    );
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!(3), None)
+        .expect("remote write");
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!(3)),
+        "reads are not cached"
+    );
+    assert_eq!(cache.stats().hits, 0);
+}
+
+/// `(name, labels, count)` of one recorded counter.
+type Counter = (String, Vec<(String, String)>, u64);
+
+#[test]
+fn hits_misses_and_invalidations_are_exported_as_metrics() {
+    let recorder = DebuggingRecorder::new();
+    let remote = InMemoryStateStore::new();
+    let cache = CachedStateStore::new(remote.clone(), CacheConfig::default());
+    let ctx = ctx();
+    let prefix = "flow/cache-metrics";
+    let key = StateKey::new("node/a");
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!(1), None)
+        .expect("seed remote");
+
+    metrics::with_local_recorder(&recorder, || {
+        cache.get_json(&ctx, prefix, &key, None).expect("miss");
+        cache.get_json(&ctx, prefix, &key, None).expect("hit");
+        cache.get_json(&ctx, prefix, &key, None).expect("hit");
+        cache.invalidation_target().apply(&Invalidation::All);
+    });
+
+    let counters: Vec<Counter> = recorder
+        .snapshotter()
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .filter_map(|(key, _, _, value)| match value {
+            DebugValue::Counter(count) => {
+                let key = key.key();
+                let labels = key
+                    .labels()
+                    .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                    .collect();
+                Some((key.name().to_owned(), labels, count))
+            }
+            _ => None,
+        })
+        .collect();
+    let count = |name: &str, label: (&str, &str)| {
+        counters
+            .iter()
+            .filter(|(entry, labels, _)| {
+                entry == name
+                    && labels
+                        .iter()
+                        .any(|(key, value)| (key.as_str(), value.as_str()) == label)
+            })
+            .map(|(_, _, count)| *count)
+            .sum::<u64>()
+    };
+    assert_eq!(count(CACHE_READS_TOTAL, ("result", "hit")), 2);
+    assert_eq!(count(CACHE_READS_TOTAL, ("result", "miss")), 1);
+    assert_eq!(count(CACHE_INVALIDATIONS_TOTAL, ("kind", "all")), 1);
+}
+
+/// Starts a fake Redis that counts connections, answers `PUBLISH` with `:0` and everything
+/// else with `+OK`, and closes each connection after `publishes_per_connection` publishes.
+#[cfg(feature = "redis")]
+fn fake_pubsub_redis(
+    publishes_per_connection: usize,
+) -> (redis::Client, Arc<std::sync::atomic::AtomicUsize>) {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Reads one RESP command and returns its name.
+    fn read_command(reader: &mut impl BufRead) -> Option<String> {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+        let mut name = None;
+        for _ in 0..count {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+            let mut arg = vec![0; len + 2];
+            reader.read_exact(&mut arg).ok()?;
+            arg.truncate(len);
+            name.get_or_insert_with(|| String::from_utf8_lossy(&arg).into_owned());
+        }
+        name
+    }
+
+    let listener = TcpListener::bind("127.0.0.1:0").expect("bind fake redis");
+    let addr = listener.local_addr().expect("fake redis address");
+    let connections = Arc::new(AtomicUsize::new(0));
+    let counter = connections.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            counter.fetch_add(1, Ordering::SeqCst);
+            std::thread::spawn(move || {
+                let Ok(mut writer) = stream.try_clone() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+                let mut publishes = 0;
+                while publishes < publishes_per_connection {
+                    let Some(command) = read_command(&mut reader) else {
+                        return;
+                    };
+                    let reply: &[u8] = if command.eq_ignore_ascii_case("PUBLISH") {
+                        publishes += 1;
+                        b":0\r\n"
+                    } else {
+                        b"+OK\r\n"
+                    };
+                    if writer.write_all(reply).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    let client = redis::Client::open(format!("redis://{addr}/")).expect("fake redis url");
+    (client, connections)
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_bus_reuses_its_publish_connection() {
+    use greentic_state::cache::RedisInvalidationBus;
+    use std::sync::atomic::Ordering;
+
+    let message = InvalidationMessage {
+        origin: "node-a".into(),
+        invalidation: Invalidation::All,
+    };
+
+    let (client, connections) = fake_pubsub_redis(usize::MAX);
+    let bus = RedisInvalidationBus::new(client, "invalidations");
+    for _ in 0..3 {
+        bus.publish(&message).expect("publish");
+    }
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+    // A connection closed by the server is replaced on the next publish.
+    let (client, connections) = fake_pubsub_redis(1);
+    let bus = RedisInvalidationBus::new(client, "invalidations");
+    bus.publish(&message).expect("first publish");
+    bus.publish(&message)
+        .expect("publish after the server closed the connection");
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "cli")]
+
+use serde_json::{Value, json};
+use std::env;
+use std::path::Path;
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+fn run(store: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_greentic-state"))
+        .args(args)
+        .args(["--store", store.to_str().expect("utf-8 path")])
+        .args(["--env", "dev", "--tenant", "tenant", "--raw"])
+        .output()
+        .expect("run greentic-state")
+}
+
+fn stdout_json(output: &Output) -> Value {
+    assert!(
+        output.status.success(),
+        "command failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("json output")
+}
+
+#[test]
+fn file_store_roundtrip() {
+    let store = env::temp_dir().join(format!("greentic-cli-{}.ndjson", Uuid::new_v4()));
+
+    let set = run(
+        &store,
+        &["set", "flow/cli", "node/a", r#"{"a":[1,2]}"#, "--ttl", "60"],
+    );
+    assert!(set.status.success());
+    let patch = run(
+        &store,
+        &["set", "flow/cli", "node/a", "3", "--path", "/a/1"],
+    );
+    assert!(patch.status.success());
+
+    assert_eq!(
+        stdout_json(&run(&store, &["get", "flow/cli", "node/a"])),
+        json!({"a": [1, 3]})
+    );
+    assert_eq!(
+        stdout_json(&run(&store, &["ls"])),
+        json!([{"prefix": "flow/cli", "key": "node/a"}])
+    );
+    let ttl = stdout_json(&run(&store, &["ttl", "flow/cli", "node/a"]));
+    assert!(ttl["ttl_ms"].as_u64().is_some_and(|ms| ms <= 60_000));
+
+    let missing = run(&store, &["get", "flow/cli", "node/missing"]);
+    assert!(!missing.status.success());
+
+    assert_eq!(
+        stdout_json(&run(&store, &["del", "flow/cli", "node/a"])),
+        json!({"deleted": true})
+    );
+    assert_eq!(stdout_json(&run(&store, &["ls"])), json!([]));
+
+    std::fs::remove_file(&store).expect("cleanup");
+}
+
+#[test]
+fn purge_user_rewrites_the_file_store() {
+    let store = env::temp_dir().join(format!("greentic-cli-{}.ndjson", Uuid::new_v4()));
+
+    for user in ["bob", "alice"] {
+        let set = run(&store, &["set", "flow/cli", "node/a", "1", "--user", user]);
+        assert!(set.status.success());
+    }
+
+    let report = stdout_json(&run(&store, &["purge", "--user", "bob"]));
+    assert_eq!(report["scope"], "dev:tenant:user=bob");
+    assert_eq!(report["deleted"], 1);
+    assert!(report["completed_at"].is_string());
+
+    let bob = run(&store, &["get", "flow/cli", "node/a", "--user", "bob"]);
+    assert!(!bob.status.success());
+    assert_eq!(
+        stdout_json(&run(
+            &store,
+            &["get", "flow/cli", "node/a", "--user", "alice"]
+        )),
+        json!(1)
+    );
+
+    let both = run(&store, &["purge", "--team", "ops", "--user", "alice"]);
+    assert!(!both.status.success());
+
+    std::fs::remove_file(&store).expect("cleanup");
+}
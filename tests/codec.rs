@@ -0,0 +1,83 @@
+#[cfg(all(feature = "redis", feature = "codecs"))]
+mod redis_codec {
+    use greentic_state::codec::ValueCodec;
+    use greentic_state::redis_store::RedisStateStore;
+    use greentic_state::{StateKey, StatePath, StateStore, TenantCtx};
+    use greentic_types::{EnvId, TenantId};
+    use serde_json::json;
+    use std::env;
+    use uuid::Uuid;
+
+    fn ctx() -> TenantCtx {
+        TenantCtx::new(
+            EnvId::try_from("dev").expect("valid env id"),
+            TenantId::try_from("tenant").expect("valid tenant id"),
+        )
+    }
+
+    fn client() -> Option<redis::Client> {
+        let url = env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url.as_str()).ok()?;
+        client.get_connection().ok()?;
+        Some(client)
+    }
+
+    #[test]
+    fn stores_read_documents_written_with_any_codec() {
+        let Some(client) = client() else {
+            return;
+        };
+        let ctx = ctx();
+        let prefix = format!("flow/codec-{}", Uuid::new_v4());
+        let codecs = [ValueCodec::Json, ValueCodec::MessagePack, ValueCodec::Cbor];
+        let stores: Vec<_> = codecs
+            .iter()
+            .map(|codec| {
+                RedisStateStore::builder(client.clone())
+                    .codec(*codec)
+                    .build()
+                    .expect("store")
+            })
+            .collect();
+
+        for (writer, codec) in stores.iter().zip(codecs) {
+            let key = StateKey::new(format!("{codec:?}"));
+            let doc = json!({"codec": format!("{codec:?}"), "items": [1, 2.5, null]});
+            writer
+                .set_json(&ctx, &prefix, &key, None, &doc, Some(60))
+                .expect("set");
+            for reader in &stores {
+                assert_eq!(
+                    reader.get_json(&ctx, &prefix, &key, None).expect("get"),
+                    Some(doc.clone())
+                );
+            }
+        }
+
+        // A path write re-encodes the document with the writing store's codec.
+        let key = StateKey::new("Json");
+        stores[2]
+            .set_json(
+                &ctx,
+                &prefix,
+                &key,
+                Some(&StatePath::from_pointer("/items/3")),
+                &json!("cbor"),
+                None,
+            )
+            .expect("path set");
+        assert_eq!(
+            stores[0]
+                .get_json(
+                    &ctx,
+                    &prefix,
+                    &key,
+                    Some(&StatePath::from_pointer("/items"))
+                )
+                .expect("get"),
+            Some(json!([1, 2.5, null, "cbor"]))
+        );
+
+        stores[0].del_prefix(&ctx, &prefix).expect("cleanup");
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(all(feature = "redis", feature = "compression"))]
+mod redis_compression {
+    use greentic_state::compress::{CompressionAlgorithm, CompressionConfig};
+    use greentic_state::redis_store::RedisStateStore;
+    use greentic_state::{StateKey, StatePath, StateStore, TenantCtx, fqn};
+    use greentic_types::{EnvId, TenantId};
+    use serde_json::json;
+    use std::env;
+    use uuid::Uuid;
+
+    fn ctx() -> TenantCtx {
+        TenantCtx::new(
+            EnvId::try_from("dev").expect("valid env id"),
+            TenantId::try_from("tenant").expect("valid tenant id"),
+        )
+    }
+
+    fn client() -> Option<redis::Client> {
+        let url = env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url.as_str()).ok()?;
+        client.get_connection().ok()?;
+        Some(client)
+    }
+
+    #[test]
+    fn large_documents_are_compressed_and_plain_values_still_load() {
+        let Some(client) = client() else {
+            return;
+        };
+        let mut conn = client.get_connection().expect("connection");
+        let ctx = ctx();
+        let prefix = format!("flow/compress-{}", Uuid::new_v4());
+
+        for (algorithm, tag) in [
+            (CompressionAlgorithm::Zstd, 1),
+            (CompressionAlgorithm::Lz4, 2),
+        ] {
+            let store = RedisStateStore::builder(client.clone())
+                .compression(Some(CompressionConfig {
+                    algorithm,
+                    threshold_bytes: 256,
+                    ..CompressionConfig::default()
+                }))
+                .build()
+                .expect("store");
+            let key = StateKey::new(format!("large-{tag}"));
+            let doc = json!({"rows": vec!["the same row over and over"; 200]});
+            store
+                .set_json(&ctx, &prefix, &key, None, &doc, Some(60))
+                .expect("set");
+
+            let raw: Vec<u8> = redis::cmd("GET")
+                .arg(fqn(&ctx, &prefix, &key).as_str())
+                .query(&mut conn)
+                .expect("raw get");
+            assert_eq!(raw.first(), Some(&tag));
+            assert!(raw.len() < serde_json::to_vec(&doc).expect("json").len() / 4);
+            assert_eq!(
+                store.get_json(&ctx, &prefix, &key, None).expect("get"),
+                Some(doc)
+            );
+            assert_eq!(
+                store
+                    .get_json(
+                        &ctx,
+                        &prefix,
+                        &key,
+                        Some(&StatePath::from_pointer("/rows/3"))
+                    )
+                    .expect("path get"),
+                Some(json!("the same row over and over"))
+            );
+        }
+
+        let plain = RedisStateStore::new(client.clone());
+        let compressing = RedisStateStore::builder(client)
+            .compression(Some(CompressionConfig {
+                threshold_bytes: 0,
+                ..CompressionConfig::default()
+            }))
+            .build()
+            .expect("store");
+        let legacy = StateKey::new("legacy");
+        plain
+            .set_json(&ctx, &prefix, &legacy, None, &json!({"old": true}), None)
+            .expect("plain set");
+        assert_eq!(
+            compressing
+                .get_json(&ctx, &prefix, &legacy, None)
+                .expect("get legacy"),
+            Some(json!({"old": true}))
+        );
+
+        plain.del_prefix(&ctx, &prefix).expect("cleanup");
+    }
+}
@@ -0,0 +1,52 @@
+#[cfg(feature = "testing")]
+mod in_memory {
+    use greentic_state::inmemory::InMemoryStateStore;
+    use std::sync::Arc;
+
+    greentic_state::state_store_conformance!(
+        clock = |clock| { InMemoryStateStore::new().with_clock(Arc::new(clock)) }
+    );
+}
+
+#[cfg(feature = "testing")]
+mod cached {
+    use greentic_state::cache::{CacheConfig, CachedStateStore};
+    use greentic_state::clock::TestClock;
+    use greentic_state::inmemory::InMemoryStateStore;
+    use std::sync::Arc;
+
+    greentic_state::state_store_conformance!(
+        clock = |clock: TestClock| {
+            let remote = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+            CachedStateStore::new(remote, CacheConfig::default()).with_clock(Arc::new(clock))
+        }
+    );
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn clocked_suite_runs_every_case() {
+    use greentic_state::conformance::run_suite_with_clock;
+    use greentic_state::inmemory::InMemoryStateStore;
+    use std::sync::Arc;
+
+    run_suite_with_clock(|clock| InMemoryStateStore::new().with_clock(Arc::new(clock)));
+}
+
+#[cfg(all(feature = "testing", feature = "redis"))]
+#[test]
+fn redis_store_conforms() {
+    use greentic_state::conformance::run_suite;
+    use greentic_state::redis_store::RedisStateStore;
+
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(client) = redis::Client::open(url.as_str()) else {
+        return;
+    };
+    if client.get_connection().is_err() {
+        return;
+    }
+    run_suite(|| RedisStateStore::new(client.clone()));
+}
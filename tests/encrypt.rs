@@ -0,0 +1,224 @@
+#![cfg(feature = "encryption")]
+
+use greentic_state::encrypt::{
+    Cipher, DataKey, EncryptedEnvelope, EncryptedStateStore, StaticKeyProvider,
+};
+use greentic_state::{
+    StateKey, StatePath, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use serde_json::json;
+use std::sync::Arc;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+fn kid(store: &InMemoryStateStore, key: &StateKey) -> String {
+    let stored = store
+        .get_json(&ctx(), "flow/enc", key, None)
+        .expect("get raw")
+        .expect("present");
+    EncryptedEnvelope::from_value(&stored)
+        .expect("well-formed envelope")
+        .expect("stored value is an envelope")
+        .kid
+}
+
+#[test]
+fn values_and_paths_round_trip_encrypted() {
+    for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+        let keys = StaticKeyProvider::new();
+        keys.rotate(&ctx(), DataKey::generate("k1", cipher));
+        let backend = InMemoryStateStore::new();
+        let store = EncryptedStateStore::new(backend.clone(), Arc::new(keys));
+        let key = StateKey::new("node/a");
+
+        store
+            .set_json(
+                &ctx(),
+                "flow/enc",
+                &key,
+                None,
+                &json!({"card": "4111", "items": [1]}),
+                Some(60),
+            )
+            .expect("set");
+        store
+            .set_json(
+                &ctx(),
+                "flow/enc",
+                &key,
+                Some(&StatePath::from_pointer("/items/1")),
+                &json!(2),
+                None,
+            )
+            .expect("path set");
+
+        let raw = backend
+            .get_json(&ctx(), "flow/enc", &key, None)
+            .expect("get raw")
+            .expect("present");
+        assert!(raw.get("card").is_none(), "plaintext leaked: {raw}");
+        assert_eq!(kid(&backend, &key), "k1");
+
+        assert_eq!(
+            store.get_json(&ctx(), "flow/enc", &key, None).expect("get"),
+            Some(json!({"card": "4111", "items": [1, 2]}))
+        );
+        assert_eq!(
+            store
+                .get_json(
+                    &ctx(),
+                    "flow/enc",
+                    &key,
+                    Some(&StatePath::from_pointer("/items/1"))
+                )
+                .expect("path get"),
+            Some(json!(2))
+        );
+        assert!(matches!(
+            store.ttl(&ctx(), "flow/enc", &key).expect("ttl"),
+            Some(StateTtl::ExpiresIn(_))
+        ));
+    }
+}
+
+#[test]
+fn rotation_reencrypts_on_read() {
+    let keys = StaticKeyProvider::new();
+    keys.rotate(&ctx(), DataKey::generate("k1", Cipher::Aes256Gcm));
+    let backend = InMemoryStateStore::new();
+    let store = EncryptedStateStore::new(backend.clone(), Arc::new(keys.clone()))
+        .with_reencrypt_on_read(true);
+    let (a, b) = (StateKey::new("a"), StateKey::new("b"));
+    for key in [&a, &b] {
+        store
+            .set_json(&ctx(), "flow/enc", key, None, &json!({"v": 1}), Some(60))
+            .expect("set");
+    }
+
+    keys.rotate(&ctx(), DataKey::generate("k2", Cipher::ChaCha20Poly1305));
+    assert_eq!(
+        store.get_json(&ctx(), "flow/enc", &a, None).expect("get"),
+        Some(json!({"v": 1}))
+    );
+    assert_eq!(kid(&backend, &a), "k2");
+    assert!(matches!(
+        backend.ttl(&ctx(), "flow/enc", &a).expect("ttl"),
+        Some(StateTtl::ExpiresIn(_))
+    ));
+    assert_eq!(kid(&backend, &b), "k1");
+
+    assert_eq!(store.reencrypt(&ctx(), None).expect("reencrypt"), 1);
+    assert_eq!(kid(&backend, &b), "k2");
+    assert!(keys.retire(&ctx(), "k1"));
+    assert_eq!(
+        store.get_json(&ctx(), "flow/enc", &b, None).expect("get"),
+        Some(json!({"v": 1}))
+    );
+}
+
+#[test]
+fn reads_leave_stale_documents_alone_by_default() {
+    let keys = StaticKeyProvider::new();
+    keys.rotate(&ctx(), DataKey::generate("k1", Cipher::Aes256Gcm));
+    let backend = InMemoryStateStore::new();
+    let store = EncryptedStateStore::new(backend.clone(), Arc::new(keys.clone()));
+    let key = StateKey::new("a");
+    store
+        .set_json(&ctx(), "flow/enc", &key, None, &json!({"v": 1}), None)
+        .expect("set");
+
+    keys.rotate(&ctx(), DataKey::generate("k2", Cipher::Aes256Gcm));
+    assert_eq!(
+        store.get_json(&ctx(), "flow/enc", &key, None).expect("get"),
+        Some(json!({"v": 1}))
+    );
+    assert_eq!(kid(&backend, &key), "k1", "reads do not write back");
+    assert_eq!(store.reencrypt(&ctx(), None).expect("reencrypt"), 1);
+    assert_eq!(kid(&backend, &key), "k2");
+}
+
+#[test]
+fn plaintext_is_upgraded_and_tampering_is_rejected() {
+    let keys = StaticKeyProvider::new();
+    keys.rotate(&ctx(), DataKey::generate("k1", Cipher::Aes256Gcm));
+    let backend = InMemoryStateStore::new();
+    let store =
+        EncryptedStateStore::new(backend.clone(), Arc::new(keys)).with_reencrypt_on_read(true);
+    let (a, b) = (StateKey::new("a"), StateKey::new("b"));
+
+    backend
+        .set_json(&ctx(), "flow/enc", &a, None, &json!("legacy"), None)
+        .expect("seed plaintext");
+    assert_eq!(
+        store.get_json(&ctx(), "flow/enc", &a, None).expect("get"),
+        Some(json!("legacy"))
+    );
+    assert_eq!(kid(&backend, &a), "k1");
+
+    // An envelope copied to another key fails authentication because the FQN is bound as AAD.
+    let sealed = backend
+        .get_json(&ctx(), "flow/enc", &a, None)
+        .expect("get raw")
+        .expect("present");
+    backend
+        .set_json(&ctx(), "flow/enc", &b, None, &sealed, None)
+        .expect("copy envelope");
+    let err = store
+        .get_json(&ctx(), "flow/enc", &b, None)
+        .expect_err("swapped envelope");
+    assert_eq!(err.code, ErrorCode::Internal);
+}
+
+#[test]
+fn malformed_envelopes_are_errors_not_plaintext() {
+    let keys = StaticKeyProvider::new();
+    keys.rotate(&ctx(), DataKey::generate("k1", Cipher::Aes256Gcm));
+    let backend = InMemoryStateStore::new();
+    let store = EncryptedStateStore::new(backend.clone(), Arc::new(keys));
+    let key = StateKey::new("a");
+
+    for damaged in [
+        json!({"$greentic_enc": 1, "alg": "aes-256-gcm", "kid": "k1", "nonce": "AAAA"}),
+        json!({"$greentic_enc": "1", "alg": "aes-256-gcm", "kid": "k1", "nonce": "", "ct": ""}),
+    ] {
+        assert!(EncryptedEnvelope::from_value(&damaged).is_err());
+        backend
+            .set_json(&ctx(), "flow/enc", &key, None, &damaged, None)
+            .expect("seed");
+        let err = store
+            .get_json(&ctx(), "flow/enc", &key, None)
+            .expect_err("damaged envelope");
+        assert_eq!(err.code, ErrorCode::Internal);
+    }
+
+    let plain = json!({"greentic_enc": 1, "ct": "not an envelope"});
+    assert_eq!(
+        EncryptedEnvelope::from_value(&plain).expect("plaintext"),
+        None
+    );
+}
+
+#[test]
+fn missing_tenant_key_fails_writes() {
+    let store = EncryptedStateStore::new(
+        InMemoryStateStore::new(),
+        Arc::new(StaticKeyProvider::new()),
+    );
+    let err = store
+        .set_json(
+            &ctx(),
+            "flow/enc",
+            &StateKey::new("a"),
+            None,
+            &json!(1),
+            None,
+        )
+        .expect_err("no key");
+    assert_eq!(err.code, ErrorCode::Internal);
+}
@@ -0,0 +1,116 @@
+#[cfg(feature = "testing")]
+mod faulty {
+    use greentic_state::faults::{Fault, FaultOperation, FaultRule, FaultyStateStore};
+    use greentic_state::{StateKey, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+    use greentic_types::{EnvId, ErrorCode, TenantId};
+    use serde_json::json;
+    use std::time::Duration;
+
+    fn ctx() -> TenantCtx {
+        TenantCtx::new(
+            EnvId::try_from("dev").expect("valid env id"),
+            TenantId::try_from("tenant").expect("valid tenant id"),
+        )
+    }
+
+    #[test]
+    fn transient_errors_hit_only_matching_calls() {
+        let store = FaultyStateStore::new(InMemoryStateStore::new(), 7).with_rule(
+            FaultRule::new(Fault::Error(ErrorCode::Unavailable))
+                .on(FaultOperation::Set)
+                .prefix("flow/flaky")
+                .times(2),
+        );
+        let ctx = ctx();
+        let key = StateKey::new("node/a");
+
+        store
+            .set_json(&ctx, "flow/stable", &key, None, &json!(1), None)
+            .expect("other prefix");
+        let attempts: Vec<_> = (0..3)
+            .map(|_| store.set_json(&ctx, "flow/flaky", &key, None, &json!(2), None))
+            .collect();
+        assert_eq!(
+            attempts[0].as_ref().expect_err("first").code,
+            ErrorCode::Unavailable
+        );
+        assert!(attempts[1].is_err());
+        assert!(attempts[2].is_ok(), "rule exhausted after two failures");
+        assert!(
+            store
+                .get_json(&ctx, "flow/flaky", &key, None)
+                .expect("get")
+                .is_some()
+        );
+        assert_eq!(store.injected(), 2);
+    }
+
+    #[test]
+    fn dropped_writes_are_acknowledged_but_lost() {
+        let store = FaultyStateStore::new(InMemoryStateStore::new(), 7)
+            .with_rule(FaultRule::new(Fault::DropWrite).times(1));
+        let ctx = ctx();
+        let key = StateKey::new("node/a");
+
+        store
+            .set_json(&ctx, "flow/drop", &key, None, &json!(1), None)
+            .expect("acknowledged");
+        assert_eq!(
+            store.get_json(&ctx, "flow/drop", &key, None).expect("get"),
+            None
+        );
+    }
+
+    #[test]
+    fn partial_batch_deletes_some_keys_then_fails() {
+        let inner = InMemoryStateStore::new();
+        let ctx = ctx();
+        for name in ["a", "b", "c"] {
+            inner
+                .set_json(
+                    &ctx,
+                    "flow/batch",
+                    &StateKey::new(name),
+                    None,
+                    &json!(1),
+                    None,
+                )
+                .expect("seed");
+        }
+        let store = FaultyStateStore::new(inner, 7)
+            .with_rule(FaultRule::new(Fault::PartialBatch { completed: 2 }).times(1));
+
+        let err = store.del_prefix(&ctx, "flow/batch").expect_err("partial");
+        assert_eq!(err.code, ErrorCode::Unavailable);
+        let left = store.list_keys(&ctx, Some("flow/batch")).expect("list");
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].key.as_str(), "c");
+        assert_eq!(store.del_prefix(&ctx, "flow/batch").expect("retry"), 1);
+    }
+
+    #[test]
+    fn probabilistic_faults_replay_with_the_same_seed() {
+        let outcomes = |seed: u64| -> Vec<bool> {
+            let store = FaultyStateStore::new(InMemoryStateStore::new(), seed)
+                .with_rule(FaultRule::new(Fault::Latency(Duration::from_micros(1))))
+                .with_rule(
+                    FaultRule::new(Fault::Error(ErrorCode::Timeout))
+                        .on(FaultOperation::Get)
+                        .probability(0.5),
+                );
+            (0..64)
+                .map(|_| {
+                    store
+                        .get_json(&ctx(), "flow/p", &StateKey::new("k"), None)
+                        .is_err()
+                })
+                .collect()
+        };
+
+        let first = outcomes(42);
+        assert_eq!(first, outcomes(42));
+        assert_ne!(first, outcomes(43));
+        let failures = first.iter().filter(|failed| **failed).count();
+        assert!((16..=48).contains(&failures), "{failures} failures");
+    }
+}
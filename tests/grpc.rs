@@ -0,0 +1,249 @@
+#![cfg(feature = "grpc")]
+
+use greentic_state::grpc::{GrpcStateStore, StateEventKind, serve};
+use greentic_state::{
+    StateKey, StatePath, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, ErrorCode, TeamId, TenantId, UserId};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+/// Starts a server for `backend` and returns the runtime driving it plus its endpoint.
+fn start(backend: InMemoryStateStore) -> (Runtime, String) {
+    let runtime = Runtime::new().expect("runtime");
+    let listener = runtime
+        .block_on(TcpListener::bind("127.0.0.1:0"))
+        .expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    runtime.spawn(serve(listener, Arc::new(backend)));
+    (runtime, format!("http://{addr}"))
+}
+
+#[test]
+fn grpc_store_roundtrips() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, endpoint) = start(backend.clone());
+    let remote = GrpcStateStore::connect(endpoint).expect("connect");
+    let ctx = ctx();
+    let prefix = "flow/grpc";
+    let key = StateKey::new("node/a");
+
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!({"a": [1, 2]}), Some(60))
+        .expect("set");
+    let path = StatePath::from_pointer("/a/0");
+    remote
+        .set_json(&ctx, prefix, &key, Some(&path), &json!("x"), None)
+        .expect("path set");
+    assert_eq!(
+        backend
+            .get_json(&ctx, prefix, &key, None)
+            .expect("backend get"),
+        Some(json!({"a": ["x", 2]}))
+    );
+    assert_eq!(
+        remote
+            .get_json(&ctx, prefix, &key, Some(&path))
+            .expect("get"),
+        Some(json!("x"))
+    );
+    assert!(matches!(
+        remote.ttl(&ctx, prefix, &key).expect("ttl"),
+        Some(StateTtl::ExpiresIn(remaining)) if remaining.as_secs() <= 60
+    ));
+    assert_eq!(
+        remote
+            .ttl(&ctx, prefix, &StateKey::new("node/missing"))
+            .expect("ttl"),
+        None
+    );
+    assert_eq!(remote.list_keys(&ctx, Some(prefix)).expect("list").len(), 1);
+
+    let err = remote
+        .set_json(
+            &ctx,
+            prefix,
+            &key,
+            Some(&StatePath::from_pointer("/a/0/b")),
+            &json!(1),
+            None,
+        )
+        .expect_err("cannot descend into a string");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+
+    assert!(remote.del(&ctx, prefix, &key).expect("del"));
+    assert!(
+        remote
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .is_none()
+    );
+}
+
+#[test]
+fn scan_streams_entries_with_ttls() {
+    let backend = InMemoryStateStore::new();
+    let ctx = ctx();
+    for index in 0..100 {
+        backend
+            .set_json(
+                &ctx,
+                "flow/scan",
+                &StateKey::new(format!("node/{index:03}")),
+                None,
+                &json!(index),
+                (index == 0).then_some(60),
+            )
+            .expect("seed");
+    }
+    let (_runtime, endpoint) = start(backend);
+    let remote = GrpcStateStore::connect(endpoint).expect("connect");
+
+    let records: Vec<_> = remote
+        .scan(&ctx, Some("flow/scan"))
+        .expect("scan")
+        .collect::<Result<_, _>>()
+        .expect("records");
+    assert_eq!(records.len(), 100);
+    assert_eq!(records[0].key, "node/000");
+    assert!(records[0].ttl_secs.is_some());
+    assert_eq!(records[99].value, json!(99));
+    assert_eq!(records[99].ttl_secs, None);
+}
+
+#[test]
+fn watch_reports_scoped_writes() {
+    let (_runtime, endpoint) = start(InMemoryStateStore::new());
+    let writer = GrpcStateStore::connect(endpoint.clone()).expect("connect writer");
+    let watcher = GrpcStateStore::connect(endpoint).expect("connect watcher");
+    let ctx = ctx();
+    let team = ctx
+        .clone()
+        .with_team(Some(TeamId::try_from("team-1").expect("valid team id")));
+    let key = StateKey::new("node/a");
+
+    let mut events = watcher.watch(&ctx, Some("flow/watch")).expect("watch");
+
+    writer
+        .set_json(&team, "flow/watch", &key, None, &json!("team"), None)
+        .expect("other scope");
+    writer
+        .set_json(&ctx, "flow/other", &key, None, &json!("other"), None)
+        .expect("other prefix");
+    writer
+        .set_json(&ctx, "flow/watch", &key, None, &json!({"n": 1}), None)
+        .expect("set");
+    writer.del(&ctx, "flow/watch", &key).expect("del");
+
+    let set = events.next().expect("set event").expect("decode");
+    assert_eq!(set.kind, StateEventKind::Set);
+    assert_eq!(set.key, Some(key.clone()));
+    assert_eq!(set.value, Some(json!({"n": 1})));
+
+    let deleted = events.next().expect("delete event").expect("decode");
+    assert_eq!(deleted.kind, StateEventKind::Deleted);
+    assert_eq!(deleted.prefix, "flow/watch");
+}
+
+#[test]
+fn remote_set_if_absent_and_purges() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, endpoint) = start(backend.clone());
+    let remote = GrpcStateStore::connect(endpoint).expect("connect");
+    let tenant = ctx();
+    let team_id = TeamId::try_from("team-1").expect("valid team id");
+    let user_id = UserId::try_from("user-1").expect("valid user id");
+    let team = tenant.clone().with_team(Some(team_id.clone()));
+    let user = tenant.clone().with_user(Some(user_id.clone()));
+    let key = StateKey::new("claim");
+
+    assert!(
+        remote
+            .set_if_absent(&tenant, "flow/claims", &key, &json!(1), Some(60))
+            .expect("first claim")
+    );
+    assert!(
+        !remote
+            .set_if_absent(&tenant, "flow/claims", &key, &json!(2), None)
+            .expect("second claim")
+    );
+    assert_eq!(
+        backend
+            .get_json(&tenant, "flow/claims", &key, None)
+            .expect("get"),
+        Some(json!(1))
+    );
+
+    for scope in [&team, &user] {
+        remote
+            .set_json(scope, "flow/claims", &key, None, &json!(3), None)
+            .expect("scoped set");
+    }
+    let report = remote.purge_team(&tenant, &team_id).expect("purge team");
+    assert_eq!(
+        (report.scope.as_str(), report.deleted),
+        ("dev:tenant:team=team-1", 1)
+    );
+    let report = remote.purge_user(&tenant, &user_id).expect("purge user");
+    assert_eq!(
+        (report.scope.as_str(), report.deleted),
+        ("dev:tenant:user=user-1", 1)
+    );
+    let report = remote.purge_tenant(&tenant).expect("purge tenant");
+    assert_eq!((report.scope.as_str(), report.deleted), ("dev:tenant", 1));
+    assert!(backend.list_keys(&tenant, None).expect("list").is_empty());
+}
+
+#[test]
+fn remote_recursive_deletes_and_prefix_stats() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, endpoint) = start(backend.clone());
+    let remote = GrpcStateStore::connect(endpoint).expect("connect");
+    let ctx = ctx();
+    for (prefix, ttl) in [
+        ("flow/tree", Some(60)),
+        ("flow/tree/a", None),
+        ("flow/other", None),
+    ] {
+        remote
+            .set_json(
+                &ctx,
+                prefix,
+                &StateKey::new("k"),
+                None,
+                &json!({"n": 1}),
+                ttl,
+            )
+            .expect("set");
+    }
+
+    let stats = remote.prefix_stats(&ctx, "flow/tree").expect("stats");
+    assert_eq!((stats.keys, stats.persistent), (2, 1));
+    assert!(stats.earliest_expiry.is_some());
+    // Served by the backend itself rather than rebuilt from per-key requests.
+    assert_eq!(
+        stats,
+        backend
+            .prefix_stats(&ctx, "flow/tree")
+            .expect("backend stats")
+    );
+
+    assert_eq!(
+        remote
+            .del_prefix_recursive(&ctx, "flow/tree")
+            .expect("recursive delete"),
+        2
+    );
+    let left = backend.list_keys(&ctx, None).expect("list");
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].prefix, "flow/other");
+}
@@ -0,0 +1,323 @@
+#![cfg(feature = "server")]
+
+use axum::http::request::Parts;
+use greentic_state::http::{RemoteStateStore, TenantResolver, authenticated_router, serve};
+use greentic_state::{
+    StateKey, StatePath, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, ErrorCode, GResult, GreenticError, TeamId, TenantId, UserId};
+use serde_json::json;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+/// Starts a server for `backend` and returns the runtime driving it plus a connected client.
+fn start(backend: InMemoryStateStore) -> (Runtime, RemoteStateStore) {
+    let runtime = Runtime::new().expect("runtime");
+    let listener = runtime
+        .block_on(TcpListener::bind("127.0.0.1:0"))
+        .expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    runtime.spawn(serve(listener, Arc::new(backend)));
+    (runtime, RemoteStateStore::new(format!("http://{addr}")))
+}
+
+/// Grants the bearer token `admin-token` access to the `tenant` tenant, keeping the team and
+/// user named by the headers.
+struct TokenResolver;
+
+impl TenantResolver for TokenResolver {
+    fn resolve(&self, request: &Parts) -> GResult<TenantCtx> {
+        let header = |name: &str| {
+            request
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        if header("authorization") != Some("Bearer admin-token") {
+            return Err(GreenticError::new(
+                ErrorCode::Unauthenticated,
+                "missing or unknown token",
+            ));
+        }
+        if header("x-greentic-tenant") != Some("tenant") {
+            return Err(GreenticError::new(
+                ErrorCode::PermissionDenied,
+                "token does not grant this tenant",
+            ));
+        }
+        let team =
+            header("x-greentic-team").map(|team| TeamId::try_from(team).expect("valid team id"));
+        let user =
+            header("x-greentic-user").map(|user| UserId::try_from(user).expect("valid user id"));
+        Ok(ctx().with_team(team).with_user(user))
+    }
+}
+
+/// Like [`start`], serving the authenticated router with [`TokenResolver`].
+fn start_authenticated(backend: InMemoryStateStore) -> (Runtime, String) {
+    let runtime = Runtime::new().expect("runtime");
+    let listener = runtime
+        .block_on(TcpListener::bind("127.0.0.1:0"))
+        .expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let router = authenticated_router(Arc::new(backend), Arc::new(TokenResolver));
+    runtime.spawn(async move { axum::serve(listener, router).await });
+    (runtime, format!("http://{addr}"))
+}
+
+#[test]
+fn remote_store_roundtrips_through_http() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, remote) = start(backend.clone());
+    let ctx = ctx();
+    let prefix = "flow/http";
+    let key = StateKey::new("node/a");
+
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!({"a": [1, 2]}), Some(60))
+        .expect("set");
+    let path = StatePath::from_pointer("/a/1");
+    remote
+        .set_json(&ctx, prefix, &key, Some(&path), &json!(3), None)
+        .expect("path set");
+
+    assert_eq!(
+        backend
+            .get_json(&ctx, prefix, &key, None)
+            .expect("backend get"),
+        Some(json!({"a": [1, 3]}))
+    );
+    assert_eq!(
+        remote
+            .get_json(&ctx, prefix, &key, Some(&path))
+            .expect("get"),
+        Some(json!(3))
+    );
+    assert!(
+        remote
+            .get_json(&ctx, prefix, &StateKey::new("node/missing"), None)
+            .expect("get missing")
+            .is_none()
+    );
+    assert!(matches!(
+        remote.ttl(&ctx, prefix, &key).expect("ttl"),
+        Some(StateTtl::ExpiresIn(remaining)) if remaining.as_secs() <= 60
+    ));
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!(1), Some(0))
+        .expect("clear ttl");
+    assert_eq!(
+        remote.ttl(&ctx, prefix, &key).expect("ttl"),
+        Some(StateTtl::Persistent)
+    );
+
+    let listed = remote.list_keys(&ctx, None).expect("list");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].key.as_str(), "node/a");
+
+    assert!(remote.del(&ctx, prefix, &key).expect("del"));
+    assert!(!remote.del(&ctx, prefix, &key).expect("second del"));
+    assert_eq!(remote.ttl(&ctx, prefix, &key).expect("ttl"), None);
+}
+
+#[test]
+fn tenant_headers_scope_requests() {
+    let (_runtime, remote) = start(InMemoryStateStore::new());
+    let tenant = ctx();
+    let team = tenant
+        .clone()
+        .with_team(Some(TeamId::try_from("team-1").expect("valid team id")));
+    let key = StateKey::new("node/a");
+
+    remote
+        .set_json(&team, "flow/scoped", &key, None, &json!("team"), None)
+        .expect("team set");
+    remote
+        .set_json(&tenant, "flow/scoped", &key, None, &json!("tenant"), None)
+        .expect("tenant set");
+    assert_eq!(
+        remote
+            .get_json(&team, "flow/scoped", &key, None)
+            .expect("team get"),
+        Some(json!("team"))
+    );
+    assert_eq!(remote.del_prefix(&tenant, "flow/scoped").expect("purge"), 1);
+    assert_eq!(
+        remote
+            .get_json(&team, "flow/scoped", &key, None)
+            .expect("team get"),
+        Some(json!("team"))
+    );
+}
+
+#[test]
+fn server_errors_keep_their_code() {
+    let (_runtime, remote) = start(InMemoryStateStore::new());
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+
+    remote
+        .set_json(&ctx, "flow/errors", &key, None, &json!({"a": 1}), None)
+        .expect("set");
+    let err = remote
+        .set_json(
+            &ctx,
+            "flow/errors",
+            &key,
+            Some(&StatePath::from_pointer("/a/b")),
+            &json!(1),
+            None,
+        )
+        .expect_err("cannot descend into a number");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+
+    let unreachable = RemoteStateStore::new("http://127.0.0.1:9");
+    let err = unreachable
+        .get_json(&ctx, "flow/errors", &key, None)
+        .expect_err("connection refused");
+    assert_eq!(err.code, ErrorCode::Unavailable);
+}
+
+#[test]
+fn remote_set_if_absent_and_purges() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, url) = start_authenticated(backend.clone());
+    let remote = RemoteStateStore::new(url).with_bearer_token("admin-token");
+    let tenant = ctx();
+    let team_id = TeamId::try_from("team-1").expect("valid team id");
+    let user_id = UserId::try_from("user-1").expect("valid user id");
+    let team = tenant.clone().with_team(Some(team_id.clone()));
+    let user = tenant.clone().with_user(Some(user_id.clone()));
+    let key = StateKey::new("claim");
+
+    assert!(
+        remote
+            .set_if_absent(&tenant, "flow/claims", &key, &json!(1), Some(60))
+            .expect("first claim")
+    );
+    assert!(
+        !remote
+            .set_if_absent(&tenant, "flow/claims", &key, &json!(2), None)
+            .expect("second claim")
+    );
+    assert_eq!(
+        backend
+            .get_json(&tenant, "flow/claims", &key, None)
+            .expect("get"),
+        Some(json!(1))
+    );
+
+    for scope in [&team, &user] {
+        remote
+            .set_json(scope, "flow/claims", &key, None, &json!(3), None)
+            .expect("scoped set");
+    }
+    let report = remote.purge_team(&tenant, &team_id).expect("purge team");
+    assert_eq!(
+        (report.scope.as_str(), report.deleted),
+        ("dev:tenant:team=team-1", 1)
+    );
+    let report = remote.purge_user(&tenant, &user_id).expect("purge user");
+    assert_eq!(
+        (report.scope.as_str(), report.deleted),
+        ("dev:tenant:user=user-1", 1)
+    );
+    let report = remote.purge_tenant(&tenant).expect("purge tenant");
+    assert_eq!((report.scope.as_str(), report.deleted), ("dev:tenant", 1));
+    assert!(backend.list_keys(&tenant, None).expect("list").is_empty());
+}
+
+#[test]
+fn remote_recursive_deletes_and_prefix_stats() {
+    let backend = InMemoryStateStore::new();
+    let (_runtime, remote) = start(backend.clone());
+    let ctx = ctx();
+    for (prefix, ttl) in [
+        ("flow/tree", Some(60)),
+        ("flow/tree/a", None),
+        ("flow/other", None),
+    ] {
+        remote
+            .set_json(
+                &ctx,
+                prefix,
+                &StateKey::new("k"),
+                None,
+                &json!({"n": 1}),
+                ttl,
+            )
+            .expect("set");
+    }
+
+    let stats = remote.prefix_stats(&ctx, "flow/tree").expect("stats");
+    assert_eq!((stats.keys, stats.persistent), (2, 1));
+    assert!(stats.earliest_expiry.is_some());
+    // Served by the backend itself rather than rebuilt from per-key requests.
+    assert_eq!(
+        stats,
+        backend
+            .prefix_stats(&ctx, "flow/tree")
+            .expect("backend stats")
+    );
+
+    assert_eq!(
+        remote
+            .del_prefix_recursive(&ctx, "flow/tree")
+            .expect("recursive delete"),
+        2
+    );
+    let left = backend.list_keys(&ctx, None).expect("list");
+    assert_eq!(left.len(), 1);
+    assert_eq!(left[0].prefix, "flow/other");
+}
+
+#[test]
+fn purges_need_a_tenant_resolver() {
+    let backend = InMemoryStateStore::new();
+    let tenant = ctx();
+    let key = StateKey::new("k");
+    backend
+        .set_json(&tenant, "flow/kept", &key, None, &json!(1), None)
+        .expect("seed");
+
+    let (_plain, remote) = start(backend.clone());
+    let err = remote
+        .purge_tenant(&tenant)
+        .expect_err("purges are disabled");
+    assert_eq!(err.code, ErrorCode::PermissionDenied);
+
+    let (_authenticated, url) = start_authenticated(backend.clone());
+    let err = RemoteStateStore::new(url.clone())
+        .purge_tenant(&tenant)
+        .expect_err("no token");
+    assert_eq!(err.code, ErrorCode::Unauthenticated);
+    let other = TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("other").expect("valid tenant id"),
+    );
+    let err = RemoteStateStore::new(url.clone())
+        .with_bearer_token("admin-token")
+        .purge_tenant(&other)
+        .expect_err("token is scoped to one tenant");
+    assert_eq!(err.code, ErrorCode::PermissionDenied);
+
+    assert_eq!(
+        backend
+            .get_json(&tenant, "flow/kept", &key, None)
+            .expect("get"),
+        Some(json!(1))
+    );
+    let report = RemoteStateStore::new(url)
+        .with_bearer_token("admin-token")
+        .purge_tenant(&tenant)
+        .expect("authorized purge");
+    assert_eq!(report.deleted, 1);
+}
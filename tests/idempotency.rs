@@ -0,0 +1,202 @@
+use greentic_state::clock::TestClock;
+use greentic_state::error::internal;
+use greentic_state::idempotency::Idempotency;
+use greentic_state::inmemory::InMemoryStateStore;
+use greentic_state::{StateKey, StateStore, TenantCtx};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use serde_json::json;
+use std::cell::Cell;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+/// `set_if_absent` writes missing and expired keys only, with the requested TTL.
+fn assert_set_if_absent(store: &dyn StateStore, prefix: &str, expire: &dyn Fn()) {
+    let ctx = ctx();
+    let key = StateKey::new("claim");
+
+    assert!(
+        store
+            .set_if_absent(&ctx, prefix, &key, &json!({"v": 1}), Some(1))
+            .expect("first write")
+    );
+    assert!(
+        !store
+            .set_if_absent(&ctx, prefix, &key, &json!({"v": 2}), None)
+            .expect("second write"),
+        "live entries are kept"
+    );
+    assert_eq!(
+        store.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"v": 1}))
+    );
+
+    expire();
+
+    assert!(
+        store
+            .set_if_absent(&ctx, prefix, &key, &json!({"v": 3}), None)
+            .expect("write after expiry"),
+        "expired entries count as absent"
+    );
+    assert_eq!(
+        store.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"v": 3}))
+    );
+    store.del_prefix(&ctx, prefix).expect("cleanup");
+}
+
+#[test]
+fn in_memory_set_if_absent() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    assert_set_if_absent(&store, "flow/claims", &|| {
+        clock.advance(Duration::from_secs(2))
+    });
+}
+
+#[test]
+fn retries_replay_the_stored_result() {
+    let idempotency = Idempotency::new(Arc::new(InMemoryStateStore::new()));
+    let calls = Cell::new(0);
+    let handler = || {
+        calls.set(calls.get() + 1);
+        Ok(json!({"order": 42}))
+    };
+
+    let first = idempotency.run(&ctx(), "evt-1", handler).expect("first");
+    assert!(!first.replayed);
+    let retry = idempotency.run(&ctx(), "evt-1", handler).expect("retry");
+    assert!(retry.replayed);
+    assert_eq!(retry.value, first.value);
+    assert_eq!(calls.get(), 1, "the handler runs once");
+
+    let other = idempotency.run(&ctx(), "evt-2", handler).expect("other");
+    assert!(!other.replayed);
+    assert_eq!(calls.get(), 2);
+
+    assert_eq!(
+        idempotency
+            .lookup::<serde_json::Value>(&ctx(), "evt-1")
+            .expect("lookup"),
+        Some(json!({"order": 42}))
+    );
+    assert!(idempotency.forget(&ctx(), "evt-1").expect("forget"));
+    assert!(
+        !idempotency
+            .run(&ctx(), "evt-1", handler)
+            .expect("rerun")
+            .replayed
+    );
+}
+
+#[test]
+fn concurrent_attempts_conflict_and_failures_release_the_claim() {
+    let idempotency = Idempotency::new(Arc::new(InMemoryStateStore::new()));
+
+    let nested = idempotency
+        .run(&ctx(), "evt-1", || {
+            let err = idempotency
+                .run(&ctx(), "evt-1", || Ok(1))
+                .expect_err("in-flight request is refused");
+            assert_eq!(err.code, ErrorCode::Conflict);
+            Err::<u32, _>(internal("webhook target down"))
+        })
+        .expect_err("handler failure is returned");
+    assert_eq!(nested.code, ErrorCode::Internal);
+
+    let retry = idempotency.run(&ctx(), "evt-1", || Ok(7)).expect("retry");
+    assert_eq!(retry.value, 7);
+    assert!(!retry.replayed, "failed attempts are not recorded");
+}
+
+#[test]
+fn abandoned_claims_expire() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let idempotency = Idempotency::new(Arc::new(store)).pending_ttl(30);
+
+    // The outer handler stands in for an attempt that hangs while holding its claim.
+    idempotency
+        .run(&ctx(), "evt-1", || {
+            clock.advance(Duration::from_secs(10));
+            let err = idempotency
+                .run(&ctx(), "evt-1", || Ok(0))
+                .expect_err("still claimed");
+            assert_eq!(err.code, ErrorCode::Conflict);
+
+            clock.advance(Duration::from_secs(30));
+            let recovered = idempotency.run(&ctx(), "evt-1", || Ok(1))?;
+            assert!(!recovered.replayed, "lapsed claims are taken over");
+            Ok(recovered.value)
+        })
+        .expect("outer attempt");
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_set_if_absent_when_available() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::{env, thread};
+    use uuid::Uuid;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&ctx(), Some("probe")).is_err() {
+        return;
+    }
+    assert_set_if_absent(&store, &format!("flow/claims-{}", Uuid::new_v4()), &|| {
+        thread::sleep(Duration::from_millis(1_200))
+    });
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn lost_claim_replies_are_not_mistaken_for_duplicates() {
+    use greentic_state::faults::{Fault, FaultOperation, FaultRule, FaultyStateStore};
+
+    let clock = TestClock::new();
+    let store = FaultyStateStore::new(
+        InMemoryStateStore::new().with_clock(Arc::new(clock.clone())),
+        1,
+    )
+    .with_rule(
+        FaultRule::new(Fault::ReplyLost)
+            .on(FaultOperation::SetIfAbsent)
+            .times(1),
+    );
+    let idempotency = Idempotency::new(Arc::new(store)).pending_ttl(30);
+    let calls = Cell::new(0);
+    let handler = || {
+        calls.set(calls.get() + 1);
+        Ok(calls.get())
+    };
+
+    let err = idempotency
+        .run(&ctx(), "evt-1", handler)
+        .expect_err("claim outcome unknown");
+    assert_eq!(err.code, ErrorCode::Unavailable);
+    let err = idempotency
+        .run(&ctx(), "evt-1", handler)
+        .expect_err("the landed claim is still pending");
+    assert_eq!(err.code, ErrorCode::Conflict);
+    assert_eq!(calls.get(), 0);
+
+    clock.advance(Duration::from_secs(30));
+    let processed = idempotency.run(&ctx(), "evt-1", handler).expect("retry");
+    assert!(
+        !processed.replayed,
+        "the request is processed, not replayed"
+    );
+    assert_eq!(calls.get(), 1);
+}
@@ -0,0 +1,144 @@
+use greentic_state::instrument::{
+    InstrumentedStateStore, OPERATION_DURATION_SECONDS, OPERATIONS_TOTAL, PAYLOAD_BYTES,
+    READS_TOTAL, TenantLabel,
+};
+use greentic_state::{StateKey, StatePath, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_types::{EnvId, TenantId};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use serde_json::json;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+/// Flattened `(name, sorted labels, value)` view of one recorded metric.
+type Entry = (String, Vec<(String, String)>, DebugValue);
+
+fn recorded(recorder: &DebuggingRecorder) -> Vec<Entry> {
+    recorder
+        .snapshotter()
+        .snapshot()
+        .into_vec()
+        .into_iter()
+        .map(|(key, _, _, value)| {
+            let key = key.key();
+            let mut labels: Vec<_> = key
+                .labels()
+                .map(|label| (label.key().to_owned(), label.value().to_owned()))
+                .collect();
+            labels.sort();
+            (key.name().to_owned(), labels, value)
+        })
+        .collect()
+}
+
+fn counter(entries: &[Entry], name: &str, labels: &[(&str, &str)]) -> u64 {
+    entries
+        .iter()
+        .filter(|(entry, entry_labels, _)| {
+            entry == name
+                && labels.iter().all(|(key, value)| {
+                    entry_labels
+                        .iter()
+                        .any(|(entry_key, entry_value)| entry_key == key && entry_value == value)
+                })
+        })
+        .map(|(_, _, value)| match value {
+            DebugValue::Counter(count) => *count,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn records_operations_reads_and_errors() {
+    let recorder = DebuggingRecorder::new();
+    let store = InstrumentedStateStore::new(InMemoryStateStore::new())
+        .with_tenant_label(TenantLabel::Plain);
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+
+    metrics::with_local_recorder(&recorder, || {
+        store
+            .set_json(&ctx, "flow/metrics", &key, None, &json!({"a": 1}), None)
+            .expect("set");
+        store
+            .get_json(&ctx, "flow/metrics", &key, None)
+            .expect("hit");
+        store
+            .get_json(&ctx, "flow/metrics", &StateKey::new("node/missing"), None)
+            .expect("miss");
+        store
+            .set_json(
+                &ctx,
+                "flow/metrics",
+                &key,
+                Some(&StatePath::from_pointer("/a/b")),
+                &json!(1),
+                None,
+            )
+            .expect_err("cannot descend into a number");
+    });
+
+    let entries = recorded(&recorder);
+    let tenant = ("tenant", "dev:tenant");
+    assert_eq!(
+        counter(
+            &entries,
+            OPERATIONS_TOTAL,
+            &[("op", "get_json"), ("outcome", "ok"), tenant]
+        ),
+        2
+    );
+    assert_eq!(
+        counter(
+            &entries,
+            OPERATIONS_TOTAL,
+            &[("op", "set_json"), ("outcome", "invalid_input")]
+        ),
+        1
+    );
+    assert_eq!(counter(&entries, READS_TOTAL, &[("result", "hit")]), 1);
+    assert_eq!(counter(&entries, READS_TOTAL, &[("result", "miss")]), 1);
+
+    let histogram = |name: &str, op: &str| {
+        entries
+            .iter()
+            .find(|(entry, labels, _)| {
+                entry == name && labels.iter().any(|(key, value)| key == "op" && value == op)
+            })
+            .map(|(_, _, value)| match value {
+                DebugValue::Histogram(samples) => samples.iter().map(|s| s.into_inner()).collect(),
+                _ => Vec::new(),
+            })
+            .unwrap_or_default()
+    };
+    assert_eq!(histogram(OPERATION_DURATION_SECONDS, "set_json").len(), 2);
+    assert_eq!(histogram(PAYLOAD_BYTES, "get_json"), vec![7.0]);
+}
+
+#[test]
+fn hashed_tenant_label_hides_the_tenant() {
+    let recorder = DebuggingRecorder::new();
+    let store = InstrumentedStateStore::new(InMemoryStateStore::new());
+    let ctx = ctx();
+
+    metrics::with_local_recorder(&recorder, || {
+        store
+            .del(&ctx, "flow/metrics", &StateKey::new("node/a"))
+            .expect("del");
+    });
+
+    let entries = recorded(&recorder);
+    let tenant = entries
+        .iter()
+        .find(|(name, _, _)| name == OPERATIONS_TOTAL)
+        .and_then(|(_, labels, _)| labels.iter().find(|(key, _)| key == "tenant"))
+        .map(|(_, value)| value.clone())
+        .expect("tenant label");
+    assert_eq!(tenant.len(), 16);
+    assert!(!tenant.contains("tenant"));
+}
@@ -0,0 +1,172 @@
+use greentic_state::TenantCtx;
+use greentic_state::clock::TestClock;
+use greentic_state::inmemory::InMemoryStateStore;
+use greentic_state::lease::{LeaseStore, MAX_LEASE_TTL};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn tenant(id: &str) -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from(id).expect("valid tenant id"),
+    )
+}
+
+/// Lets `node-a`'s lease lapse through `expire`, then checks that `node-b` takes over with a
+/// greater fencing token and that `node-a` can neither renew nor release it.
+fn assert_takeover(store: &dyn LeaseStore, tenant_id: &str, ttl: Duration, expire: &dyn Fn()) {
+    let ctx = tenant(tenant_id);
+    let name = "session/s1";
+
+    let first = store
+        .acquire_lease(&ctx, name, "node-a", ttl)
+        .expect("acquire")
+        .expect("free lease is granted");
+    assert!(
+        store
+            .acquire_lease(&ctx, name, "node-b", ttl)
+            .expect("acquire")
+            .is_none(),
+        "held lease is refused"
+    );
+    let again = store
+        .acquire_lease(&ctx, name, "node-a", ttl)
+        .expect("acquire")
+        .expect("holder re-acquires");
+    assert_eq!(again.token, first.token, "re-acquiring keeps the token");
+    assert!(
+        store
+            .acquire_lease(&tenant(&format!("{tenant_id}-other")), name, "node-b", ttl)
+            .expect("acquire")
+            .is_some(),
+        "leases are tenant-scoped"
+    );
+
+    expire();
+
+    let second = store
+        .acquire_lease(&ctx, name, "node-b", ttl)
+        .expect("acquire")
+        .expect("expired lease is taken over");
+    assert!(second.token > first.token, "fencing tokens increase");
+    assert_eq!(store.renew_lease(&first, ttl).expect("renew"), None);
+    assert!(!store.release_lease(&first).expect("release"));
+
+    let renewed = store
+        .renew_lease(&second, ttl * 2)
+        .expect("renew")
+        .expect("holder renews");
+    assert_eq!(renewed.token, second.token);
+    assert!(renewed.expires_at >= second.expires_at);
+    assert!(store.release_lease(&renewed).expect("release"));
+    assert!(!store.release_lease(&renewed).expect("release again"));
+
+    let third = store
+        .acquire_lease(&ctx, name, "node-a", ttl)
+        .expect("acquire")
+        .expect("released lease is free");
+    assert!(third.token > second.token);
+    assert!(store.release_lease(&third).expect("cleanup"));
+}
+
+#[test]
+fn in_memory_lease_takeover_after_expiry() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let ttl = Duration::from_secs(30);
+    assert_takeover(&store, "acme", ttl, &|| clock.advance(ttl));
+}
+
+#[test]
+fn renewal_postpones_takeover() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let ctx = tenant("acme");
+    let ttl = Duration::from_secs(10);
+
+    let lease = store
+        .acquire_lease(&ctx, "resume", "node-a", ttl)
+        .expect("acquire")
+        .expect("granted");
+    clock.advance(Duration::from_secs(8));
+    let lease = store
+        .renew_lease(&lease, ttl)
+        .expect("renew")
+        .expect("still held");
+    clock.advance(Duration::from_secs(8));
+    assert!(
+        store
+            .acquire_lease(&ctx, "resume", "node-b", ttl)
+            .expect("acquire")
+            .is_none(),
+        "renewed lease is still held"
+    );
+    clock.advance(Duration::from_secs(2));
+    assert!(
+        store
+            .acquire_lease(&ctx, "resume", "node-b", ttl)
+            .expect("acquire")
+            .is_some()
+    );
+    assert_eq!(store.renew_lease(&lease, ttl).expect("renew"), None);
+}
+
+#[test]
+fn renewing_with_an_oversized_ttl_is_rejected() {
+    let store = InMemoryStateStore::new();
+    let lease = store
+        .acquire_lease(&tenant("acme"), "resume", "node-a", MAX_LEASE_TTL)
+        .expect("acquire")
+        .expect("granted");
+    let err = store
+        .renew_lease(&lease, Duration::from_millis(i64::MAX as u64))
+        .expect_err("rejected");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+    assert!(store.release_lease(&lease).expect("release"));
+}
+
+#[test]
+fn invalid_lease_arguments_are_rejected() {
+    let store = InMemoryStateStore::new();
+    let ctx = tenant("acme");
+    let ttl = Duration::from_secs(1);
+    for (name, owner, ttl) in [
+        ("", "node-a", ttl),
+        ("resume", "", ttl),
+        ("resume", "node-a", Duration::from_micros(10)),
+        ("resume", "node-a", MAX_LEASE_TTL + Duration::from_secs(1)),
+        ("resume", "node-a", Duration::from_millis(i64::MAX as u64)),
+    ] {
+        let err = store
+            .acquire_lease(&ctx, name, owner, ttl)
+            .expect_err("rejected");
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+    }
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_lease_takeover_when_available() {
+    use greentic_state::StateStore;
+    use greentic_state::redis_store::RedisStateStore;
+    use std::{env, thread};
+    use uuid::Uuid;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&tenant("probe"), Some("probe")).is_err() {
+        return;
+    }
+    let ttl = Duration::from_millis(200);
+    assert_takeover(
+        &store,
+        &format!("lease-{}", Uuid::new_v4().simple()),
+        ttl,
+        &|| thread::sleep(ttl + Duration::from_millis(100)),
+    );
+}
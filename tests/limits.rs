@@ -0,0 +1,139 @@
+use greentic_state::limits::StateLimits;
+use greentic_state::{StateKey, StatePath, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use serde_json::{Value, json};
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+fn limits() -> StateLimits {
+    StateLimits {
+        max_document_bytes: 64,
+        max_depth: 3,
+        max_array_len: 10,
+    }
+}
+
+/// Runs every limit violation against `store` and checks the stored document is untouched.
+fn assert_limits_enforced(store: &dyn StateStore, prefix: &str) {
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+    store
+        .set_json(&ctx, prefix, &key, None, &json!({"items": [1]}), None)
+        .expect("set within limits");
+
+    let violations: Vec<(Option<StatePath>, Value)> = vec![
+        (None, json!({"text": "x".repeat(100)})),
+        (None, json!([[[[1]]]])),
+        (Some(StatePath::from_pointer("/items/999999999")), json!(1)),
+        (Some(StatePath::from_pointer("/a/b/c/d")), json!(1)),
+        (Some(StatePath::from_pointer("/items/0")), json!([[1]])),
+        (
+            Some(StatePath::from_pointer("/text")),
+            json!("x".repeat(100)),
+        ),
+        (
+            Some(StatePath::from_pointer("/extra")),
+            json!("x".repeat(55)),
+        ),
+    ];
+    for (path, value) in violations {
+        let err = store
+            .set_json(&ctx, prefix, &key, path.as_ref(), &value, None)
+            .expect_err("limit violation");
+        assert_eq!(err.code, ErrorCode::InvalidInput, "{path:?}: {err:?}");
+    }
+
+    store
+        .set_json(
+            &ctx,
+            prefix,
+            &key,
+            Some(&StatePath::from_pointer("/items/9")),
+            &json!(2),
+            None,
+        )
+        .expect("padding within limits");
+    let stored = store
+        .get_json(&ctx, prefix, &key, None)
+        .expect("get")
+        .expect("present");
+    assert_eq!(stored["items"].as_array().map(Vec::len), Some(10));
+    assert_eq!(stored.as_object().map(|map| map.len()), Some(1));
+}
+
+#[test]
+fn in_memory_store_enforces_limits() {
+    let store = InMemoryStateStore::new().with_limits(limits());
+    assert_limits_enforced(&store, "flow/limits");
+}
+
+#[test]
+fn default_limits_stop_runaway_padding() {
+    let store = InMemoryStateStore::new();
+    let err = store
+        .set_json(
+            &ctx(),
+            "flow/limits",
+            &StateKey::new("node/a"),
+            Some(&StatePath::from_pointer("/999999999")),
+            &json!(1),
+            None,
+        )
+        .expect_err("padding rejected");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_store_enforces_limits() {
+    use greentic_state::redis_store::RedisStateStore;
+
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(client) = redis::Client::open(url.as_str()) else {
+        return;
+    };
+    if client.get_connection().is_err() {
+        return;
+    }
+    let store = RedisStateStore::builder(client)
+        .limits(limits())
+        .build()
+        .expect("store");
+    let prefix = format!("flow/limits-{}", uuid::Uuid::new_v4());
+    assert_limits_enforced(&store, &prefix);
+    store.del_prefix(&ctx(), &prefix).expect("cleanup");
+}
+
+#[cfg(feature = "encryption")]
+#[test]
+fn encrypted_store_enforces_limits_on_plaintext() {
+    use greentic_state::encrypt::{Cipher, DataKey, EncryptedStateStore, StaticKeyProvider};
+    use std::sync::Arc;
+
+    let keys = StaticKeyProvider::new();
+    keys.rotate(&ctx(), DataKey::generate("k1", Cipher::Aes256Gcm));
+    let backend = InMemoryStateStore::new().with_limits(StateLimits::unlimited());
+    let store = EncryptedStateStore::new(backend, Arc::new(keys)).with_limits(limits());
+    assert_limits_enforced(&store, "flow/limits");
+
+    // The envelope is flat, so only the plaintext check catches deep documents.
+    let store = EncryptedStateStore::new(
+        InMemoryStateStore::new(),
+        Arc::new(StaticKeyProvider::new()),
+    );
+    let mut deep = json!(1);
+    for _ in 0..200 {
+        deep = json!([deep]);
+    }
+    let err = store
+        .set_if_absent(&ctx(), "flow/limits", &StateKey::new("deep"), &deep, None)
+        .expect_err("too deep");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+}
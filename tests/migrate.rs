@@ -0,0 +1,252 @@
+use greentic_state::migrate::{MigrationCheckpoint, MigrationOptions, MigrationReport, migrate};
+use greentic_state::{
+    StateKey, StatePath, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, ErrorCode, GResult, TenantId};
+use serde_json::{Value, json};
+use std::env;
+use uuid::Uuid;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+fn seeded_source() -> InMemoryStateStore {
+    let store = InMemoryStateStore::new();
+    let ctx = ctx();
+    for (prefix, key, ttl) in [
+        ("flow/a", "node/1", Some(300)),
+        ("flow/a", "node/2", None),
+        ("flow/b", "node/1", None),
+        ("flow/b", "node/2", None),
+    ] {
+        store
+            .set_json(
+                &ctx,
+                prefix,
+                &StateKey::new(key),
+                None,
+                &json!({"from": format!("{prefix}:{key}")}),
+                ttl,
+            )
+            .expect("seed");
+    }
+    store
+}
+
+/// Target that silently drops writes for one key, to exercise verification.
+struct LossyTarget {
+    inner: InMemoryStateStore,
+    dropped: &'static str,
+}
+
+impl StateStore for LossyTarget {
+    fn get_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+    ) -> GResult<Option<Value>> {
+        self.inner.get_json(tenant, prefix, key, path)
+    }
+
+    fn set_json(
+        &self,
+        tenant: &TenantCtx,
+        prefix: &str,
+        key: &StateKey,
+        path: Option<&StatePath>,
+        value: &Value,
+        ttl_secs: Option<u32>,
+    ) -> GResult<()> {
+        if key.as_str() == self.dropped {
+            return Ok(());
+        }
+        self.inner
+            .set_json(tenant, prefix, key, path, value, ttl_secs)
+    }
+
+    fn del(&self, tenant: &TenantCtx, prefix: &str, key: &StateKey) -> GResult<bool> {
+        self.inner.del(tenant, prefix, key)
+    }
+
+    fn del_prefix(&self, tenant: &TenantCtx, prefix: &str) -> GResult<u64> {
+        self.inner.del_prefix(tenant, prefix)
+    }
+}
+
+#[test]
+fn copies_values_and_ttls() {
+    let source = seeded_source();
+    let target = InMemoryStateStore::new();
+    let ctx = ctx();
+
+    let report = migrate(&source, &target, &ctx, &MigrationOptions::default()).expect("migrate");
+    assert_eq!(
+        report,
+        MigrationReport {
+            scanned: 4,
+            copied: 4,
+            verified: 4,
+            ..MigrationReport::default()
+        }
+    );
+    assert_eq!(
+        target
+            .get_json(&ctx, "flow/b", &StateKey::new("node/2"), None)
+            .expect("get"),
+        Some(json!({"from": "flow/b:node/2"}))
+    );
+    assert!(matches!(
+        target.ttl(&ctx, "flow/a", &StateKey::new("node/1")).expect("ttl"),
+        Some(StateTtl::ExpiresIn(remaining)) if remaining.as_secs() <= 300
+    ));
+    assert_eq!(
+        target
+            .ttl(&ctx, "flow/a", &StateKey::new("node/2"))
+            .expect("ttl"),
+        Some(StateTtl::Persistent)
+    );
+}
+
+#[test]
+fn dry_run_leaves_target_untouched() {
+    let source = seeded_source();
+    let target = InMemoryStateStore::new();
+    let ctx = ctx();
+    let options = MigrationOptions {
+        prefix: Some("flow/a".into()),
+        dry_run: true,
+        ..MigrationOptions::default()
+    };
+
+    let report = migrate(&source, &target, &ctx, &options).expect("dry run");
+    assert!(report.dry_run);
+    assert_eq!((report.scanned, report.copied), (2, 2));
+    assert!(target.list_keys(&ctx, None).expect("list").is_empty());
+}
+
+#[test]
+fn resumes_after_checkpoint() {
+    let source = seeded_source();
+    let target = InMemoryStateStore::new();
+    let ctx = ctx();
+    let checkpoint = env::temp_dir().join(format!("greentic-migrate-{}.json", Uuid::new_v4()));
+    MigrationCheckpoint {
+        tenant: "dev:tenant".into(),
+        filter: None,
+        source: None,
+        prefix: "flow/a".into(),
+        key: "node/2".into(),
+    }
+    .save(&checkpoint)
+    .expect("save checkpoint");
+
+    let options = MigrationOptions {
+        checkpoint: Some(checkpoint.clone()),
+        verify: false,
+        ..MigrationOptions::default()
+    };
+    let report = migrate(&source, &target, &ctx, &options).expect("resume");
+    assert_eq!((report.resumed, report.copied), (2, 2));
+    assert!(
+        target
+            .get_json(&ctx, "flow/a", &StateKey::new("node/1"), None)
+            .expect("get")
+            .is_none()
+    );
+    assert!(
+        MigrationCheckpoint::load(&checkpoint)
+            .expect("load")
+            .is_none(),
+        "checkpoint is removed after a clean run"
+    );
+}
+
+#[test]
+fn checkpoints_of_other_runs_are_refused() {
+    let source = seeded_source();
+    let target = InMemoryStateStore::new();
+    let checkpoint = env::temp_dir().join(format!("greentic-migrate-{}.json", Uuid::new_v4()));
+    MigrationCheckpoint {
+        tenant: "dev:tenant".into(),
+        filter: Some("flow/a".into()),
+        source: Some("redis://old:6379/".into()),
+        prefix: "flow/a".into(),
+        key: "node/2".into(),
+    }
+    .save(&checkpoint)
+    .expect("save checkpoint");
+
+    let other = TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("other").expect("valid tenant id"),
+    );
+    let run = MigrationOptions {
+        prefix: Some("flow/a".into()),
+        source: Some("redis://old:6379/".into()),
+        checkpoint: Some(checkpoint.clone()),
+        ..MigrationOptions::default()
+    };
+    for (tenant, options) in [
+        (&other, run.clone()),
+        (
+            &ctx(),
+            MigrationOptions {
+                prefix: None,
+                ..run.clone()
+            },
+        ),
+        (
+            &ctx(),
+            MigrationOptions {
+                source: Some("redis://new:6379/".into()),
+                ..run.clone()
+            },
+        ),
+    ] {
+        let err = migrate(&source, &target, tenant, &options).expect_err("refused");
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+    }
+    assert!(target.list_keys(&ctx(), None).expect("list").is_empty());
+
+    let report = migrate(&source, &target, &ctx(), &run).expect("matching run resumes");
+    assert_eq!((report.resumed, report.copied), (2, 0));
+}
+
+#[test]
+fn verification_reports_mismatches() {
+    let source = seeded_source();
+    let target = LossyTarget {
+        inner: InMemoryStateStore::new(),
+        dropped: "node/2",
+    };
+    let ctx = ctx();
+    let checkpoint = env::temp_dir().join(format!("greentic-migrate-{}.json", Uuid::new_v4()));
+    let options = MigrationOptions {
+        checkpoint: Some(checkpoint.clone()),
+        checkpoint_every: 1,
+        ..MigrationOptions::default()
+    };
+
+    let report = migrate(&source, &target, &ctx, &options).expect("migrate");
+    assert!(!report.is_consistent());
+    assert_eq!(report.verified, 2);
+    assert_eq!(report.mismatches, vec!["flow/a:node/2", "flow/b:node/2"]);
+    assert_eq!(
+        MigrationCheckpoint::load(&checkpoint).expect("load"),
+        Some(MigrationCheckpoint {
+            tenant: "dev:tenant".into(),
+            filter: None,
+            source: None,
+            prefix: "flow/b".into(),
+            key: "node/2".into(),
+        }),
+        "checkpoint is kept when verification fails"
+    );
+    std::fs::remove_file(&checkpoint).expect("cleanup");
+}
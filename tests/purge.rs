@@ -0,0 +1,244 @@
+use greentic_state::{StateKey, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_types::{EnvId, TeamId, TenantId, UserId};
+use serde_json::json;
+#[cfg(feature = "redis")]
+use uuid::Uuid;
+
+fn tenant(id: &str) -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from(id).expect("valid tenant id"),
+    )
+}
+
+fn team(id: &str) -> TeamId {
+    TeamId::try_from(id).expect("valid team id")
+}
+
+fn user(id: &str) -> UserId {
+    UserId::try_from(id).expect("valid user id")
+}
+
+fn exists(store: &dyn StateStore, ctx: &TenantCtx, prefix: &str) -> bool {
+    store
+        .get_json(ctx, prefix, &StateKey::new("node/a"), None)
+        .expect("get")
+        .is_some()
+}
+
+/// Seeds team, user and tenant-level entries (including a tenant-level prefix named like the
+/// team), then purges a user, a team and finally the whole tenant.
+fn assert_purges(store: &dyn StateStore, tenant_id: &str) {
+    let base = tenant(tenant_id);
+    let ops = base.clone().with_team(Some(team("ops")));
+    let ops_bob = ops.clone().with_user(Some(user("bob")));
+    let ops_alice = ops.clone().with_user(Some(user("alice")));
+    let bob = base.clone().with_user(Some(user("bob")));
+    let sales = base.clone().with_team(Some(team("sales")));
+    let sales_bob = sales.clone().with_user(Some(user("bob")));
+    let neighbour = tenant(&format!("{tenant_id}-other")).with_team(Some(team("ops")));
+
+    let seeded = [
+        (&base, "ops"),
+        (&base, "flow/a"),
+        (&ops, "flow/a"),
+        (&ops_bob, "flow/a"),
+        (&ops_bob, "flow/b"),
+        (&ops_alice, "flow/a"),
+        (&bob, "flow/a"),
+        (&sales, "flow/a"),
+        (&sales_bob, "flow/a"),
+        (&neighbour, "flow/a"),
+    ];
+    for (ctx, prefix) in seeded {
+        store
+            .set_json(
+                ctx,
+                prefix,
+                &StateKey::new("node/a"),
+                None,
+                &json!({"seeded": prefix}),
+                Some(600),
+            )
+            .expect("seed");
+    }
+
+    let report = store.purge_user(&ops, &user("bob")).expect("purge user");
+    assert_eq!(report.scope, format!("dev:{tenant_id}:user=bob"));
+    assert_eq!(report.deleted, 4);
+    for (ctx, prefix) in [
+        (&ops_bob, "flow/a"),
+        (&bob, "flow/a"),
+        (&sales_bob, "flow/a"),
+    ] {
+        assert!(!exists(store, ctx, prefix), "user entries are purged");
+    }
+    assert!(exists(store, &ops_alice, "flow/a"));
+    assert!(exists(store, &ops, "flow/a"));
+
+    let report = store.purge_team(&base, &team("ops")).expect("purge team");
+    assert_eq!(report.scope, format!("dev:{tenant_id}:team=ops"));
+    assert_eq!(report.deleted, 2);
+    assert!(!exists(store, &ops, "flow/a"));
+    assert!(!exists(store, &ops_alice, "flow/a"));
+    assert!(
+        exists(store, &base, "ops"),
+        "tenant-level prefixes named like the team are kept"
+    );
+    assert!(exists(store, &sales, "flow/a"));
+    assert!(
+        exists(store, &neighbour, "flow/a"),
+        "other tenants are kept"
+    );
+
+    let report = store.purge_tenant(&sales).expect("purge tenant");
+    assert_eq!(report.scope, format!("dev:{tenant_id}"));
+    assert_eq!(report.deleted, 3);
+    for (ctx, prefix) in [(&base, "ops"), (&base, "flow/a"), (&sales, "flow/a")] {
+        assert!(!exists(store, ctx, prefix), "tenant entries are purged");
+    }
+    assert!(
+        exists(store, &neighbour, "flow/a"),
+        "other tenants are kept"
+    );
+
+    assert_eq!(store.purge_tenant(&base).expect("purge again").deleted, 0);
+    store.purge_tenant(&neighbour).expect("cleanup");
+}
+
+#[test]
+fn in_memory_purges() {
+    assert_purges(&InMemoryStateStore::new(), "acme");
+}
+
+#[test]
+fn cached_purges_invalidate_local_entries() {
+    use greentic_state::cache::{CacheConfig, CachedStateStore};
+
+    let store = CachedStateStore::new(InMemoryStateStore::new(), CacheConfig::default());
+    let ctx = tenant("acme").with_user(Some(user("bob")));
+    let key = StateKey::new("node/a");
+    store
+        .set_json(&ctx, "flow/a", &key, None, &json!(1), Some(600))
+        .expect("set");
+    assert!(exists(&store, &ctx, "flow/a"), "warms the local cache");
+
+    assert_eq!(
+        store.purge_user(&ctx, &user("bob")).expect("purge").deleted,
+        1
+    );
+    assert!(!exists(&store, &ctx, "flow/a"));
+}
+
+#[test]
+fn report_serializes_scope_count_and_timestamp() {
+    let store = InMemoryStateStore::new();
+    let report = store.purge_tenant(&tenant("acme")).expect("purge");
+    let rendered = serde_json::to_value(&report).expect("serialize");
+    assert_eq!(rendered["scope"], "dev:acme");
+    assert_eq!(rendered["deleted"], 0);
+    assert!(rendered["completed_at"].is_string());
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_purges_when_available() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&tenant("probe"), Some("probe")).is_err() {
+        return;
+    }
+    assert_purges(&store, &format!("purge-{}", Uuid::new_v4().simple()));
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_backfilled_entries_are_purged_when_available() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&tenant("probe"), Some("probe")).is_err() {
+        return;
+    }
+    let Ok(mut conn) = redis::Client::open(url.as_str()).and_then(|c| c.get_connection()) else {
+        return;
+    };
+    let tenant_id = format!("purge-{}", Uuid::new_v4().simple());
+    let base = tenant(&tenant_id);
+    let ops = base.clone().with_team(Some(team("ops")));
+    let ops_bob = ops.clone().with_user(Some(user("bob")));
+    let seeded = [(&base, "ops"), (&ops, "flow/a"), (&ops_bob, "flow/a")];
+    for (ctx, prefix) in seeded {
+        store
+            .set_json(ctx, prefix, &StateKey::new("node/a"), None, &json!(1), None)
+            .expect("seed");
+    }
+    // A tenant-level key that reads like a user-scoped FQN must survive user purges.
+    store
+        .set_json(
+            &base,
+            "flow",
+            &StateKey::new("bob:x:y"),
+            None,
+            &json!(1),
+            None,
+        )
+        .expect("seed");
+    // Stand in for entries written before the purge indexes existed.
+    let indexes: Vec<String> = redis::cmd("KEYS")
+        .arg(format!("greentic:state-index:dev:{tenant_id}:*"))
+        .query(&mut conn)
+        .expect("keys");
+    let _: i64 = redis::cmd("DEL")
+        .arg(&indexes)
+        .query(&mut conn)
+        .expect("drop indexes");
+
+    let report = store.purge_team(&base, &team("ops")).expect("purge team");
+    assert_eq!(report.deleted, 0, "unindexed entries are left alone");
+    assert_eq!(
+        store
+            .backfill_purge_index(&ops, "flow/a")
+            .expect("backfill"),
+        1
+    );
+    assert_eq!(
+        store
+            .backfill_purge_index(&ops_bob, "flow/a")
+            .expect("backfill"),
+        1
+    );
+    let err = store
+        .backfill_purge_index(&base, "ops")
+        .expect_err("tenant scopes have no index");
+    assert_eq!(err.code, greentic_types::ErrorCode::InvalidInput);
+
+    let report = store.purge_user(&base, &user("bob")).expect("purge user");
+    assert_eq!(report.deleted, 1);
+    assert!(!exists(&store, &ops_bob, "flow/a"));
+    let report = store.purge_team(&base, &team("ops")).expect("purge team");
+    assert_eq!(report.deleted, 1);
+    assert!(!exists(&store, &ops, "flow/a"));
+    assert!(exists(&store, &base, "ops"));
+    assert!(
+        store
+            .get_json(&base, "flow", &StateKey::new("bob:x:y"), None)
+            .expect("get")
+            .is_some(),
+        "tenant-level keys are never purged with a user"
+    );
+    store.purge_tenant(&base).expect("cleanup");
+}
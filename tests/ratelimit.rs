@@ -0,0 +1,245 @@
+use greentic_state::TenantCtx;
+use greentic_state::clock::TestClock;
+use greentic_state::inmemory::InMemoryStateStore;
+use greentic_state::ratelimit::{MAX_RATE_PERIOD, RateLimit, RateLimitStore, RateLimiter};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn tenant(id: &str) -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from(id).expect("valid tenant id"),
+    )
+}
+
+/// Exhausts a 3-per-window allowance, lets `advance` move past the window and checks that the
+/// allowance is restored and that tenants do not share usage.
+fn assert_window(store: &dyn RateLimitStore, tenant_id: &str, advance: &dyn Fn(Duration)) {
+    let ctx = tenant(tenant_id);
+    let limit = RateLimit::sliding_window(3, Duration::from_millis(500));
+
+    let first = store.check_rate(&ctx, "api", &limit, 2).expect("check");
+    assert!(first.allowed);
+    assert_eq!(first.remaining, 1);
+    assert!(
+        store
+            .check_rate(&ctx, "api", &limit, 1)
+            .expect("check")
+            .allowed
+    );
+    let denied = store.check_rate(&ctx, "api", &limit, 1).expect("check");
+    assert!(!denied.allowed);
+    assert_eq!(denied.remaining, 0);
+    let retry_after = denied.retry_after.expect("retry hint");
+    assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_millis(500));
+    assert!(
+        store
+            .check_rate(&tenant(&format!("{tenant_id}-other")), "api", &limit, 3)
+            .expect("check")
+            .allowed,
+        "usage is tenant-scoped"
+    );
+
+    advance(retry_after);
+
+    let restored = store.check_rate(&ctx, "api", &limit, 3).expect("check");
+    assert!(restored.allowed, "expired grants leave the window");
+    assert!(store.reset_rate(&ctx, "api").expect("reset"));
+    assert!(
+        store
+            .check_rate(&ctx, "api", &limit, 3)
+            .expect("check")
+            .allowed
+    );
+    store.reset_rate(&ctx, "api").expect("cleanup");
+}
+
+/// Records usage under a larger limit, then checks the key against a smaller one.
+fn assert_shrinking(store: &dyn RateLimitStore, tenant_id: &str) {
+    let ctx = tenant(tenant_id);
+    let second = Duration::from_secs(1);
+
+    let wide = RateLimit::sliding_window(5, second);
+    assert!(
+        store
+            .check_rate(&ctx, "w", &wide, 4)
+            .expect("check")
+            .allowed
+    );
+    let narrow = store
+        .check_rate(&ctx, "w", &RateLimit::sliding_window(2, second), 1)
+        .expect("check");
+    assert!(!narrow.allowed);
+    assert_eq!(narrow.remaining, 0);
+    assert!(narrow.retry_after.is_some_and(|retry| retry <= second));
+
+    let large = RateLimit::token_bucket(10, 1, second);
+    assert!(
+        store
+            .check_rate(&ctx, "b", &large, 1)
+            .expect("check")
+            .allowed
+    );
+    let small = RateLimit::token_bucket(3, 1, second);
+    let capped = store.check_rate(&ctx, "b", &small, 1).expect("check");
+    assert!(capped.allowed);
+    assert_eq!(capped.remaining, 2, "tokens are capped at the new capacity");
+
+    store.reset_rate(&ctx, "w").expect("cleanup");
+    store.reset_rate(&ctx, "b").expect("cleanup");
+}
+
+#[test]
+fn in_memory_sliding_window() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    assert_window(&store, "acme", &|by| clock.advance(by));
+}
+
+#[test]
+fn in_memory_shrinking_a_limit_keeps_existing_usage() {
+    let store = InMemoryStateStore::new().with_clock(Arc::new(TestClock::new()));
+    assert_shrinking(&store, "acme");
+}
+
+#[test]
+fn token_bucket_allows_bursts_and_refills_over_time() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let limiter = RateLimiter::new(
+        Arc::new(store),
+        RateLimit::token_bucket(5, 1, Duration::from_secs(2)),
+    );
+    let ctx = tenant("acme");
+
+    for expected in (0..5).rev() {
+        let decision = limiter.check(&ctx, "user/u1").expect("check");
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, expected);
+    }
+    let denied = limiter.check(&ctx, "user/u1").expect("check");
+    assert!(!denied.allowed);
+    assert_eq!(denied.retry_after, Some(Duration::from_secs(2)));
+    let err = limiter.enforce(&ctx, "user/u1").expect_err("limited");
+    assert_eq!(err.code, ErrorCode::RateLimited);
+    assert!(limiter.check(&ctx, "user/u2").expect("check").allowed);
+
+    clock.advance(Duration::from_secs(3));
+    assert!(limiter.check(&ctx, "user/u1").expect("check").allowed);
+    assert!(
+        !limiter.check(&ctx, "user/u1").expect("check").allowed,
+        "a partial token is not spendable"
+    );
+
+    clock.advance(Duration::from_secs(60));
+    let refilled = limiter.check_cost(&ctx, "user/u1", 5).expect("check");
+    assert!(refilled.allowed, "refill is capped at the capacity");
+    assert_eq!(refilled.remaining, 0);
+}
+
+#[test]
+fn idle_limiters_are_forgotten() {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let ctx = tenant("acme");
+    let bucket = RateLimit::token_bucket(2, 2, Duration::from_secs(1));
+
+    assert!(
+        store
+            .check_rate(&ctx, "k", &bucket, 1)
+            .expect("check")
+            .allowed
+    );
+    clock.advance(Duration::from_millis(500));
+    assert!(
+        !store.reset_rate(&ctx, "k").expect("reset"),
+        "a refilled bucket holds no usage"
+    );
+
+    let window = RateLimit::sliding_window(1, Duration::from_secs(1));
+    assert!(
+        store
+            .check_rate(&ctx, "k", &window, 1)
+            .expect("check")
+            .allowed
+    );
+    assert!(
+        store
+            .check_rate(&ctx, "k", &bucket, 2)
+            .expect("check")
+            .allowed,
+        "switching the kind of limit starts afresh"
+    );
+}
+
+#[test]
+fn year_long_limits_do_not_overflow() {
+    let store = InMemoryStateStore::new();
+    let ctx = tenant("acme");
+    for limit in [
+        RateLimit::sliding_window(1, MAX_RATE_PERIOD),
+        RateLimit::token_bucket(u32::MAX, u32::MAX, MAX_RATE_PERIOD),
+    ] {
+        assert!(
+            store
+                .check_rate(&ctx, "yearly", &limit, 1)
+                .expect("check")
+                .allowed
+        );
+    }
+}
+
+#[test]
+fn invalid_rate_limits_are_rejected() {
+    let store = InMemoryStateStore::new();
+    let ctx = tenant("acme");
+    let second = Duration::from_secs(1);
+    for (key, limit, cost) in [
+        ("", RateLimit::sliding_window(1, second), 1),
+        ("k", RateLimit::sliding_window(0, second), 0),
+        ("k", RateLimit::sliding_window(1, Duration::ZERO), 1),
+        ("k", RateLimit::sliding_window(2, second), 3),
+        ("k", RateLimit::token_bucket(1, 0, second), 1),
+        (
+            "k",
+            RateLimit::sliding_window(1, MAX_RATE_PERIOD + second),
+            1,
+        ),
+        ("k", RateLimit::token_bucket(2, 1, MAX_RATE_PERIOD), 1),
+        (
+            "k",
+            RateLimit::token_bucket(1, 1, Duration::from_micros(10)),
+            1,
+        ),
+    ] {
+        let err = store
+            .check_rate(&ctx, key, &limit, cost)
+            .expect_err("rejected");
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+    }
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_rate_limits_when_available() {
+    use greentic_state::StateStore;
+    use greentic_state::redis_store::RedisStateStore;
+    use std::{env, thread};
+    use uuid::Uuid;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&tenant("probe"), Some("probe")).is_err() {
+        return;
+    }
+    let tenant_id = format!("rate-{}", Uuid::new_v4().simple());
+    assert_window(&store, &tenant_id, &|by| {
+        thread::sleep(by + Duration::from_millis(20))
+    });
+    assert_shrinking(&store, &tenant_id);
+}
@@ -0,0 +1,184 @@
+#[cfg(feature = "redis")]
+mod redis_json {
+    use greentic_state::redis_store::{JsonModuleMode, RedisStateStore};
+    use greentic_state::{StateKey, StatePath, StateStore, TenantCtx};
+    use greentic_types::{EnvId, TenantId};
+    use serde_json::json;
+    use std::env;
+    use std::time::Duration;
+    use tokio::time::sleep;
+    use uuid::Uuid;
+
+    fn ctx() -> TenantCtx {
+        TenantCtx::new(
+            EnvId::try_from("dev").expect("valid env id"),
+            TenantId::try_from("tenant").expect("valid tenant id"),
+        )
+    }
+
+    /// Returns a JSON-mode store, or `None` when Redis or the RedisJSON module is unavailable.
+    fn json_store() -> Option<RedisStateStore> {
+        let url = env::var("REDIS_URL").ok()?;
+        let client = redis::Client::open(url.as_str()).ok()?;
+        let store = RedisStateStore::builder(client)
+            .json_module(JsonModuleMode::Auto)
+            .build()
+            .ok()?;
+        store.json_module_active().ok()?.then_some(store)
+    }
+
+    #[test]
+    fn json_mode_roundtrip_and_paths() {
+        let Some(store) = json_store() else {
+            return;
+        };
+        let ctx = ctx();
+        let prefix = format!("flow/redis-json-{}", Uuid::new_v4());
+        let key = StateKey::new("node/a");
+
+        let doc = json!({"a": [1, 2, 3], "status": "ready", "map": {"0": "zero"}});
+        store
+            .set_json(&ctx, &prefix, &key, None, &doc, Some(60))
+            .expect("set");
+        let loaded = store.get_json(&ctx, &prefix, &key, None).expect("get");
+        assert_eq!(loaded, Some(doc));
+
+        let index = StatePath::from_pointer("/a/1");
+        assert_eq!(
+            store
+                .get_json(&ctx, &prefix, &key, Some(&index))
+                .expect("get index"),
+            Some(json!(2))
+        );
+        let object_digit = StatePath::from_pointer("/map/0");
+        assert_eq!(
+            store
+                .get_json(&ctx, &prefix, &key, Some(&object_digit))
+                .expect("get object digit"),
+            Some(json!("zero"))
+        );
+        let missing = StatePath::from_pointer("/a/7");
+        assert_eq!(
+            store
+                .get_json(&ctx, &prefix, &key, Some(&missing))
+                .expect("get missing"),
+            None
+        );
+
+        store
+            .set_json(&ctx, &prefix, &key, Some(&index), &json!(42), None)
+            .expect("in-place set");
+        store
+            .set_json(
+                &ctx,
+                &prefix,
+                &key,
+                Some(&StatePath::from_pointer("/a/5")),
+                &json!("padded"),
+                None,
+            )
+            .expect("padded set");
+        store
+            .set_json(
+                &ctx,
+                &prefix,
+                &key,
+                Some(&StatePath::from_pointer("/nested/deep/value")),
+                &json!(true),
+                None,
+            )
+            .expect("nested set");
+
+        let updated = store
+            .get_json(&ctx, &prefix, &key, None)
+            .expect("get")
+            .expect("value");
+        assert_eq!(
+            updated,
+            json!({
+                "a": [1, 42, 3, null, null, "padded"],
+                "status": "ready",
+                "map": {"0": "zero"},
+                "nested": {"deep": {"value": true}}
+            })
+        );
+
+        let err = store
+            .set_json(
+                &ctx,
+                &prefix,
+                &key,
+                Some(&StatePath::from_pointer("/status/inner")),
+                &json!(1),
+                None,
+            )
+            .expect_err("non-container segment");
+        assert_eq!(err.code, greentic_types::ErrorCode::InvalidInput);
+
+        store.del_prefix(&ctx, &prefix).expect("cleanup");
+    }
+
+    #[test]
+    fn json_mode_reads_and_converts_string_values() {
+        let Some(json_store) = json_store() else {
+            return;
+        };
+        let Ok(url) = env::var("REDIS_URL") else {
+            return;
+        };
+        let string_store = RedisStateStore::from_url(&url).expect("string store");
+        let ctx = ctx();
+        let prefix = format!("flow/redis-json-legacy-{}", Uuid::new_v4());
+        let key = StateKey::new("node/legacy");
+
+        string_store
+            .set_json(&ctx, &prefix, &key, None, &json!({"legacy": [1]}), Some(60))
+            .expect("legacy set");
+        let path = StatePath::from_pointer("/legacy/0");
+        assert_eq!(
+            json_store
+                .get_json(&ctx, &prefix, &key, Some(&path))
+                .expect("legacy get"),
+            Some(json!(1))
+        );
+
+        json_store
+            .set_json(&ctx, &prefix, &key, Some(&path), &json!(2), None)
+            .expect("legacy path set");
+        assert_eq!(
+            json_store.get_json(&ctx, &prefix, &key, None).expect("get"),
+            Some(json!({"legacy": [2]}))
+        );
+
+        json_store.del_prefix(&ctx, &prefix).expect("cleanup");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn json_mode_preserves_ttl_on_path_update() {
+        let Some(store) = json_store() else {
+            return;
+        };
+        let ctx = ctx();
+        let prefix = format!("flow/redis-json-ttl-{}", Uuid::new_v4());
+        let key = StateKey::new("node/a");
+
+        store
+            .set_json(&ctx, &prefix, &key, None, &json!({"ttl": true}), Some(1))
+            .expect("set");
+        sleep(Duration::from_millis(600)).await;
+        store
+            .set_json(
+                &ctx,
+                &prefix,
+                &key,
+                Some(&StatePath::from_pointer("/ttl")),
+                &json!("still"),
+                None,
+            )
+            .expect("path update");
+        sleep(Duration::from_millis(500)).await;
+
+        let value = store.get_json(&ctx, &prefix, &key, None).expect("get");
+        assert!(value.is_none(), "expected TTL to be preserved");
+    }
+}
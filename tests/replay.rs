@@ -0,0 +1,149 @@
+use greentic_state::replay::{RecordingStateStore, ReplayStateStore, StateCall};
+use greentic_state::{StateKey, StatePath, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use serde_json::{Value, json};
+use std::env;
+use uuid::Uuid;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+/// A small flow whose reads feed its later writes.
+fn run_flow(store: &dyn StateStore, input: i64) -> Vec<Value> {
+    let ctx = ctx();
+    let prefix = "flow/replay";
+    let key = StateKey::new("node/a");
+    let mut seen = Vec::new();
+
+    let current = store
+        .get_json(&ctx, prefix, &key, None)
+        .expect("get")
+        .unwrap_or(json!({"count": 0}));
+    seen.push(current.clone());
+    let count = current["count"].as_i64().unwrap_or(0) + input;
+    store
+        .set_json(&ctx, prefix, &key, None, &json!({"count": count}), Some(60))
+        .expect("set");
+    store
+        .set_json(
+            &ctx,
+            prefix,
+            &key,
+            Some(&StatePath::from_pointer("/last")),
+            &json!(input),
+            None,
+        )
+        .expect("path set");
+    seen.push(
+        store
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .expect("value"),
+    );
+    seen.push(json!(
+        store.list_keys(&ctx, Some(prefix)).expect("list").len()
+    ));
+    seen.push(json!(
+        store
+            .del(&ctx, prefix, &StateKey::new("missing"))
+            .expect("del")
+    ));
+    seen
+}
+
+fn record(input: i64) -> (std::path::PathBuf, Vec<Value>) {
+    let path = env::temp_dir().join(format!("greentic-replay-{}.jsonl", Uuid::new_v4()));
+    let live = InMemoryStateStore::new();
+    live.set_json(
+        &ctx(),
+        "flow/replay",
+        &StateKey::new("node/a"),
+        None,
+        &json!({"count": 40}),
+        None,
+    )
+    .expect("seed production state");
+    let recorder = RecordingStateStore::create(live, &path).expect("create");
+    let seen = run_flow(&recorder, input);
+    let err = recorder
+        .set_json(
+            &ctx(),
+            "flow/replay",
+            &StateKey::new("node/a"),
+            Some(&StatePath::from_pointer("/count/x")),
+            &json!(1),
+            None,
+        )
+        .expect_err("path through a number");
+    assert_eq!(err.code, ErrorCode::InvalidInput);
+    (path, seen)
+}
+
+fn failing_write(store: &dyn StateStore) -> ErrorCode {
+    store
+        .set_json(
+            &ctx(),
+            "flow/replay",
+            &StateKey::new("node/a"),
+            Some(&StatePath::from_pointer("/count/x")),
+            &json!(1),
+            None,
+        )
+        .expect_err("recorded failure")
+        .code
+}
+
+#[test]
+fn replay_reproduces_a_recorded_run() {
+    let (path, recorded) = record(2);
+    assert_eq!(recorded[1], json!({"count": 42, "last": 2}));
+
+    let replay = ReplayStateStore::open(&path).expect("open");
+    assert_eq!(run_flow(&replay, 2), recorded);
+    assert_eq!(failing_write(&replay), ErrorCode::InvalidInput);
+    assert_eq!(replay.remaining(), 0);
+    assert!(replay.divergences().is_empty());
+    assert_eq!(
+        replay
+            .mirror()
+            .get_json(&ctx(), "flow/replay", &StateKey::new("node/a"), None)
+            .expect("mirror"),
+        Some(json!({"count": 42, "last": 2}))
+    );
+
+    std::fs::remove_file(&path).expect("cleanup");
+}
+
+#[test]
+fn replay_flags_divergence() {
+    let (path, _) = record(2);
+
+    let replay = ReplayStateStore::open(&path).expect("open");
+    let seen = run_flow(&replay, 5);
+    let divergences = replay.divergences();
+    assert_eq!(divergences[0].seq, 1);
+    assert!(matches!(
+        (&divergences[0].expected, &divergences[0].actual),
+        (Some(StateCall::Set { value: expected, .. }), StateCall::Set { value: actual, .. })
+            if expected == &json!({"count": 42}) && actual == &json!({"count": 45})
+    ));
+    // The mirror takes over after the divergence.
+    assert_eq!(seen[1], json!({"count": 45, "last": 5}));
+
+    let strict = ReplayStateStore::open(&path).expect("open").strict(true);
+    let ctx = ctx();
+    let key = StateKey::new("node/a");
+    strict
+        .get_json(&ctx, "flow/replay", &key, None)
+        .expect("matches the recording");
+    let err = strict
+        .set_json(&ctx, "flow/replay", &key, None, &json!({}), None)
+        .expect_err("diverged");
+    assert_eq!(err.code, ErrorCode::Conflict);
+
+    std::fs::remove_file(&path).expect("cleanup");
+}
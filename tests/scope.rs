@@ -0,0 +1,91 @@
+use greentic_state::scope::{ScopedState, StatePrefix};
+use greentic_state::{StateKey, StatePath, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_types::{EnvId, ErrorCode, TenantId};
+use serde_json::json;
+use std::sync::Arc;
+
+fn ctx() -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from("tenant").expect("valid tenant id"),
+    )
+}
+
+#[test]
+fn prefixes_follow_the_runner_convention() {
+    let pack = StatePrefix::pack("pack-42").expect("pack");
+    let run = pack
+        .flow("ingest")
+        .and_then(|flow| flow.run("run-2024-09-13"))
+        .expect("run");
+    assert_eq!(run.as_str(), "pack/pack-42/flow/ingest/run/run-2024-09-13");
+    assert_eq!(
+        pack.session("sess-7f3b2").expect("session").as_str(),
+        "pack/pack-42/session/sess-7f3b2"
+    );
+
+    for bad in ["", "a/b", "a:b", "a b", "tab\t", &"x".repeat(129)] {
+        let err = pack.flow(bad).expect_err(bad);
+        assert_eq!(err.code, ErrorCode::InvalidInput);
+    }
+    assert!(pack.child("step/x", "1").is_err());
+}
+
+#[test]
+fn scoped_handles_read_and_write_under_their_prefix() {
+    let store = Arc::new(InMemoryStateStore::new());
+    let ctx = ctx();
+    let flow = ScopedState::new(
+        store.clone(),
+        ctx.clone(),
+        StatePrefix::pack("pack-42").expect("pack"),
+    )
+    .flow("ingest")
+    .expect("flow");
+    let run = flow.run("r1").expect("run");
+
+    let fetch = run.node("fetch").expect("node");
+    fetch
+        .set(None, &json!({"payload": {"count": 42}}), None)
+        .expect("set");
+    run.node("transform")
+        .expect("node")
+        .set(
+            Some(&StatePath::from_pointer("/status")),
+            &json!("ready"),
+            None,
+        )
+        .expect("path set");
+    flow.set(&StateKey::new("meta"), None, &json!(1), None)
+        .expect("flow set");
+
+    assert_eq!(fetch.key().as_str(), "node/fetch");
+    assert_eq!(
+        store
+            .get_json(
+                &ctx,
+                "pack/pack-42/flow/ingest/run/r1",
+                &StateKey::new("node/fetch"),
+                Some(&StatePath::from_pointer("/payload/count")),
+            )
+            .expect("raw get"),
+        Some(json!(42))
+    );
+    assert!(run.node("a/b").is_err());
+
+    let keys: Vec<_> = run
+        .keys()
+        .expect("keys")
+        .into_iter()
+        .map(|scoped| scoped.key.as_str().to_owned())
+        .collect();
+    assert_eq!(keys, ["node/fetch", "node/transform"]);
+
+    assert_eq!(run.clear().expect("clear"), 2);
+    assert_eq!(fetch.get(None).expect("get"), None);
+    assert_eq!(
+        flow.get(&StateKey::new("meta"), None).expect("get"),
+        Some(json!(1)),
+        "parent scope is a separate prefix"
+    );
+}
@@ -1,6 +1,7 @@
 use greentic_state::{StateKey, StateStore, TenantCtx, inmemory::InMemoryStateStore};
 use greentic_types::{EnvId, TenantId};
 use serde_json::json;
+#[cfg(feature = "redis")]
 use uuid::Uuid;
 
 fn ctx(tenant: &str) -> TenantCtx {
@@ -89,3 +90,56 @@ fn redis_prefix_delete_is_tenant_scoped() {
     let still_there = store.get_json(&ctx_b, &prefix, &key, None).expect("get");
     assert!(still_there.is_some(), "expected tenant-b entry to remain");
 }
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_pool_serves_concurrent_callers() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    let url = match env::var("REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let client = match redis::Client::open(url.as_str()) {
+        Ok(client) => client,
+        Err(_) => return,
+    };
+    let store = RedisStateStore::builder(client)
+        .pool_size(4)
+        .command_timeout(Some(Duration::from_secs(2)))
+        .build()
+        .expect("build pooled store");
+    let store = Arc::new(store);
+
+    let ctx = ctx("tenant-pool");
+    let prefix = format!("flow/pool-{}", Uuid::new_v4());
+    let handles: Vec<_> = (0..8)
+        .map(|idx| {
+            let store = Arc::clone(&store);
+            let ctx = ctx.clone();
+            let prefix = prefix.clone();
+            thread::spawn(move || {
+                let key = StateKey::new(format!("node/{idx}"));
+                store
+                    .set_json(&ctx, &prefix, &key, None, &json!({"idx": idx}), Some(60))
+                    .expect("set");
+                store
+                    .get_json(&ctx, &prefix, &key, None)
+                    .expect("get")
+                    .expect("value")
+            })
+        })
+        .collect();
+
+    for (idx, handle) in handles.into_iter().enumerate() {
+        let value = handle.join().expect("thread");
+        assert_eq!(value, json!({"idx": idx}));
+    }
+
+    let removed = store.del_prefix(&ctx, &prefix).expect("delete prefix");
+    assert_eq!(removed, 8);
+}
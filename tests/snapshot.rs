@@ -0,0 +1,336 @@
+use greentic_state::snapshot::{self, ConflictPolicy, ImportReport};
+use greentic_state::{
+    ScopedKey, StateKey, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore,
+};
+use greentic_types::{EnvId, ErrorCode, TeamId, TenantId};
+use serde_json::json;
+
+fn ctx(tenant: &str) -> TenantCtx {
+    TenantCtx::new(
+        EnvId::try_from("dev").expect("valid env id"),
+        TenantId::try_from(tenant).expect("valid tenant id"),
+    )
+}
+
+fn seeded_store() -> InMemoryStateStore {
+    let store = InMemoryStateStore::new();
+    let tenant = ctx("tenant-a");
+    let team = tenant
+        .clone()
+        .with_team(Some(TeamId::try_from("team-1").expect("valid team id")));
+
+    store
+        .set_json(
+            &tenant,
+            "flow/a",
+            &StateKey::new("node/1"),
+            None,
+            &json!({"n": 1}),
+            Some(120),
+        )
+        .expect("set a1");
+    store
+        .set_json(
+            &tenant,
+            "flow/a",
+            &StateKey::new("node/2"),
+            None,
+            &json!([1, 2]),
+            None,
+        )
+        .expect("set a2");
+    store
+        .set_json(
+            &tenant,
+            "flow/b",
+            &StateKey::new("node/1"),
+            None,
+            &json!("b"),
+            None,
+        )
+        .expect("set b1");
+    store
+        .set_json(
+            &team,
+            "flow/a",
+            &StateKey::new("node/team"),
+            None,
+            &json!(true),
+            None,
+        )
+        .expect("set team");
+    store
+        .set_json(
+            &ctx("tenant-b"),
+            "flow/a",
+            &StateKey::new("node/1"),
+            None,
+            &json!(0),
+            None,
+        )
+        .expect("set other tenant");
+    store
+}
+
+#[test]
+fn list_keys_is_scope_exact() {
+    let store = seeded_store();
+    let tenant = ctx("tenant-a");
+
+    let all = store.list_keys(&tenant, None).expect("list");
+    let names: Vec<(String, String)> = all
+        .iter()
+        .map(|ScopedKey { prefix, key }| (prefix.clone(), key.as_str().to_owned()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("flow/a".to_owned(), "node/1".to_owned()),
+            ("flow/a".to_owned(), "node/2".to_owned()),
+            ("flow/b".to_owned(), "node/1".to_owned()),
+        ]
+    );
+
+    let narrowed = store
+        .list_keys(&tenant, Some("flow/b"))
+        .expect("list prefix");
+    assert_eq!(narrowed.len(), 1);
+
+    assert_eq!(
+        store
+            .ttl(&tenant, "flow/a", &StateKey::new("node/2"))
+            .expect("ttl"),
+        Some(StateTtl::Persistent)
+    );
+    assert!(matches!(
+        store.ttl(&tenant, "flow/a", &StateKey::new("node/1")).expect("ttl"),
+        Some(StateTtl::ExpiresIn(remaining)) if remaining.as_secs() <= 120
+    ));
+    assert_eq!(
+        store
+            .ttl(&tenant, "flow/a", &StateKey::new("missing"))
+            .expect("ttl"),
+        None
+    );
+}
+
+#[test]
+fn export_roundtrips_through_ndjson() {
+    let source = seeded_store();
+    let tenant = ctx("tenant-a");
+
+    let mut buffer = Vec::new();
+    let written = snapshot::write_ndjson(
+        &mut buffer,
+        snapshot::export(&source, &tenant, None).expect("export"),
+    )
+    .expect("write ndjson");
+    assert_eq!(written, 3);
+
+    let records: Vec<_> = snapshot::read_ndjson(buffer.as_slice())
+        .collect::<Result<_, _>>()
+        .expect("read ndjson");
+    let first = &records[0];
+    assert_eq!(
+        (first.env.as_str(), first.tenant.as_str()),
+        ("dev", "tenant-a")
+    );
+    assert_eq!(
+        (first.prefix.as_str(), first.key.as_str()),
+        ("flow/a", "node/1")
+    );
+    assert!(matches!(first.ttl_secs, Some(ttl) if (1..=120).contains(&ttl)));
+    assert_eq!(records[1].ttl_secs, None);
+
+    let target = InMemoryStateStore::new();
+    let report = snapshot::import(&target, records.into_iter().map(Ok), ConflictPolicy::Fail)
+        .expect("import");
+    assert_eq!(
+        report,
+        ImportReport {
+            written: 3,
+            skipped: 0
+        }
+    );
+    assert_eq!(
+        target
+            .get_json(&tenant, "flow/a", &StateKey::new("node/2"), None)
+            .expect("get"),
+        Some(json!([1, 2]))
+    );
+    assert!(matches!(
+        target
+            .ttl(&tenant, "flow/a", &StateKey::new("node/1"))
+            .expect("ttl"),
+        Some(StateTtl::ExpiresIn(_))
+    ));
+}
+
+#[test]
+fn import_applies_conflict_policy() {
+    let source = seeded_store();
+    let tenant = ctx("tenant-a");
+    let records: Vec<_> = snapshot::export(&source, &tenant, Some("flow/a"))
+        .expect("export")
+        .collect::<Result<_, _>>()
+        .expect("records");
+    assert_eq!(records.len(), 2);
+
+    let target = InMemoryStateStore::new();
+    target
+        .set_json(
+            &tenant,
+            "flow/a",
+            &StateKey::new("node/1"),
+            None,
+            &json!("keep"),
+            None,
+        )
+        .expect("existing");
+
+    let report = snapshot::import(
+        &target,
+        records.clone().into_iter().map(Ok),
+        ConflictPolicy::Skip,
+    )
+    .expect("skip import");
+    assert_eq!(
+        report,
+        ImportReport {
+            written: 1,
+            skipped: 1
+        }
+    );
+    assert_eq!(
+        target
+            .get_json(&tenant, "flow/a", &StateKey::new("node/1"), None)
+            .expect("get"),
+        Some(json!("keep"))
+    );
+
+    let err = snapshot::import(
+        &target,
+        records.clone().into_iter().map(Ok),
+        ConflictPolicy::Fail,
+    )
+    .expect_err("conflict");
+    assert_eq!(err.code, ErrorCode::Conflict);
+
+    let report = snapshot::import(
+        &target,
+        records.into_iter().map(Ok),
+        ConflictPolicy::Overwrite,
+    )
+    .expect("overwrite import");
+    assert_eq!(report.written, 2);
+    assert_eq!(
+        target
+            .get_json(&tenant, "flow/a", &StateKey::new("node/1"), None)
+            .expect("get"),
+        Some(json!({"n": 1}))
+    );
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_export_lists_prefix_when_available() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+    use uuid::Uuid;
+
+    let url = match env::var("REDIS_URL") {
+        Ok(url) => url,
+        Err(_) => return,
+    };
+    let store = match RedisStateStore::from_url(&url) {
+        Ok(store) => store,
+        Err(_) => return,
+    };
+
+    let tenant = ctx("tenant-snapshot");
+    let prefix = format!("flow/snapshot-{}", Uuid::new_v4());
+    store
+        .set_json(
+            &tenant,
+            &prefix,
+            &StateKey::new("node/a"),
+            None,
+            &json!(1),
+            Some(60),
+        )
+        .expect("set a");
+    store
+        .set_json(
+            &tenant,
+            &prefix,
+            &StateKey::new("node/b"),
+            None,
+            &json!(2),
+            None,
+        )
+        .expect("set b");
+
+    let records: Vec<_> = snapshot::export(&store, &tenant, Some(&prefix))
+        .expect("export")
+        .collect::<Result<_, _>>()
+        .expect("records");
+    assert_eq!(records.len(), 2);
+    assert!(records[0].ttl_secs.is_some());
+    assert_eq!(records[1].ttl_secs, None);
+
+    store.del_prefix(&tenant, &prefix).expect("cleanup");
+}
+
+#[cfg(feature = "redis")]
+#[test]
+fn redis_full_listing_rejects_ambiguous_keys_when_available() {
+    use greentic_state::redis_store::RedisStateStore;
+    use std::env;
+    use uuid::Uuid;
+
+    let Ok(url) = env::var("REDIS_URL") else {
+        return;
+    };
+    let Ok(store) = RedisStateStore::from_url(&url) else {
+        return;
+    };
+    if store.list_keys(&ctx("probe"), Some("probe")).is_err() {
+        return;
+    }
+
+    let tenant = ctx(&format!("tenant-{}", Uuid::new_v4().simple()));
+    let team = tenant
+        .clone()
+        .with_team(Some(TeamId::try_from("team-1").expect("valid team id")));
+    for (scope, key) in [(&tenant, "node/1"), (&team, "node/team")] {
+        store
+            .set_json(scope, "flow/a", &StateKey::new(key), None, &json!(1), None)
+            .expect("seed");
+    }
+    let all = store.list_keys(&tenant, None).expect("list");
+    assert_eq!(all.len(), 1, "team entries are left out, not reported");
+
+    store
+        .set_json(
+            &tenant,
+            "flow/a",
+            &StateKey::new("a:b"),
+            None,
+            &json!(2),
+            None,
+        )
+        .expect("set");
+    let err = store
+        .list_keys(&tenant, None)
+        .expect_err("ambiguous keys are not dropped");
+    assert_eq!(err.code, ErrorCode::Internal);
+    assert_eq!(
+        store
+            .list_keys(&tenant, Some("flow/a"))
+            .expect("list prefix")
+            .len(),
+        2
+    );
+
+    store.purge_tenant(&tenant).expect("cleanup");
+}
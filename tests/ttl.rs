@@ -1,8 +1,13 @@
-use greentic_state::{StateKey, StateStore, TenantCtx, inmemory::InMemoryStateStore};
+use greentic_state::cache::{CacheConfig, CachedStateStore};
+use greentic_state::clock::TestClock;
+use greentic_state::{StateKey, StateStore, StateTtl, TenantCtx, inmemory::InMemoryStateStore};
 use greentic_types::{EnvId, TenantId};
 use serde_json::json;
+use std::sync::Arc;
 use std::time::Duration;
+#[cfg(feature = "redis")]
 use tokio::time::sleep;
+#[cfg(feature = "redis")]
 use uuid::Uuid;
 
 fn ctx() -> TenantCtx {
@@ -12,9 +17,15 @@ fn ctx() -> TenantCtx {
     )
 }
 
-#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-async fn in_memory_ttl_expires() {
-    let store = InMemoryStateStore::new();
+fn clocked_store() -> (InMemoryStateStore, TestClock) {
+    let clock = TestClock::new();
+    let store = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    (store, clock)
+}
+
+#[test]
+fn in_memory_ttl_expires() {
+    let (store, clock) = clocked_store();
     let ctx = ctx();
     let prefix = "flow/ttl-in-memory";
     let key = StateKey::new("node/a");
@@ -23,15 +34,23 @@ async fn in_memory_ttl_expires() {
         .set_json(&ctx, prefix, &key, None, &json!({"ttl": true}), Some(1))
         .expect("set");
 
-    sleep(Duration::from_millis(1_100)).await;
+    clock.advance(Duration::from_millis(999));
+    assert!(
+        store
+            .get_json(&ctx, prefix, &key, None)
+            .expect("get")
+            .is_some()
+    );
 
+    clock.advance(Duration::from_millis(1));
     let value = store.get_json(&ctx, prefix, &key, None).expect("get");
     assert!(value.is_none(), "expected value to expire");
+    assert_eq!(store.ttl(&ctx, prefix, &key).expect("ttl"), None);
 }
 
-#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
-async fn in_memory_ttl_preserved_on_none_update() {
-    let store = InMemoryStateStore::new();
+#[test]
+fn in_memory_ttl_preserved_on_none_update() {
+    let (store, clock) = clocked_store();
     let ctx = ctx();
     let prefix = "flow/ttl-preserve";
     let key = StateKey::new("node/a");
@@ -40,18 +59,78 @@ async fn in_memory_ttl_preserved_on_none_update() {
         .set_json(&ctx, prefix, &key, None, &json!({"ttl": true}), Some(1))
         .expect("set");
 
-    sleep(Duration::from_millis(600)).await;
+    clock.advance(Duration::from_millis(600));
 
     store
         .set_json(&ctx, prefix, &key, None, &json!({"ttl": "still"}), None)
         .expect("update");
+    assert_eq!(
+        store.ttl(&ctx, prefix, &key).expect("ttl"),
+        Some(StateTtl::ExpiresIn(Duration::from_millis(400)))
+    );
 
-    sleep(Duration::from_millis(500)).await;
+    clock.advance(Duration::from_millis(500));
 
     let value = store.get_json(&ctx, prefix, &key, None).expect("get");
     assert!(value.is_none(), "expected TTL to be preserved");
 }
 
+#[test]
+fn in_memory_ttl_cleared_by_zero() {
+    let (store, clock) = clocked_store();
+    let ctx = ctx();
+    let prefix = "flow/ttl-clear";
+    let key = StateKey::new("node/a");
+
+    store
+        .set_json(&ctx, prefix, &key, None, &json!({"ttl": true}), Some(1))
+        .expect("set");
+    store
+        .set_json(&ctx, prefix, &key, None, &json!({"ttl": false}), Some(0))
+        .expect("clear");
+
+    clock.advance(Duration::from_secs(86_400));
+
+    assert_eq!(
+        store.ttl(&ctx, prefix, &key).expect("ttl"),
+        Some(StateTtl::Persistent)
+    );
+    assert_eq!(
+        store.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"ttl": false}))
+    );
+}
+
+#[test]
+fn cached_copies_expire_on_the_injected_clock() {
+    let clock = TestClock::new();
+    let remote = InMemoryStateStore::new().with_clock(Arc::new(clock.clone()));
+    let cache = CachedStateStore::new(remote.clone(), CacheConfig { entry_ttl_secs: 5 })
+        .with_clock(Arc::new(clock.clone()));
+    let ctx = ctx();
+    let prefix = "flow/ttl-cache";
+    let key = StateKey::new("node/a");
+
+    cache
+        .set_json(&ctx, prefix, &key, None, &json!({"v": 1}), Some(60))
+        .expect("set");
+    // Bypass the cache so only the entry TTL can make it reload.
+    remote
+        .set_json(&ctx, prefix, &key, None, &json!({"v": 2}), None)
+        .expect("remote set");
+
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"v": 1}))
+    );
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(
+        cache.get_json(&ctx, prefix, &key, None).expect("get"),
+        Some(json!({"v": 2}))
+    );
+}
+
 #[cfg(feature = "redis")]
 #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
 async fn redis_ttl_expires_when_configured() {